@@ -1,14 +1,20 @@
-use clap::App;
+use clap::{App, Arg};
 use fs_err as fs;
+use itertools::Itertools;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use crate::config::Config;
-use crate::core::codegen::{codegen, CodegenOptions};
-use crate::core::io::{to_vice_symbols, SegmentMerger};
+use crate::core::codegen::{codegen, BasicStub, CodegenOptions, Rounding};
+use crate::core::io::{
+    bank_target, to_crt, to_d64, to_ines, to_output_bytes, to_vice_symbols, CartridgeType,
+    DiskFile, MemoryMap, NesOptions, SegmentMerger,
+};
 use crate::core::parser;
+use crate::core::target::{OutputFormat, Target};
 use crate::errors::{MosError, MosResult};
 
 #[derive(Debug, Clone, Deserialize, PartialEq)]
@@ -17,6 +23,18 @@ pub struct BuildOptions {
     pub entry: String,
     pub target_directory: String,
     pub symbols: Vec<SymbolType>,
+    pub rounding: Rounding,
+    pub memory_map: bool,
+    pub target: Option<Target>,
+    pub basic_stub: bool,
+    pub basic_stub_entry: Option<String>,
+    pub cartridge: Option<CartridgeType>,
+    pub cartridge_name: Option<String>,
+    pub disk_image: Option<String>,
+    pub disk_name: Option<String>,
+    pub disk_id: Option<String>,
+    pub disk_files: HashMap<String, String>,
+    pub nes: NesOptions,
 }
 
 impl Default for BuildOptions {
@@ -25,10 +43,55 @@ impl Default for BuildOptions {
             entry: "main.asm".into(),
             target_directory: "target".into(),
             symbols: vec![],
+            rounding: Rounding::default(),
+            memory_map: false,
+            target: None,
+            basic_stub: false,
+            basic_stub_entry: None,
+            cartridge: None,
+            cartridge_name: None,
+            disk_image: None,
+            disk_name: None,
+            disk_id: None,
+            disk_files: HashMap::new(),
+            nes: NesOptions::default(),
         }
     }
 }
 
+impl BuildOptions {
+    /// The options used for code generation, which are shared by the build command and the language server
+    pub fn codegen_options(&self) -> MosResult<CodegenOptions> {
+        let basic_stub = match self.basic_stub {
+            true => {
+                // Without a target, assume the C64
+                let target = self.target.unwrap_or(Target::C64);
+                let start = target.basic_start().ok_or_else(|| {
+                    MosError::BuildError(format!(
+                        "a BASIC stub is not supported for target: {:?}",
+                        target
+                    ))
+                })?;
+                Some(BasicStub {
+                    start,
+                    entry: self.basic_stub_entry.as_deref().map(|e| e.into()),
+                })
+            }
+            false => None,
+        };
+
+        Ok(CodegenOptions {
+            pc: match self.target {
+                Some(target) => target.default_pc(),
+                None => 0x2000.into(),
+            },
+            rounding: self.rounding,
+            basic_stub,
+            target: self.target,
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 pub enum SymbolType {
@@ -47,7 +110,11 @@ impl FromStr for SymbolType {
 }
 
 pub fn build_app() -> App<'static> {
-    App::new("build").about("Assembles input file(s)")
+    App::new("build").about("Assembles input file(s)").arg(
+        Arg::new("memory-map")
+            .long("memory-map")
+            .about("Reports where all segments are located in memory"),
+    )
 }
 
 pub fn build_command(root: &Path, cfg: &Config) -> MosResult<()> {
@@ -55,11 +122,19 @@ pub fn build_command(root: &Path, cfg: &Config) -> MosResult<()> {
     let target_dir = root.join(&cfg.build.target_directory);
     fs::create_dir_all(&target_dir)?;
 
+    let output_format = match cfg.build.target {
+        Some(target) => target.output_format(),
+        None => OutputFormat::Prg,
+    };
+    let codegen_options = cfg.build.codegen_options()?;
+
+    let mut disk_files = vec![];
     for input_name in input_names {
         let input_path = PathBuf::from(".").join(PathBuf::from(&input_name));
         let output_path = target_dir.join(format!(
-            "{}.prg",
-            input_path.file_stem().unwrap().to_string_lossy()
+            "{}.{}",
+            input_path.file_stem().unwrap().to_string_lossy(),
+            output_format.extension()
         ));
 
         let mut file = fs::File::open(&input_path)?;
@@ -70,7 +145,7 @@ pub fn build_command(root: &Path, cfg: &Config) -> MosResult<()> {
         if let Some(e) = error {
             return Err(e);
         }
-        let generated_code = codegen(tree, CodegenOptions { pc: 0x2000.into() })?;
+        let generated_code = codegen(tree, codegen_options.clone())?;
 
         let mut merger = SegmentMerger::new(output_path);
         for segment_name in generated_code.segments().keys() {
@@ -89,23 +164,87 @@ pub fn build_command(root: &Path, cfg: &Config) -> MosResult<()> {
                 log::trace!("Writing: (${:04x} - ${:04x})", range.start, range.end);
                 log::trace!("Writing: {:?}", m.range_data());
                 let mut out = fs::File::create(target_dir.join(path))?;
-                out.write_all(&(range.start as u16).to_le_bytes())?;
-                out.write_all(&m.range_data())?;
+                out.write_all(&to_output_bytes(output_format, m))?;
             }
         }
 
+        if cfg.build.disk_image.is_some() {
+            let targets = merger
+                .targets()
+                .iter()
+                .filter(|(_, m)| m.range().is_some())
+                .sorted_by_key(|(path, m)| (m.bank(), path.to_path_buf()));
+            for (path, m) in targets {
+                let stem = path.file_stem().unwrap().to_string_lossy().to_string();
+                disk_files.push(DiskFile {
+                    name: cfg.build.disk_files.get(&stem).cloned().unwrap_or(stem),
+                    data: to_output_bytes(OutputFormat::Prg, m),
+                });
+            }
+        }
+
+        if cfg.build.target == Some(Target::Nes) {
+            let stem = input_path.file_stem().unwrap().to_string_lossy();
+            let nes = to_ines(&cfg.build.nes, &merger)?;
+            fs::write(target_dir.join(format!("{}.nes", stem)), nes)?;
+        }
+
+        if let Some(cartridge) = cfg.build.cartridge {
+            let stem = input_path.file_stem().unwrap().to_string_lossy();
+            let name = match &cfg.build.cartridge_name {
+                Some(name) => name.clone(),
+                None => stem.to_uppercase(),
+            };
+            let crt = to_crt(cartridge, &name, &merger)?;
+            fs::write(target_dir.join(format!("{}.crt", stem)), crt)?;
+        }
+
+        if cfg.build.memory_map {
+            let stem = input_path.file_stem().unwrap().to_string_lossy();
+            let memory_map = MemoryMap::new(generated_code.segments(), &merger);
+            let text = memory_map.to_text();
+            println!("{}", text);
+            fs::write(target_dir.join(format!("{}.map", stem)), text)?;
+            fs::write(
+                target_dir.join(format!("{}.map.json", stem)),
+                memory_map.to_json(),
+            )?;
+        }
+
         for symbol_type in &cfg.build.symbols {
             match symbol_type {
                 SymbolType::Vice => {
-                    let symbol_path =
-                        format!("{}.vs", input_path.file_stem().unwrap().to_string_lossy());
-                    let mut out = fs::File::create(target_dir.join(symbol_path))?;
-                    out.write_all(to_vice_symbols(generated_code.symbol_table()).as_bytes())?;
+                    let symbol_path = PathBuf::from(format!(
+                        "{}.vs",
+                        input_path.file_stem().unwrap().to_string_lossy()
+                    ));
+                    for (bank, symbols) in to_vice_symbols(generated_code.symbol_table()) {
+                        let path = match bank {
+                            Some(bank) => bank_target(&symbol_path, bank),
+                            None => symbol_path.clone(),
+                        };
+                        let mut out = fs::File::create(target_dir.join(path))?;
+                        out.write_all(symbols.as_bytes())?;
+                    }
                 }
             }
         }
     }
 
+    if let Some(disk_image) = &cfg.build.disk_image {
+        let disk_name = match &cfg.build.disk_name {
+            Some(name) => name.clone(),
+            None => Path::new(disk_image)
+                .file_stem()
+                .unwrap()
+                .to_string_lossy()
+                .to_string(),
+        };
+        let disk_id = cfg.build.disk_id.as_deref().unwrap_or("00");
+        let d64 = to_d64(&disk_name, disk_id, &disk_files)?;
+        fs::write(target_dir.join(disk_image), d64)?;
+    }
+
     Ok(())
 }
 
@@ -115,9 +254,11 @@ mod tests {
 
     use anyhow::Result;
     use itertools::Itertools;
+    use tempfile::tempdir;
 
     use crate::commands::{build_command, BuildOptions, SymbolType};
     use crate::config::Config;
+    use crate::core::target::Target;
 
     #[test]
     fn can_invoke_build() -> Result<()> {
@@ -158,6 +299,105 @@ mod tests {
         build_and_compare("include.asm")
     }
 
+    #[test]
+    fn build_with_basic_stub() -> Result<()> {
+        let root = env!("CARGO_MANIFEST_DIR");
+        let entry = format!("{}/test/cli/build/basic_stub.asm", root);
+        let cfg = Config {
+            build: BuildOptions {
+                entry,
+                target_directory: format!("{}/target", root),
+                target: Some(Target::Vic20),
+                basic_stub: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        build_command(PathBuf::from(root).as_path(), &cfg)?;
+
+        let out_bytes = std::fs::read(format!("{}/target/basic_stub.prg", root))?;
+        assert_eq!(
+            out_bytes,
+            vec![
+                0x01, 0x10, 0x0c, 0x10, 0x0a, 0x00, 0x9e, 0x20, 0x34, 0x31, 0x31, 0x30, 0x00, 0x00,
+                0x00, 0xee, 0x20, 0xd0, 0x60
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn build_with_disk_image() -> Result<()> {
+        let root = env!("CARGO_MANIFEST_DIR");
+        let entry = format!("{}/test/cli/build/valid.asm", root);
+        // Use a separate target directory, since other tests also build 'valid.asm'
+        let target_dir = tempdir()?;
+        let cfg = Config {
+            build: BuildOptions {
+                entry,
+                target_directory: target_dir.path().to_string_lossy().to_string(),
+                disk_image: Some("valid.d64".into()),
+                disk_id: Some("ab".into()),
+                disk_files: vec![("valid".to_string(), "my game".to_string())]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        build_command(PathBuf::from(root).as_path(), &cfg)?;
+
+        let d64 = std::fs::read(target_dir.path().join("valid.d64"))?;
+        let prg = std::fs::read(format!("{}/test/cli/build/valid.prg", root))?;
+        let bam = &d64[357 * 256..358 * 256];
+        assert_eq!(&bam[0x90..0x96], b"VALID\xa0");
+        assert_eq!(&bam[0xa2..0xa4], b"AB");
+        let directory = &d64[358 * 256..359 * 256];
+        assert_eq!(&directory[5..13], b"MY GAME\xa0");
+        assert_eq!(&d64[2..2 + prg.len()], prg.as_slice());
+
+        target_dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn build_vice_symbols_per_bank() -> Result<()> {
+        let source_dir = tempdir()?;
+        let entry = source_dir.path().join("banks.asm");
+        std::fs::write(
+            &entry,
+            r"
+            .define segment { name = code start = $2000 }
+            .define segment { name = bank1 start = $8000 bank = 1 }
+            .segment code { main: nop }
+            .segment bank1 { init: rts }
+            ",
+        )?;
+        let cfg = Config {
+            build: BuildOptions {
+                entry: entry.to_string_lossy().to_string(),
+                target_directory: source_dir
+                    .path()
+                    .join("target")
+                    .to_string_lossy()
+                    .to_string(),
+                symbols: vec![SymbolType::Vice],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        build_command(source_dir.path(), &cfg)?;
+
+        let target_dir = source_dir.path().join("target");
+        let vs = std::fs::read_to_string(target_dir.join("banks.vs"))?;
+        assert_eq!(vs.lines().collect_vec(), vec!["al C:2000 .code.main"]);
+        let vs = std::fs::read_to_string(target_dir.join("banks-bank1.vs"))?;
+        assert_eq!(vs.lines().collect_vec(), vec!["al C:8000 .bank1.init"]);
+
+        source_dir.close()?;
+        Ok(())
+    }
+
     fn build_and_compare(input: &str) -> Result<()> {
         let root = env!("CARGO_MANIFEST_DIR");
         let entry = format!("{}/test/cli/build/{}", root, input);
@@ -0,0 +1,60 @@
+use crate::core::codegen::ProgramCounter;
+use crate::core::parser::IdentifierPath;
+
+/// The name of the segment that contains the BASIC stub
+pub const BASIC_STUB_SEGMENT: &str = "basic";
+
+/// The BASIC token for `SYS`
+const SYS_TOKEN: u8 = 0x9e;
+
+/// A tokenized BASIC program consisting of a single `10 SYS xxxxx` line, so that the assembled program can be started with `RUN`
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasicStub {
+    /// Where BASIC programs are loaded on the target machine
+    pub start: ProgramCounter,
+    /// The label to jump to. If not provided, the stub jumps to the code that directly follows it.
+    pub entry: Option<IdentifierPath>,
+}
+
+impl BasicStub {
+    /// The size of the stub in bytes. The address is always padded to five characters so the size does not depend on it.
+    pub const LEN: usize = 13;
+
+    /// The first address after the stub
+    pub fn end(&self) -> ProgramCounter {
+        self.start + ProgramCounter::new(Self::LEN)
+    }
+
+    pub fn to_bytes(&self, sys_address: u16) -> Vec<u8> {
+        // The pointer to the next line points to the two zero bytes that terminate the program
+        let next_line = self.start.as_i64() as u16 + Self::LEN as u16 - 2;
+        let line_number: u16 = 10;
+
+        let mut bytes = vec![];
+        bytes.extend(&next_line.to_le_bytes());
+        bytes.extend(&line_number.to_le_bytes());
+        bytes.push(SYS_TOKEN);
+        bytes.extend(format!("{:>5}", sys_address).bytes());
+        bytes.extend(&[0, 0, 0]);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::codegen::BasicStub;
+
+    #[test]
+    fn can_generate_stub() {
+        let stub = BasicStub {
+            start: 0x0801.into(),
+            entry: None,
+        };
+        assert_eq!(
+            stub.to_bytes(2062),
+            vec![0x0c, 0x08, 0x0a, 0x00, 0x9e, 0x20, 0x32, 0x30, 0x36, 0x32, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(stub.to_bytes(49152)[5..10], *b"49152");
+        assert_eq!(stub.end(), 0x080e.into());
+    }
+}
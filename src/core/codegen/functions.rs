@@ -0,0 +1,435 @@
+use crate::core::codegen::{
+    CodegenContext, CodegenError, CodegenResult, DetailedCodegenError, Value,
+};
+use crate::core::parser::IdentifierPath;
+use codemap::Span;
+use fs_err as fs;
+
+/// The kind of value a function expects for one of its parameters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParameterType {
+    /// Any expression that evaluates to a number
+    Number,
+    /// The name of a symbol, which is passed without being evaluated
+    Symbol,
+    /// A string literal
+    String,
+}
+
+/// The name of a parameter and the kind of value it expects
+pub type Parameter = (&'static str, ParameterType);
+
+/// The value of an argument, converted according to the type of its parameter
+#[derive(Debug)]
+pub enum ArgumentValue<'a> {
+    /// The evaluated number, or `None` if it could not be evaluated yet
+    Number(Option<Value>),
+    Symbol(&'a IdentifierPath),
+    String(&'a str),
+}
+
+#[derive(Debug)]
+pub struct FunctionArgument<'a> {
+    pub span: Span,
+    pub value: ArgumentValue<'a>,
+}
+
+/// Everything a function has access to when it is called
+pub struct FunctionCall<'a> {
+    pub ctx: &'a CodegenContext,
+    /// The span of the entire call, e.g. `min(1, 2)`
+    pub span: Span,
+    pub args: Vec<FunctionArgument<'a>>,
+    /// Whether failures should be reported as errors, or should be retried in a later pass
+    pub error_on_failure: bool,
+}
+
+impl<'a> FunctionCall<'a> {
+    /// The numeric argument at the provided index, if it could be evaluated
+    pub fn value(&self, index: usize) -> Option<Value> {
+        match &self.args[index].value {
+            ArgumentValue::Number(val) => *val,
+            other => panic!("Expected a number, but found: {:?}", other),
+        }
+    }
+
+    /// All numeric arguments, but only if all of them could be evaluated
+    pub fn values(&self) -> Option<Vec<Value>> {
+        (0..self.args.len())
+            .map(|index| self.value(index))
+            .collect()
+    }
+
+    /// The numeric argument at the provided index, rounded to an integer if needed
+    pub fn number(&self, index: usize) -> Option<i64> {
+        self.value(index)
+            .and_then(|val| val.to_integer(self.ctx.options.rounding))
+    }
+
+    pub fn symbol(&self, index: usize) -> &'a IdentifierPath {
+        match &self.args[index].value {
+            ArgumentValue::Symbol(path) => path,
+            other => panic!("Expected a symbol, but found: {:?}", other),
+        }
+    }
+
+    pub fn string(&self, index: usize) -> &'a str {
+        match &self.args[index].value {
+            ArgumentValue::String(s) => s,
+            other => panic!("Expected a string, but found: {:?}", other),
+        }
+    }
+
+    /// Reports an error at the argument with the provided index, or tries again later if errors should not yet be reported
+    fn fail(&self, index: usize, error: DetailedCodegenError) -> CodegenResult<Option<Value>> {
+        if self.error_on_failure {
+            Err(CodegenError::new(self.args[index].span, error))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+pub type FunctionCallback = &'static dyn Fn(&FunctionCall) -> CodegenResult<Option<Value>>;
+
+/// A function that may be called from within expressions
+pub struct RegisteredFunction {
+    pub name: &'static str,
+    pub parameters: &'static [Parameter],
+    pub documentation: &'static str,
+    pub callback: FunctionCallback,
+}
+
+impl RegisteredFunction {
+    /// The number of arguments the function expects
+    pub fn arity(&self) -> usize {
+        self.parameters.len()
+    }
+
+    /// A human-readable signature, e.g. `defined(symbol)`
+    pub fn signature(&self) -> String {
+        let parameters: Vec<&str> = self.parameters.iter().map(|(name, _)| *name).collect();
+        format!("{}({})", self.name, parameters.join(", "))
+    }
+}
+
+/// All functions that are available to every program
+pub fn builtin_functions() -> Vec<RegisteredFunction> {
+    use ParameterType::*;
+
+    vec![
+        RegisteredFunction {
+            name: "defined",
+            parameters: &[("symbol", Symbol)],
+            documentation: "Evaluates to `1` if the symbol is defined and to `0` otherwise.",
+            callback: &defined,
+        },
+        RegisteredFunction {
+            name: "lo",
+            parameters: &[("value", Number)],
+            documentation: "The low byte of a value.",
+            callback: &lo,
+        },
+        RegisteredFunction {
+            name: "hi",
+            parameters: &[("value", Number)],
+            documentation: "The high byte of a 16-bit value.",
+            callback: &hi,
+        },
+        RegisteredFunction {
+            name: "bank",
+            parameters: &[("address", Number)],
+            documentation: "The bank byte (bits 16-23) of an address.",
+            callback: &bank,
+        },
+        RegisteredFunction {
+            name: "min",
+            parameters: &[("a", Number), ("b", Number)],
+            documentation: "The smallest of two values.",
+            callback: &min,
+        },
+        RegisteredFunction {
+            name: "max",
+            parameters: &[("a", Number), ("b", Number)],
+            documentation: "The largest of two values.",
+            callback: &max,
+        },
+        RegisteredFunction {
+            name: "abs",
+            parameters: &[("value", Number)],
+            documentation: "The absolute value of a value.",
+            callback: &abs,
+        },
+        RegisteredFunction {
+            name: "clamp",
+            parameters: &[("value", Number), ("min", Number), ("max", Number)],
+            documentation: "Limits a value so that it lies between `min` and `max`.",
+            callback: &clamp,
+        },
+        RegisteredFunction {
+            name: "sin",
+            parameters: &[("angle", Number), ("period", Number), ("amplitude", Number)],
+            documentation: "The sine of `angle`, where a full circle is `period` steps, multiplied by `amplitude`.",
+            callback: &sin,
+        },
+        RegisteredFunction {
+            name: "cos",
+            parameters: &[("angle", Number), ("period", Number), ("amplitude", Number)],
+            documentation: "The cosine of `angle`, where a full circle is `period` steps, multiplied by `amplitude`.",
+            callback: &cos,
+        },
+        RegisteredFunction {
+            name: "sizeof",
+            parameters: &[("scope", Symbol)],
+            documentation: "The number of bytes in the block of a scope, e.g. the block following `label:`.",
+            callback: &sizeof,
+        },
+        RegisteredFunction {
+            name: "segment_start",
+            parameters: &[("segment", Symbol)],
+            documentation: "The start address of a segment.",
+            callback: &segment_start,
+        },
+        RegisteredFunction {
+            name: "segment_end",
+            parameters: &[("segment", Symbol)],
+            documentation: "The end address of a segment.",
+            callback: &segment_end,
+        },
+        RegisteredFunction {
+            name: "file_size",
+            parameters: &[("filename", String)],
+            documentation: "The size of a file in bytes, relative to the current source file.",
+            callback: &file_size,
+        },
+    ]
+}
+
+#[allow(clippy::unnecessary_wraps)]
+fn defined(call: &FunctionCall) -> CodegenResult<Option<Value>> {
+    let symbol = call.ctx.symbols.value(call.symbol(0))?;
+    Ok(Some(Value::Integer(symbol.is_some() as i64)))
+}
+
+#[allow(clippy::unnecessary_wraps)]
+fn lo(call: &FunctionCall) -> CodegenResult<Option<Value>> {
+    Ok(call.number(0).map(|val| Value::Integer(val & 0xff)))
+}
+
+#[allow(clippy::unnecessary_wraps)]
+fn hi(call: &FunctionCall) -> CodegenResult<Option<Value>> {
+    Ok(call.number(0).map(|val| Value::Integer((val >> 8) & 0xff)))
+}
+
+#[allow(clippy::unnecessary_wraps)]
+fn bank(call: &FunctionCall) -> CodegenResult<Option<Value>> {
+    Ok(call.number(0).map(|val| Value::Integer((val >> 16) & 0xff)))
+}
+
+#[allow(clippy::unnecessary_wraps)]
+fn min(call: &FunctionCall) -> CodegenResult<Option<Value>> {
+    Ok(call.values().and_then(|values| {
+        values
+            .into_iter()
+            .min_by(|a, b| a.as_f64().total_cmp(&b.as_f64()))
+    }))
+}
+
+#[allow(clippy::unnecessary_wraps)]
+fn max(call: &FunctionCall) -> CodegenResult<Option<Value>> {
+    Ok(call.values().and_then(|values| {
+        values
+            .into_iter()
+            .max_by(|a, b| a.as_f64().total_cmp(&b.as_f64()))
+    }))
+}
+
+fn abs(call: &FunctionCall) -> CodegenResult<Option<Value>> {
+    match call.value(0) {
+        Some(Value::Integer(val)) => match val.checked_abs() {
+            Some(val) => Ok(Some(Value::Integer(val))),
+            None => call.fail(0, DetailedCodegenError::ArithmeticOverflow()),
+        },
+        Some(Value::Decimal(val)) => Ok(Some(Value::Decimal(val.abs()))),
+        None => Ok(None),
+    }
+}
+
+fn clamp(call: &FunctionCall) -> CodegenResult<Option<Value>> {
+    match call.values().as_deref() {
+        Some(&[_, min, max]) if min.as_f64() > max.as_f64() => {
+            call.fail(1, DetailedCodegenError::InvalidRange(min, max))
+        }
+        Some(&[val, min, _]) if val.as_f64() < min.as_f64() => Ok(Some(min)),
+        Some(&[val, _, max]) if val.as_f64() > max.as_f64() => Ok(Some(max)),
+        Some(&[val, _, _]) => Ok(Some(val)),
+        _ => Ok(None),
+    }
+}
+
+fn trigonometry(call: &FunctionCall, f: fn(f64) -> f64) -> CodegenResult<Option<Value>> {
+    match call.values().as_deref() {
+        Some(&[_, period, _]) if period.is_zero() => Err(CodegenError::new(
+            call.args[1].span,
+            DetailedCodegenError::DivisionByZero(),
+        )),
+        Some(&[angle, period, amplitude]) => {
+            let radians = 2.0 * std::f64::consts::PI * angle.as_f64() / period.as_f64();
+            Ok(Some(Value::Decimal(f(radians) * amplitude.as_f64())))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn sin(call: &FunctionCall) -> CodegenResult<Option<Value>> {
+    trigonometry(call, f64::sin)
+}
+
+fn cos(call: &FunctionCall) -> CodegenResult<Option<Value>> {
+    trigonometry(call, f64::cos)
+}
+
+fn sizeof(call: &FunctionCall) -> CodegenResult<Option<Value>> {
+    // Every block registers its start and end addresses as `-` and `+`
+    let scope = call.symbol(0);
+    let start = call.ctx.symbols.value(&scope.join("-"))?;
+    let end = call.ctx.symbols.value(&scope.join("+"))?;
+    match (start, end) {
+        (Some(Value::Integer(start)), Some(Value::Integer(end))) => {
+            Ok(Some(Value::Integer(end - start)))
+        }
+        _ => call.fail(0, DetailedCodegenError::UnknownIdentifier(scope.clone())),
+    }
+}
+
+fn segment_address(call: &FunctionCall, key: &str) -> CodegenResult<Option<Value>> {
+    let segment = call.symbol(0);
+    let path = IdentifierPath::from("segments").join(segment).join(key);
+    match call.ctx.symbols.value(&path)? {
+        Some(val) => Ok(Some(val)),
+        None => call.fail(0, DetailedCodegenError::UnknownIdentifier(segment.clone())),
+    }
+}
+
+fn segment_start(call: &FunctionCall) -> CodegenResult<Option<Value>> {
+    segment_address(call, "start")
+}
+
+fn segment_end(call: &FunctionCall) -> CodegenResult<Option<Value>> {
+    segment_address(call, "end")
+}
+
+fn file_size(call: &FunctionCall) -> CodegenResult<Option<Value>> {
+    let filename = call.ctx.resolve_path(call.span, call.string(0));
+    match fs::metadata(&filename) {
+        Ok(metadata) => Ok(Some(Value::Integer(metadata.len() as i64))),
+        Err(_) => Err(CodegenError::new(
+            call.args[0].span,
+            DetailedCodegenError::FileNotFound(filename),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::codegen::{codegen, CodegenContext, CodegenOptions};
+    use crate::core::parser::parse_or_err;
+    use crate::errors::MosResult;
+    use std::path::Path;
+
+    #[test]
+    fn byte_functions() -> MosResult<()> {
+        let ctx = test_codegen(".byte lo($1234), hi($1234), bank($123456)")?;
+        assert_eq!(
+            ctx.segments().current().range_data(),
+            vec![0x34, 0x12, 0x12]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn numeric_functions() -> MosResult<()> {
+        let ctx =
+            test_codegen(".byte min(3, 5), max(3, 5), abs(-4), clamp(10, 0, 7), clamp(-10, 0, 7)")?;
+        assert_eq!(ctx.segments().current().range_data(), vec![3, 5, 4, 7, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn trigonometric_functions() -> MosResult<()> {
+        let ctx = test_codegen(
+            ".byte sin(0, 256, 127), sin(64, 256, 127), sin(192, 256, 127), cos(0, 256, 100), cos(128, 256, 100)",
+        )?;
+        assert_eq!(
+            ctx.segments().current().range_data(),
+            vec![0, 127, (-127_i8) as u8, 100, (-100_i8) as u8]
+        );
+
+        let err = test_codegen(".byte sin(1, 0, 1)").err().unwrap();
+        assert_eq!(err.to_string(), "test.asm:1:14: error: division by zero");
+        Ok(())
+    }
+
+    #[test]
+    fn symbol_functions() -> MosResult<()> {
+        let ctx = test_codegen(
+            ".define segment { name = a start = $1000 }\n.segment a { .byte sizeof(data), sizeof(a.data), lo(segment_start(a)), hi(segment_end(a))\ndata: { nop\nnop } }",
+        )?;
+        assert_eq!(
+            ctx.segments().get("a").range_data(),
+            vec![2, 2, 0x00, 0x10, 0xea, 0xea]
+        );
+
+        let err = test_codegen("lda #sizeof(foo)").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:13: error: unknown identifier: foo"
+        );
+        let err = test_codegen("lda #segment_start(foo)").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:20: error: unknown identifier: foo"
+        );
+        let err = test_codegen("lda #sizeof(1)").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:13: error: expected a symbol name"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn file_size() -> MosResult<()> {
+        let ctx = test_codegen_in(
+            Path::new("test/cli/build/include.asm"),
+            ".word file_size(\"include.bin\")",
+        )?;
+        let expected = std::fs::metadata("test/cli/build/include.bin")?.len() as u16;
+        assert_eq!(
+            ctx.segments().current().range_data(),
+            expected.to_le_bytes().to_vec()
+        );
+
+        let err = test_codegen(".word file_size(1)").err().unwrap();
+        assert_eq!(err.to_string(), "test.asm:1:17: error: expected a string");
+        Ok(())
+    }
+
+    #[test]
+    fn functions_require_correct_arity() {
+        let err = test_codegen("lda #min(1)").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:6: error: function 'min' expects 2 argument(s), but 1 were provided"
+        );
+    }
+
+    fn test_codegen(code: &str) -> MosResult<CodegenContext> {
+        test_codegen_in(Path::new("test.asm"), code)
+    }
+
+    fn test_codegen_in(path: &Path, code: &str) -> MosResult<CodegenContext> {
+        let ast = parse_or_err(path, code)?;
+        codegen(ast, CodegenOptions::default())
+    }
+}
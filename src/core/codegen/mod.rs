@@ -1,4 +1,6 @@
-use crate::core::codegen::segment::{require_segment_options_fields, SegmentOptions};
+use crate::core::codegen::region::require_region_options_fields;
+use crate::core::codegen::segment::{require_segment_options_fields, Padding, SegmentOptions};
+use crate::core::target::Target;
 use crate::errors::{MosError, MosResult};
 use crate::parser::*;
 use codemap::Span;
@@ -6,17 +8,29 @@ use fs_err as fs;
 use itertools::Itertools;
 use smallvec::{smallvec, SmallVec};
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ops::Range;
 use std::str::FromStr;
 use std::sync::Arc;
 
+pub use basic_stub::*;
+pub use functions::*;
 pub use program_counter::*;
+pub use region::*;
 pub use segment::*;
 use std::path::PathBuf;
 pub use symbol_table::*;
+pub use text::*;
+pub use value::*;
 
+mod basic_stub;
+mod functions;
 mod program_counter;
+mod region;
 mod segment;
 mod symbol_table;
+mod text;
+mod value;
 
 pub type CodegenResult<T> = Result<T, CodegenError>;
 
@@ -54,18 +68,71 @@ pub enum DetailedCodegenError {
     UnknownIdentifier(IdentifierPath),
     #[error("unknown function: {0}")]
     UnknownFunction(Identifier),
+    #[error("function '{0}' expects {1} argument(s), but {2} were provided")]
+    FunctionArity(Identifier, usize, usize),
     #[error("branch too far")]
     BranchTooFar(),
     #[error("cannot redefine symbol: {0}")]
     SymbolRedefinition(IdentifierPath),
     #[error("operand size mismatch")]
     OperandSizeMismatch(),
+    #[error("division by zero")]
+    DivisionByZero(),
+    #[error("arithmetic overflow")]
+    ArithmeticOverflow(),
+    #[error("value {0} is out of range: expected a value between {1} and {2}")]
+    ValueOutOfRange(i64, i64, i64),
+    #[error("expected a number, but found a string")]
+    UnexpectedString(),
+    #[error("expected a symbol name")]
+    ExpectedSymbol(),
+    #[error("expected a string")]
+    ExpectedString(),
+    #[error("unknown encoding: {0}")]
+    UnknownEncoding(Identifier),
+    #[error("character {0:?} cannot be represented in encoding '{1}'")]
+    UnencodableCharacter(char, Identifier),
+    #[error("minimum {0} is greater than maximum {1}")]
+    InvalidRange(Value, Value),
     #[error("invalid definition: {0}: {1}")]
     InvalidDefinition(Identifier, String),
     #[error("segment '{0}' is out of range: beyond ${1:04X}")]
     SegmentOutOfRange(Identifier, ProgramCounter),
+    #[error("segment '{0}' exceeds its end address of ${1:04X} by {2} byte(s)")]
+    SegmentOverflow(Identifier, ProgramCounter, usize),
+    #[error("segments depend on each other: {}", .0.iter().join(" -> "))]
+    SegmentCycle(Vec<Identifier>),
+    #[error("segment '{0}' exceeds region '{1}' by {2} byte(s)")]
+    SegmentExceedsRegion(Identifier, Identifier, usize),
+    #[error("segment '{0}' does not start within region '{1}' (${2:04X} - ${3:04X})")]
+    SegmentOutsideRegion(Identifier, Identifier, ProgramCounter, ProgramCounter),
+    #[error("segment name '{0}' is reserved for the BASIC stub")]
+    ReservedSegmentName(Identifier),
+    #[error("segment '{0}' places data in I/O space (${1:04X} - ${2:04X})")]
+    SegmentInIoSpace(Identifier, ProgramCounter, ProgramCounter),
+    #[error("region '{0}' is already defined")]
+    RegionRedefinition(Identifier),
+    #[error("region '{0}' has an end address (${2:04X}) that is not beyond its start address (${1:04X})")]
+    RegionEndBeforeStart(Identifier, ProgramCounter, ProgramCounter),
+    #[error("region '{0}' (${1:04X} - ${2:04X}) overlaps with region '{3}' (${4:04X} - ${5:04X})")]
+    RegionOverlap(
+        Identifier,
+        ProgramCounter,
+        ProgramCounter,
+        Identifier,
+        ProgramCounter,
+        ProgramCounter,
+    ),
+    #[error("unknown region kind '{0}', expected one of: ram, rom, io")]
+    UnknownRegionKind(String),
+    #[error("unknown definition type '{0}', expected one of: segment, region")]
+    UnknownDefinitionType(Identifier),
     #[error("file not found: {0}")]
     FileNotFound(PathBuf),
+    #[error("no segments defined, so a default segment was created")]
+    ImplicitDefaultSegment(),
+    #[error("segments are defined, so 'basic_stub_entry' is needed to know where the BASIC stub should jump to")]
+    BasicStubEntryRequired(),
 }
 
 impl CodegenError {
@@ -74,6 +141,74 @@ impl CodegenError {
     }
 }
 
+/// Determines if a value can be stored in the provided number of bytes, either as a signed or as an unsigned value
+fn fits_in_bytes(val: i64, len: usize) -> bool {
+    let bits = 8 * len as u32;
+    val >= -(1 << (bits - 1)) && val < (1 << bits)
+}
+
+/// The factor an expression consists of, but only if it is a factor without any modifiers
+fn factor_of(expr: &Expression) -> Option<&ExpressionFactor> {
+    match expr {
+        Expression::Factor { factor, flags, .. } if flags.is_empty() => Some(&factor.data),
+        _ => None,
+    }
+}
+
+/// Applies a binary operator to two integers, returning `None` if the result is undefined or does not fit
+fn integer_operation(op: &BinaryOp, lhs: i64, rhs: i64) -> Option<i64> {
+    let shift = || u32::try_from(rhs).ok();
+    match op {
+        BinaryOp::Add => lhs.checked_add(rhs),
+        BinaryOp::Sub => lhs.checked_sub(rhs),
+        BinaryOp::Mul => lhs.checked_mul(rhs),
+        BinaryOp::Div => lhs.checked_div(rhs),
+        BinaryOp::Mod => lhs.checked_rem(rhs),
+        BinaryOp::Shl => shift().and_then(|rhs| lhs.checked_shl(rhs)),
+        BinaryOp::Shr => shift().and_then(|rhs| lhs.checked_shr(rhs)),
+        BinaryOp::Xor => Some(lhs ^ rhs),
+        BinaryOp::BitAnd => Some(lhs & rhs),
+        BinaryOp::BitOr => Some(lhs | rhs),
+        BinaryOp::Eq => Some((lhs == rhs) as i64),
+        BinaryOp::Ne => Some((lhs != rhs) as i64),
+        BinaryOp::Gt => Some((lhs > rhs) as i64),
+        BinaryOp::GtEq => Some((lhs >= rhs) as i64),
+        BinaryOp::Lt => Some((lhs < rhs) as i64),
+        BinaryOp::LtEq => Some((lhs <= rhs) as i64),
+        BinaryOp::And => Some((lhs != 0 && rhs != 0) as i64),
+        BinaryOp::Or => Some((lhs != 0 || rhs != 0) as i64),
+    }
+}
+
+/// Applies a non-bitwise binary operator to two decimals, returning `None` if the result is not a finite number
+fn decimal_operation(op: &BinaryOp, lhs: f64, rhs: f64) -> Option<Value> {
+    let result = match op {
+        BinaryOp::Add => lhs + rhs,
+        BinaryOp::Sub => lhs - rhs,
+        BinaryOp::Mul => lhs * rhs,
+        BinaryOp::Div if rhs != 0.0 => lhs / rhs,
+        BinaryOp::Mod if rhs != 0.0 => lhs % rhs,
+        BinaryOp::Div | BinaryOp::Mod => return None,
+        BinaryOp::Eq => return Some(Value::Integer((lhs == rhs) as i64)),
+        BinaryOp::Ne => return Some(Value::Integer((lhs != rhs) as i64)),
+        BinaryOp::Gt => return Some(Value::Integer((lhs > rhs) as i64)),
+        BinaryOp::GtEq => return Some(Value::Integer((lhs >= rhs) as i64)),
+        BinaryOp::Lt => return Some(Value::Integer((lhs < rhs) as i64)),
+        BinaryOp::LtEq => return Some(Value::Integer((lhs <= rhs) as i64)),
+        BinaryOp::And => return Some(Value::Integer((lhs != 0.0 && rhs != 0.0) as i64)),
+        BinaryOp::Or => return Some(Value::Integer((lhs != 0.0 || rhs != 0.0) as i64)),
+        BinaryOp::Shl | BinaryOp::Shr | BinaryOp::Xor | BinaryOp::BitAnd | BinaryOp::BitOr => {
+            unreachable!("bitwise operators are only applied to integers")
+        }
+    };
+
+    if result.is_finite() {
+        Some(Value::Decimal(result))
+    } else {
+        None
+    }
+}
+
 fn to_mos_error(tree: Arc<ParseTree>, error: CodegenError) -> MosError {
     match error {
         CodegenError::Mos(e) => e,
@@ -91,14 +226,22 @@ fn to_mos_error(tree: Arc<ParseTree>, error: CodegenError) -> MosError {
     }
 }
 
+#[derive(Clone)]
 pub struct CodegenOptions {
     pub pc: ProgramCounter,
+    pub rounding: Rounding,
+    pub basic_stub: Option<BasicStub>,
+    /// The machine to assemble for, providing its memory layout and hardware registers
+    pub target: Option<Target>,
 }
 
 impl Default for CodegenOptions {
     fn default() -> Self {
         Self {
             pc: ProgramCounter::new(0xc000),
+            rounding: Rounding::default(),
+            basic_stub: None,
+            target: None,
         }
     }
 }
@@ -124,10 +267,19 @@ impl SegmentMap {
         }
     }
 
-    fn try_get<I: Into<Identifier>>(&self, name: I) -> Option<&Segment> {
+    /// Inserts a segment without making it the current segment
+    fn insert_inactive<N: Into<Identifier>>(&mut self, name: N, segment: Segment) {
+        self.segments.insert(name.into(), segment);
+    }
+
+    pub fn try_get<I: Into<Identifier>>(&self, name: I) -> Option<&Segment> {
         self.segments.get(&name.into())
     }
 
+    fn try_get_mut<I: Into<Identifier>>(&mut self, name: I) -> Option<&mut Segment> {
+        self.segments.get_mut(&name.into())
+    }
+
     pub fn get<I: Into<Identifier>>(&self, name: I) -> &Segment {
         let name = name.into();
         self.segments
@@ -172,22 +324,42 @@ impl SegmentMap {
     }
 }
 
-pub type RegisteredFunction = &'static dyn Fn(Vec<Option<i64>>) -> CodegenResult<Option<i64>>;
-
 pub enum EmitResult {
     Success(Option<Span>, Vec<u8>),
     SuccessNoData,
+    /// Space was reserved, but no data was written
+    Reserve(Span, usize),
     TryLater(Option<Span>, usize),
     TryLaterNoData,
 }
 
+/// A value that was determined during code generation for a specific location in the source
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeneratedValue {
+    /// The address that a label resolved to
+    Address(ProgramCounter),
+    /// The evaluated value of a constant or variable
+    Value(Value),
+    /// The bytes that were emitted at a certain program counter
+    Bytes(ProgramCounter, Vec<u8>),
+}
+
 #[derive(Debug)]
 pub enum Emittable<'a> {
-    Single(Option<ProgramCounter>, &'a Token),
+    /// (Program counter, the token, the `.encoding` that is active for the token, the position of the token between anonymous labels)
+    Single(
+        Option<ProgramCounter>,
+        &'a Token,
+        Option<&'a Located<Identifier>>,
+        AnonymousLabelPosition,
+    ),
     Label(&'a Located<Identifier>),
+    /// (The `-` or `+` marker, the generated name of the label)
+    AnonymousLabel(&'a Located<char>, Identifier),
     /// (Name of the scope, the emittables in the scope)
     Nested(&'a Identifier, Vec<Emittable<'a>>),
     SegmentDefinition(ConfigMap<'a>),
+    RegionDefinition(ConfigMap<'a>),
     Segment(&'a Identifier, Span, Option<Box<Emittable<'a>>>),
     If(
         &'a Located<Expression>,
@@ -196,13 +368,32 @@ pub enum Emittable<'a> {
     ),
 }
 
+/// The position of a token relative to the anonymous labels in its block
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AnonymousLabelPosition {
+    /// The number of `-` labels that precede the token
+    preceding: usize,
+    /// The number of `+` labels that follow the token
+    following: usize,
+}
+
 pub struct CodegenContext {
     tree: Arc<ParseTree>,
     options: CodegenOptions,
     segments: SegmentMap,
     symbols: SymbolTable,
     functions: HashMap<Identifier, RegisteredFunction>,
+    charmaps: HashMap<Identifier, HashMap<char, u8>>,
+    /// For every segment that is placed after another segment, the name of that other segment
+    segment_dependencies: HashMap<Identifier, Identifier>,
+    regions: HashMap<Identifier, Region>,
+    /// The encoding that is active for the token that is being emitted
+    encoding: Option<Located<Identifier>>,
+    /// The position of the token that is being emitted, used to resolve anonymous label references
+    anonymous_labels: AnonymousLabelPosition,
     errors: Vec<CodegenError>,
+    warnings: Vec<CodegenError>,
+    generated_values: HashMap<Span, GeneratedValue>,
 }
 
 impl CodegenContext {
@@ -213,7 +404,14 @@ impl CodegenContext {
             segments: SegmentMap::new(),
             symbols: SymbolTable::new(),
             functions: HashMap::new(),
+            charmaps: HashMap::new(),
+            segment_dependencies: HashMap::new(),
+            regions: HashMap::new(),
+            encoding: None,
+            anonymous_labels: AnonymousLabelPosition::default(),
             errors: vec![],
+            warnings: vec![],
+            generated_values: HashMap::new(),
         }
     }
 
@@ -225,8 +423,32 @@ impl CodegenContext {
         &self.segments
     }
 
-    pub fn register_fn(&mut self, name: &str, function: RegisteredFunction) {
-        self.functions.insert(name.into(), function);
+    pub fn register_fn(&mut self, function: RegisteredFunction) {
+        self.functions.insert(function.name.into(), function);
+    }
+
+    /// The errors that were encountered during code generation
+    pub fn errors(&self) -> &[CodegenError] {
+        &self.errors
+    }
+
+    /// Any diagnostics that did not prevent code generation from succeeding
+    pub fn warnings(&self) -> &[CodegenError] {
+        &self.warnings
+    }
+
+    /// The value that was generated for the token at the provided span, if any
+    pub fn generated_value(&self, span: &Span) -> Option<&GeneratedValue> {
+        self.generated_values.get(span)
+    }
+
+    /// Resolves a filename relative to the source file that contains the span
+    fn resolve_path(&self, span: Span, filename: &str) -> PathBuf {
+        let source_file: PathBuf = self.tree.code_map().look_up_span(span).file.name().into();
+        match source_file.parent() {
+            Some(parent) => parent.join(filename),
+            None => PathBuf::from(filename),
+        }
     }
 
     fn push_error(&mut self, error: CodegenError) {
@@ -235,24 +457,164 @@ impl CodegenContext {
         }
     }
 
+    fn push_warning(&mut self, warning: CodegenError) {
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// Registers the regions and hardware register constants of the target machine
+    fn register_target(&mut self, target: Target) -> CodegenResult<()> {
+        for (name, region) in target.regions() {
+            self.regions.insert(name.into(), region);
+        }
+        for (path, value) in target.constants() {
+            self.symbols
+                .register(path, Symbol::System(value), None, true)?;
+        }
+        Ok(())
+    }
+
+    /// Inserts the segments that the target machine predefines, next to the default segment
+    fn insert_target_segments(&mut self, target: Target) {
+        for (name, start, end, write) in target.segments() {
+            let options = SegmentOptions {
+                initial_pc: start,
+                write,
+                target_address: start,
+                end: Some(end),
+                io_ranges: self.io_ranges(),
+                ..Default::default()
+            };
+            self.segments
+                .insert_inactive(name, Segment::new(name, options));
+        }
+    }
+
+    /// The target addresses that contain I/O registers
+    fn io_ranges(&self) -> Vec<Range<usize>> {
+        match self.options.target {
+            Some(target) => target
+                .regions()
+                .into_iter()
+                .filter(|(_, region)| region.kind == RegionKind::Io)
+                .map(|(_, region)| region.start.into()..region.end.into())
+                .collect(),
+            None => vec![IO_RANGE],
+        }
+    }
+
+    /// An empty span at the start of the main source file, used for diagnostics that do not belong to a specific token
+    fn file_start(&self) -> Option<Span> {
+        self.tree.files().first().map(|f| f.span.subspan(0, 0))
+    }
+
+    /// Have any segments been defined, apart from the segment containing the BASIC stub?
+    fn has_user_segments(&self) -> bool {
+        let stub_segment = Identifier::from(BASIC_STUB_SEGMENT);
+        match &self.options.basic_stub {
+            Some(_) => self.segments.keys().into_iter().any(|n| n != &stub_segment),
+            None => !self.segments.is_empty(),
+        }
+    }
+
+    /// Reserves space for the BASIC stub. The stub itself is emitted by [CodegenContext::emit_basic_stub].
+    fn reserve_basic_stub(&mut self, stub: &BasicStub, span: Span) -> CodegenResult<()> {
+        let options = SegmentOptions {
+            initial_pc: stub.start,
+            target_address: stub.start,
+            ..Default::default()
+        };
+        let mut segment = Segment::new(BASIC_STUB_SEGMENT, options);
+        segment.reserve(&span, BasicStub::LEN)?;
+        self.segments.insert_inactive(BASIC_STUB_SEGMENT, segment);
+        Ok(())
+    }
+
+    /// Emits the BASIC stub, now that the address it should jump to is known.
+    ///
+    /// Without an entry the stub can only jump to the code directly following it, which is only there when the default segment was created.
+    fn emit_basic_stub(
+        &mut self,
+        stub: &BasicStub,
+        span: Span,
+        code_follows_stub: bool,
+    ) -> CodegenResult<()> {
+        let sys_address = match &stub.entry {
+            Some(entry) => match self.symbols.value(entry)? {
+                Some(Value::Integer(address)) => address,
+                _ => {
+                    return Err(CodegenError::new(
+                        span,
+                        DetailedCodegenError::UnknownIdentifier(entry.clone()),
+                    ))
+                }
+            },
+            None if code_follows_stub => stub.end().as_i64(),
+            None => {
+                return Err(CodegenError::new(
+                    span,
+                    DetailedCodegenError::BasicStubEntryRequired(),
+                ))
+            }
+        };
+        if !(0..=0xffff).contains(&sys_address) {
+            return Err(CodegenError::new(
+                span,
+                DetailedCodegenError::ValueOutOfRange(sys_address, 0, 0xffff),
+            ));
+        }
+
+        // The segment should still be the one that was reserved by [CodegenContext::reserve_basic_stub]
+        let segment = match self.segments.try_get_mut(BASIC_STUB_SEGMENT) {
+            Some(segment) if segment.options().target_address == stub.start => segment,
+            _ => {
+                return Err(CodegenError::new(
+                    span,
+                    DetailedCodegenError::ReservedSegmentName(BASIC_STUB_SEGMENT.into()),
+                ))
+            }
+        };
+        segment.set_current_pc(stub.start);
+        segment.set(&span, &stub.to_bytes(sys_address as u16))?;
+        Ok(())
+    }
+
     fn evaluate_factor(
         &self,
         lt: &Located<ExpressionFactor>,
         pc: Option<ProgramCounter>,
         error_on_failure: bool,
-    ) -> CodegenResult<Option<i64>> {
+    ) -> CodegenResult<Option<Value>> {
         match &lt.data {
-            ExpressionFactor::Number { value, .. } => Ok(Some(value.data.value())),
-            ExpressionFactor::CurrentProgramCounter(_) => Ok(pc.map(|p| p.as_i64())),
+            ExpressionFactor::Number { value, .. } => match value.data.is_integer() {
+                true => Ok(Some(Value::Integer(value.data.value()))),
+                false => Ok(Some(Value::Decimal(value.data.as_f64()))),
+            },
+            ExpressionFactor::CurrentProgramCounter(_) => {
+                Ok(pc.map(|p| Value::Integer(p.as_i64())))
+            }
+            ExpressionFactor::ExprParens { inner, .. } => {
+                self.evaluate_value(&inner.data, pc, error_on_failure)
+            }
             ExpressionFactor::IdentifierValue { path, modifier } => {
-                let symbol_value = self.symbols.value(&path.data)?;
+                let symbol_value = match self.anonymous_label_path(&path.data) {
+                    Some(label) => self.symbols.local_value(&label)?,
+                    None => self.symbols.value(&path.data)?,
+                };
 
                 match (symbol_value, modifier, error_on_failure) {
                     (Some(val), None, _) => Ok(Some(val)),
-                    (Some(val), Some(modifier), _) => match modifier.data {
-                        AddressModifier::HighByte => Ok(Some((val >> 8) & 255)),
-                        AddressModifier::LowByte => Ok(Some(val & 255)),
-                    },
+                    (Some(val), Some(modifier), _) => {
+                        let val = match self.to_integer(val, path.span, error_on_failure)? {
+                            Some(val) => val,
+                            None => return Ok(None),
+                        };
+                        match modifier.data {
+                            AddressModifier::HighByte => Ok(Some(Value::Integer((val >> 8) & 255))),
+                            AddressModifier::LowByte => Ok(Some(Value::Integer(val & 255))),
+                        }
+                    }
                     (None, _, false) => Ok(None),
                     (None, _, true) => Err(CodegenError::new(
                         path.span,
@@ -260,72 +622,227 @@ impl CodegenContext {
                     )),
                 }
             }
-            ExpressionFactor::FunctionCall { name, args, .. } => {
-                let mut evaluated_args = vec![];
-                for (arg, _comma) in args {
-                    evaluated_args.push(self.evaluate(&arg.data, pc, error_on_failure)?);
+            ExpressionFactor::FunctionCall {
+                name, args, rparen, ..
+            } => {
+                let function = match self.functions.get(&name.data) {
+                    Some(f) => f,
+                    None => {
+                        return Err(CodegenError::new(
+                            name.span,
+                            DetailedCodegenError::UnknownFunction(name.data.clone()),
+                        ))
+                    }
+                };
+                let span = name.span.merge(rparen.span);
+                if args.len() != function.arity() {
+                    return Err(CodegenError::new(
+                        span,
+                        DetailedCodegenError::FunctionArity(
+                            name.data.clone(),
+                            function.arity(),
+                            args.len(),
+                        ),
+                    ));
                 }
-                match self.functions.get(&name.data) {
-                    Some(f) => f(evaluated_args),
-                    None => Err(CodegenError::new(
-                        name.span,
-                        DetailedCodegenError::UnknownFunction(name.data.clone()),
-                    )),
+
+                let mut evaluated_args = vec![];
+                for ((arg, _comma), (_, ty)) in args.iter().zip(function.parameters) {
+                    let value = match ty {
+                        ParameterType::Number => ArgumentValue::Number(self.evaluate_value(
+                            &arg.data,
+                            pc,
+                            error_on_failure,
+                        )?),
+                        ParameterType::Symbol => match factor_of(&arg.data) {
+                            Some(ExpressionFactor::IdentifierValue {
+                                path,
+                                modifier: None,
+                            }) => ArgumentValue::Symbol(&path.data),
+                            _ => {
+                                return Err(CodegenError::new(
+                                    arg.span,
+                                    DetailedCodegenError::ExpectedSymbol(),
+                                ))
+                            }
+                        },
+                        ParameterType::String => match factor_of(&arg.data) {
+                            Some(ExpressionFactor::StringLiteral(s)) => {
+                                ArgumentValue::String(&s.data.0)
+                            }
+                            _ => {
+                                return Err(CodegenError::new(
+                                    arg.span,
+                                    DetailedCodegenError::ExpectedString(),
+                                ))
+                            }
+                        },
+                    };
+                    evaluated_args.push(FunctionArgument {
+                        span: arg.span,
+                        value,
+                    });
                 }
+
+                (function.callback)(&FunctionCall {
+                    ctx: self,
+                    span,
+                    args: evaluated_args,
+                    error_on_failure,
+                })
             }
-            _ => panic!("Unsupported token: {:?}", lt.data),
+            ExpressionFactor::CharLiteral(c) => Ok(self
+                .encode_char(c.data.0, c.span, error_on_failure)?
+                .map(Value::Integer)),
+            ExpressionFactor::StringLiteral(_) => Err(CodegenError::new(
+                lt.span,
+                DetailedCodegenError::UnexpectedString(),
+            )),
+        }
+    }
+
+    /// Encodes a character using the encoding that is currently active
+    fn encode_char(
+        &self,
+        c: char,
+        span: Span,
+        error_on_failure: bool,
+    ) -> CodegenResult<Option<i64>> {
+        let name = match &self.encoding {
+            Some(name) => name.data.clone(),
+            None => "ascii".into(),
+        };
+        let encoded = match Encoding::from_name(&name) {
+            Encoding::Charmap(id) => match self.charmaps.get(&id) {
+                Some(map) => map.get(&c).copied(),
+                // The character map may not have been defined yet
+                None if !error_on_failure => return Ok(None),
+                None => {
+                    let span = self.encoding.as_ref().map(|e| e.span).unwrap_or(span);
+                    return Err(CodegenError::new(
+                        span,
+                        DetailedCodegenError::UnknownEncoding(id),
+                    ));
+                }
+            },
+            encoding => encoding.encode(c),
+        };
+
+        match encoded {
+            Some(byte) => Ok(Some(byte as i64)),
+            // Character maps may be extended by definitions that have not been processed yet
+            None if !error_on_failure => Ok(None),
+            None => Err(CodegenError::new(
+                span,
+                DetailedCodegenError::UnencodableCharacter(c, name),
+            )),
         }
     }
 
+    /// Evaluates an expression to an integer. Decimal results are rounded according to the configured [Rounding].
     fn evaluate(
         &self,
         expr: &Expression,
         pc: Option<ProgramCounter>,
         error_on_failure: bool,
     ) -> CodegenResult<Option<i64>> {
+        match self.evaluate_value(expr, pc, error_on_failure)? {
+            Some(value) => self.to_integer(value, expr.span(), error_on_failure),
+            None => Ok(None),
+        }
+    }
+
+    fn to_integer(
+        &self,
+        value: Value,
+        span: Span,
+        error_on_failure: bool,
+    ) -> CodegenResult<Option<i64>> {
+        match value.to_integer(self.options.rounding) {
+            Some(val) => Ok(Some(val)),
+            None if error_on_failure => Err(CodegenError::new(
+                span,
+                DetailedCodegenError::ArithmeticOverflow(),
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn evaluate_value(
+        &self,
+        expr: &Expression,
+        pc: Option<ProgramCounter>,
+        error_on_failure: bool,
+    ) -> CodegenResult<Option<Value>> {
         match expr {
             Expression::Factor { factor, flags, .. } => {
-                match self.evaluate_factor(factor, pc, error_on_failure) {
-                    Ok(Some(mut val)) => {
-                        if flags.contains(ExpressionFactorFlags::NOT) {
-                            if val == 0 {
-                                val = 1
-                            } else {
-                                val = 0
-                            };
-                        }
-                        if flags.contains(ExpressionFactorFlags::NEG) {
-                            val = -val;
-                        }
-                        Ok(Some(val))
-                    }
-                    v => v,
+                let mut val = match self.evaluate_factor(factor, pc, error_on_failure)? {
+                    Some(val) => val,
+                    None => return Ok(None),
+                };
+                if flags.contains(ExpressionFactorFlags::INVERT) {
+                    val = match self.to_integer(val, factor.span, error_on_failure)? {
+                        Some(val) => Value::Integer(!val),
+                        None => return Ok(None),
+                    };
+                }
+                if flags.contains(ExpressionFactorFlags::NOT) {
+                    val = Value::Integer(val.is_zero() as i64);
+                }
+                if flags.contains(ExpressionFactorFlags::NEG) {
+                    val = match val {
+                        Value::Integer(val) => match val.checked_neg() {
+                            Some(val) => Value::Integer(val),
+                            None if error_on_failure => {
+                                return Err(CodegenError::new(
+                                    factor.span,
+                                    DetailedCodegenError::ArithmeticOverflow(),
+                                ))
+                            }
+                            None => return Ok(None),
+                        },
+                        Value::Decimal(val) => Value::Decimal(-val),
+                    };
                 }
+                Ok(Some(val))
             }
             Expression::BinaryExpression(expr) => {
-                let lhs = self.evaluate(&expr.lhs.data, pc, error_on_failure)?;
-                let rhs = self.evaluate(&expr.rhs.data, pc, error_on_failure)?;
+                let lhs = self.evaluate_value(&expr.lhs.data, pc, error_on_failure)?;
+                let rhs = self.evaluate_value(&expr.rhs.data, pc, error_on_failure)?;
                 let op = &expr.op.data;
                 match (lhs, rhs) {
                     (Some(lhs), Some(rhs)) => {
-                        let result: i64 = match op {
-                            BinaryOp::Add => lhs + rhs,
-                            BinaryOp::Sub => lhs - rhs,
-                            BinaryOp::Mul => lhs * rhs,
-                            BinaryOp::Div => lhs / rhs,
-                            BinaryOp::Shl => lhs << rhs,
-                            BinaryOp::Shr => lhs >> rhs,
-                            BinaryOp::Xor => lhs ^ rhs,
-                            BinaryOp::Eq => (lhs == rhs) as i64,
-                            BinaryOp::Ne => (lhs != rhs) as i64,
-                            BinaryOp::Gt => (lhs > rhs) as i64,
-                            BinaryOp::GtEq => (lhs >= rhs) as i64,
-                            BinaryOp::Lt => (lhs < rhs) as i64,
-                            BinaryOp::LtEq => (lhs <= rhs) as i64,
-                            BinaryOp::And => (lhs != 0 && rhs != 0) as i64,
-                            BinaryOp::Or => (lhs != 0 || rhs != 0) as i64,
+                        let rounding = self.options.rounding;
+                        let result = match (lhs, rhs) {
+                            (Value::Integer(lhs), Value::Integer(rhs)) => {
+                                integer_operation(op, lhs, rhs).map(Value::Integer)
+                            }
+                            // Bitwise operations only apply to integers, so decimals are rounded first
+                            _ if op.is_bitwise() => {
+                                match (lhs.to_integer(rounding), rhs.to_integer(rounding)) {
+                                    (Some(lhs), Some(rhs)) => {
+                                        integer_operation(op, lhs, rhs).map(Value::Integer)
+                                    }
+                                    _ => None,
+                                }
+                            }
+                            _ => decimal_operation(op, lhs.as_f64(), rhs.as_f64()),
                         };
-                        Ok(Some(result))
+
+                        match result {
+                            Some(result) => Ok(Some(result)),
+                            // Operands may still change in a later pass, so only report errors when we have to
+                            None if !error_on_failure => Ok(None),
+                            None => {
+                                let error = match op {
+                                    BinaryOp::Div | BinaryOp::Mod if rhs.is_zero() => {
+                                        DetailedCodegenError::DivisionByZero()
+                                    }
+                                    _ => DetailedCodegenError::ArithmeticOverflow(),
+                                };
+                                Err(CodegenError::new(expr.op.span, error))
+                            }
+                        }
                     }
                     _ => Ok(None),
                 }
@@ -522,10 +1039,10 @@ impl CodegenContext {
                     Some(val) => {
                         let mut result = None;
                         for (opcode, operand_length) in possible_opcodes {
-                            if operand_length == 1 && val < 256 {
+                            if operand_length == 1 && fits_in_bytes(val, 1) {
                                 result = Some((true, smallvec![opcode, val as u8]));
                                 break;
-                            } else if operand_length == 2 {
+                            } else if operand_length == 2 && fits_in_bytes(val, 2) {
                                 let v = (val as u16).to_le_bytes();
                                 result = Some((true, smallvec![opcode, v[0], v[1]]));
                                 break;
@@ -570,40 +1087,58 @@ impl CodegenContext {
     fn emit_data(
         &mut self,
         exprs: &[&Located<Expression>],
-        data_length: usize,
+        size: DataSize,
         pc: Option<ProgramCounter>,
         error_on_failure: bool,
     ) -> CodegenResult<EmitResult> {
         // Did any of the emitted exprs fail to evaluate? Then re-evaluate all of them later.
         let mut any_failed = false;
 
-        let mut span = None;
-        let mut bytes = vec![];
+        let data_length = size.byte_len();
+        let span = exprs.first().map(|expr| expr.span);
+        let mut values = vec![];
         for expr in exprs {
-            if span.is_none() {
-                span = Some(expr.span);
-            }
-            let evaluated = self.evaluate(&expr.data, pc, error_on_failure)?;
-            let data: Vec<u8> = match evaluated {
-                Some(val) => match data_length {
-                    1 => vec![val as u8],
-                    2 => (val as u16).to_le_bytes().to_vec(),
-                    4 => (val as u32).to_le_bytes().to_vec(),
-                    _ => panic!(),
-                },
+            match self.evaluate(&expr.data, pc, error_on_failure)? {
+                Some(val) if !fits_in_bytes(val, data_length) => {
+                    let bits = 8 * data_length as u32;
+                    return Err(CodegenError::new(
+                        expr.span,
+                        DetailedCodegenError::ValueOutOfRange(
+                            val,
+                            -(1 << (bits - 1)),
+                            (1 << bits) - 1,
+                        ),
+                    ));
+                }
+                Some(val) => values.push(val),
                 None => {
                     any_failed = true;
-                    match data_length {
-                        1 => vec![0_u8],
-                        2 => 0_u16.to_le_bytes().to_vec(),
-                        4 => 0_u32.to_le_bytes().to_vec(),
-                        _ => panic!(),
-                    }
+                    values.push(0);
                 }
-            };
-            bytes.extend(data);
+            }
         }
 
+        let bytes = match size {
+            DataSize::LoHi | DataSize::HiLo => {
+                let lo = values.iter().map(|val| *val as u8).collect_vec();
+                let hi = values.iter().map(|val| (*val >> 8) as u8).collect_vec();
+                match size {
+                    DataSize::LoHi => [lo, hi].concat(),
+                    _ => [hi, lo].concat(),
+                }
+            }
+            _ => values
+                .iter()
+                .flat_map(|val| {
+                    let mut bytes = val.to_le_bytes()[..data_length].to_vec();
+                    if size.is_big_endian() {
+                        bytes.reverse();
+                    }
+                    bytes
+                })
+                .collect_vec(),
+        };
+
         let result = match any_failed {
             true => EmitResult::TryLater(span, bytes.len()),
             false => EmitResult::Success(span, bytes),
@@ -612,6 +1147,98 @@ impl CodegenContext {
         Ok(result)
     }
 
+    fn emit_fill(
+        &mut self,
+        count: &Located<Expression>,
+        value: &Located<Expression>,
+        pc: Option<ProgramCounter>,
+        error_on_failure: bool,
+    ) -> CodegenResult<EmitResult> {
+        let count = match self.evaluate_count(count, pc, error_on_failure)? {
+            Some(0) => return Ok(EmitResult::SuccessNoData),
+            Some(count) => count,
+            None => return Ok(EmitResult::TryLaterNoData),
+        };
+
+        match self.evaluate(&value.data, pc, error_on_failure)? {
+            Some(val) if !fits_in_bytes(val, 1) => Err(CodegenError::new(
+                value.span,
+                DetailedCodegenError::ValueOutOfRange(val, -128, 255),
+            )),
+            Some(val) => Ok(EmitResult::Success(
+                Some(value.span),
+                vec![val as u8; count],
+            )),
+            None => Ok(EmitResult::TryLater(Some(value.span), count)),
+        }
+    }
+
+    /// Evaluates the number of bytes that a directive such as `.fill` or `.res` takes up
+    fn evaluate_count(
+        &mut self,
+        count: &Located<Expression>,
+        pc: Option<ProgramCounter>,
+        error_on_failure: bool,
+    ) -> CodegenResult<Option<usize>> {
+        match self.evaluate(&count.data, pc, error_on_failure)? {
+            Some(val) if !(0..=0x10000).contains(&val) => Err(CodegenError::new(
+                count.span,
+                DetailedCodegenError::ValueOutOfRange(val, 0, 0x10000),
+            )),
+            Some(val) => Ok(Some(val as usize)),
+            None => Ok(None),
+        }
+    }
+
+    fn emit_text(
+        &mut self,
+        values: &[ArgItem],
+        pc: Option<ProgramCounter>,
+        error_on_failure: bool,
+    ) -> CodegenResult<EmitResult> {
+        let mut any_failed = false;
+
+        let span = values.first().map(|(expr, _)| expr.span);
+        let mut bytes = vec![];
+        for (expr, _) in values {
+            // Strings are encoded character by character, anything else is emitted as a single byte
+            match factor_of(&expr.data) {
+                Some(ExpressionFactor::StringLiteral(s)) => {
+                    for c in s.data.0.chars() {
+                        match self.encode_char(c, s.span, error_on_failure)? {
+                            Some(byte) => bytes.push(byte as u8),
+                            None => {
+                                any_failed = true;
+                                bytes.push(0);
+                            }
+                        }
+                    }
+                }
+                _ => match self.evaluate(&expr.data, pc, error_on_failure)? {
+                    Some(val) if !fits_in_bytes(val, 1) => {
+                        return Err(CodegenError::new(
+                            expr.span,
+                            DetailedCodegenError::ValueOutOfRange(val, -128, 255),
+                        ));
+                    }
+                    Some(val) => bytes.push(val as u8),
+                    None => {
+                        any_failed = true;
+                        bytes.push(0);
+                    }
+                },
+            }
+        }
+
+        let result = match (any_failed, bytes.is_empty()) {
+            (true, _) => EmitResult::TryLater(span, bytes.len()),
+            (false, true) => EmitResult::SuccessNoData,
+            (false, false) => EmitResult::Success(span, bytes),
+        };
+
+        Ok(result)
+    }
+
     fn emit_single(
         &mut self,
         token: &Token,
@@ -632,7 +1259,9 @@ impl CodegenContext {
                 None => Ok(EmitResult::TryLaterNoData),
             },
             Token::VariableDefinition { ty, id, value, .. } => {
-                let eval = self.evaluate(&value.data, pc, error_on_failure)?.unwrap();
+                let eval = self
+                    .evaluate_value(&value.data, pc, error_on_failure)?
+                    .unwrap();
 
                 let result = match ty.data {
                     VariableType::Variable => self.symbols.register(
@@ -649,6 +1278,10 @@ impl CodegenContext {
                     ),
                 };
 
+                if result.is_ok() {
+                    self.generated_values
+                        .insert(id.span, GeneratedValue::Value(eval));
+                }
                 result.map(|_| EmitResult::SuccessNoData)
             }
             Token::ProgramCounterDefinition { value, .. } => {
@@ -666,16 +1299,47 @@ impl CodegenContext {
             }
             Token::Data { values, size } => {
                 let values = values.iter().map(|(expr, _comma)| expr).collect_vec();
-                self.emit_data(&values, size.data.byte_len(), pc, error_on_failure)
+                self.emit_data(&values, size.data, pc, error_on_failure)
             }
+            Token::Fill { count, value, .. } => self.emit_fill(count, value, pc, error_on_failure),
+            Token::Reserve { count, .. } => {
+                match self.evaluate_count(count, pc, error_on_failure)? {
+                    Some(0) => Ok(EmitResult::SuccessNoData),
+                    Some(length) => Ok(EmitResult::Reserve(count.span, length)),
+                    None => Ok(EmitResult::TryLaterNoData),
+                }
+            }
+            Token::Text {
+                encoding, values, ..
+            } => {
+                // An explicitly provided encoding overrides the one set by `.encoding`
+                if encoding.is_some() {
+                    self.encoding = encoding.clone();
+                }
+                self.emit_text(values, pc, error_on_failure)
+            }
+            Token::Charmap {
+                id, chars, value, ..
+            } => match self.evaluate(&value.data, pc, error_on_failure)? {
+                Some(start) => {
+                    let map = self.charmaps.entry(id.data.clone()).or_default();
+                    for (index, c) in chars.data.0.chars().enumerate() {
+                        let code = start + index as i64;
+                        if !(0..=255).contains(&code) {
+                            return Err(CodegenError::new(
+                                value.span,
+                                DetailedCodegenError::ValueOutOfRange(code, 0, 255),
+                            ));
+                        }
+                        map.insert(c, code as u8);
+                    }
+                    Ok(EmitResult::SuccessNoData)
+                }
+                None => Ok(EmitResult::TryLaterNoData),
+            },
             Token::Include { filename, .. } => {
                 let span = filename.span;
-                let source_file: PathBuf =
-                    self.tree.code_map().look_up_span(span).file.name().into();
-                let filename = match source_file.parent() {
-                    Some(parent) => parent.join(&filename.data),
-                    None => PathBuf::from(&filename.data),
-                };
+                let filename = self.resolve_path(span, &filename.data);
                 match fs::read(&filename) {
                     Ok(bytes) => Ok(EmitResult::Success(Some(span), bytes)),
                     Err(_) => Err(CodegenError::Detailed(
@@ -701,6 +1365,19 @@ impl CodegenContext {
                         let segment = self.segments.current_mut();
                         segment.set_current_pc(pc);
                         segment.set(span, &bytes)?;
+                        self.generated_values
+                            .insert(*span, GeneratedValue::Bytes(pc, bytes.clone()));
+                    }
+                    EmitResult::Reserve(span, length) => {
+                        log::trace!(
+                            "Reserving {} bytes without writing in segment {:?} at pc {}",
+                            length,
+                            self.segments.current,
+                            pc,
+                        );
+                        let segment = self.segments.current_mut();
+                        segment.set_current_pc(pc);
+                        segment.reserve(span, *length)?;
                     }
                     EmitResult::TryLater(Some(span), bytes_to_reserve) => {
                         log::trace!(
@@ -759,86 +1436,405 @@ impl CodegenContext {
         }
     }
 
-    fn emit_emittable<'a>(
+    /// Like [CodegenContext::evaluate_or_error], but for keys that may be omitted.
+    ///
+    /// Returns `None` if the key is present, but could not be evaluated (yet).
+    fn evaluate_optional_or_error(
         &mut self,
-        emittable: Emittable<'a>,
+        identifier: &str,
+        cfg: &ConfigMap,
+        pc: Option<ProgramCounter>,
         error_on_failure: bool,
-    ) -> Option<Emittable<'a>> {
-        log::trace!("Processing emittable: {:?}", emittable);
-        let pc = self.segments.try_current().map(|seg| seg.current_pc());
-        match emittable {
-            Emittable::Label(id) => match pc {
-                Some(pc) => {
-                    match self
-                        .symbols
-                        .register(&id.data, Symbol::Label(pc), Some(&id.span), false)
-                    {
-                        Ok(_) => None,
-                        Err(e) => {
-                            self.push_error(e);
-                            None
-                        }
-                    }
-                }
-                None => Some(Emittable::Label(id)),
-            },
-            Emittable::SegmentDefinition(cfg) => {
-                let start = self.evaluate_or_error(
-                    "start",
-                    &cfg,
-                    pc,
-                    error_on_failure,
-                    "Could not determine start address for segment",
-                );
+        error_msg: &str,
+    ) -> Option<Option<i64>> {
+        match cfg.try_value(identifier) {
+            Some(_) => self
+                .evaluate_or_error(identifier, cfg, pc, error_on_failure, error_msg)
+                .map(Some),
+            None => Some(None),
+        }
+    }
 
-                let target_pc = self.evaluate_or_error(
-                    "pc",
-                    &cfg,
-                    pc,
-                    error_on_failure,
-                    "Could not determine target PC for segment",
-                );
-
-                match start {
-                    Some(start) => {
-                        let start = ProgramCounter::new(start as usize);
-                        let initial_pc = match target_pc {
-                            Some(pc) => ProgramCounter::new(pc as usize),
-                            None => start,
-                        };
+    /// Tries to create a segment from its definition. Returns `false` if not all options could be evaluated yet.
+    fn define_segment(
+        &mut self,
+        cfg: &ConfigMap,
+        pc: Option<ProgramCounter>,
+        error_on_failure: bool,
+    ) -> bool {
+        let name = cfg.value_as_identifier_path("name").single().clone();
+        if self.options.basic_stub.is_some() && name == Identifier::from(BASIC_STUB_SEGMENT) {
+            self.push_error(CodegenError::new(
+                cfg.value("name").span(),
+                DetailedCodegenError::ReservedSegmentName(name),
+            ));
+            return true;
+        }
 
-                        let name = cfg.value_as_identifier_path("name").single();
-                        let write = match cfg.try_value_as_identifier_path("write") {
-                            Some(val) => bool::from_str(&val.single().value()).unwrap_or(true),
-                            None => true,
-                        };
-                        let options = SegmentOptions {
-                            initial_pc,
-                            write,
-                            target_address: start,
-                        };
-                        let segment = Segment::new(name, options);
-                        self.segments.insert(name, segment);
-                        None
-                    }
-                    None => {
+        let base = match cfg.try_value_as_identifier_path("after") {
+            Some(after) => self.end_of_preceding_segment(&name, after, cfg, error_on_failure),
+            None => self.evaluate_or_error(
+                "start",
+                cfg,
+                pc,
+                error_on_failure,
+                "Could not determine start address for segment",
+            ),
+        };
+
+        let mut evaluate = |key: &str, error_msg: &str| {
+            self.evaluate_optional_or_error(key, cfg, pc, error_on_failure, error_msg)
+        };
+        let target_pc = evaluate("pc", "Could not determine target PC for segment");
+        let end = evaluate("end", "Could not determine end address for segment");
+        let size = evaluate("size", "Could not determine size of segment");
+        let align = evaluate("align", "Could not determine alignment of segment");
+        let fill = evaluate("fill", "Could not determine fill byte for segment");
+        let bank = evaluate("bank", "Could not determine bank of segment");
+
+        let (base, target_pc, end, size, align, fill, bank) =
+            match (base, target_pc, end, size, align, fill, bank) {
+                (
+                    Some(base),
+                    Some(pc),
+                    Some(end),
+                    Some(size),
+                    Some(align),
+                    Some(fill),
+                    Some(bank),
+                ) => (base, pc, end, size, align, fill, bank),
+                _ => return false,
+            };
+
+        if let Some(bank) = bank {
+            if !(0..=255).contains(&bank) {
+                let span = cfg.value("bank").span();
+                self.push_error(CodegenError::new(
+                    span,
+                    DetailedCodegenError::ValueOutOfRange(bank, 0, 255),
+                ));
+                return true;
+            }
+        }
+
+        let start = match align {
+            Some(align) if align <= 0 => {
+                let span = cfg.value("align").span();
+                self.push_error(CodegenError::new(
+                    span,
+                    DetailedCodegenError::ValueOutOfRange(align, 1, 0x10000),
+                ));
+                return true;
+            }
+            Some(align) => (base + align - 1) / align * align,
+            None => base,
+        };
+
+        let padding = match fill {
+            Some(fill) if !fits_in_bytes(fill, 1) => {
+                let span = cfg.value("fill").span();
+                self.push_error(CodegenError::new(
+                    span,
+                    DetailedCodegenError::ValueOutOfRange(fill, -128, 255),
+                ));
+                return true;
+            }
+            Some(fill) if start > base => Some(Padding {
+                range: base as usize..start as usize,
+                value: fill as u8,
+            }),
+            _ => None,
+        };
+
+        let region = match cfg.try_value_as_identifier_path("region") {
+            Some(region_name) => match self.regions.get(region_name.single()) {
+                Some(region) => {
+                    if start < region.start.as_i64() || start >= region.end.as_i64() {
+                        let error = DetailedCodegenError::SegmentOutsideRegion(
+                            name,
+                            region_name.single().clone(),
+                            region.start,
+                            region.end,
+                        );
+                        self.push_error(CodegenError::new(cfg.value("region").span(), error));
+                        return true;
+                    }
+                    Some((region_name.single().clone(), region.end))
+                }
+                None if error_on_failure => {
+                    self.push_error(CodegenError::new(
+                        cfg.value("region").span(),
+                        DetailedCodegenError::UnknownIdentifier(region_name.clone()),
+                    ));
+                    return true;
+                }
+                // The region may not have been defined yet
+                None => return false,
+            },
+            None => None,
+        };
+
+        let start = ProgramCounter::new(start as usize);
+        let initial_pc = match target_pc {
+            Some(pc) => ProgramCounter::new(pc as usize),
+            None => start,
+        };
+
+        let write = match cfg.try_value_as_identifier_path("write") {
+            Some(val) => bool::from_str(val.single().value()).unwrap_or(true),
+            None => true,
+        };
+
+        // When both an end and a size are provided, the most restrictive one applies
+        let end = match (end, size) {
+            (Some(end), Some(size)) => Some(end.min(start.as_i64() + size)),
+            (Some(end), None) => Some(end),
+            (None, Some(size)) => Some(start.as_i64() + size),
+            (None, None) => None,
+        };
+
+        // A segment may never grow beyond the end of its region
+        let end = match (end, &region) {
+            (Some(end), Some((_, region_end))) => Some(end.min(region_end.as_i64())),
+            (None, Some((_, region_end))) => Some(region_end.as_i64()),
+            (end, None) => end,
+        };
+
+        let options = SegmentOptions {
+            initial_pc,
+            write,
+            target_address: start,
+            end: end.map(|end| ProgramCounter::new(end as usize)),
+            padding,
+            region: region.map(|(region_name, _)| region_name),
+            bank: bank.map(|bank| bank as u8),
+            io_ranges: self.io_ranges(),
+        };
+        let segment = Segment::new(&name, options);
+        self.segments.insert(&name, segment);
+        true
+    }
+
+    /// Tries to create a region from its definition. Returns `false` if not all options could be evaluated yet.
+    fn define_region(
+        &mut self,
+        cfg: &ConfigMap,
+        pc: Option<ProgramCounter>,
+        error_on_failure: bool,
+    ) -> bool {
+        let name = cfg.value_as_identifier_path("name").single().clone();
+        let start = self.evaluate_or_error(
+            "start",
+            cfg,
+            pc,
+            error_on_failure,
+            "Could not determine start address for region",
+        );
+        let end = self.evaluate_or_error(
+            "end",
+            cfg,
+            pc,
+            error_on_failure,
+            "Could not determine end address for region",
+        );
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return false,
+        };
+
+        let kind = cfg.value_as_identifier_path("kind").single().value();
+        let kind = match RegionKind::from_str(kind) {
+            Ok(kind) => kind,
+            Err(()) => {
+                self.push_error(CodegenError::new(
+                    cfg.value("kind").span(),
+                    DetailedCodegenError::UnknownRegionKind(kind.to_string()),
+                ));
+                return true;
+            }
+        };
+
+        let region = Region {
+            start: start.into(),
+            end: end.into(),
+            kind,
+        };
+
+        if self.regions.contains_key(&name) {
+            self.push_error(CodegenError::new(
+                cfg.value("name").span(),
+                DetailedCodegenError::RegionRedefinition(name),
+            ));
+            return true;
+        }
+        if region.end <= region.start {
+            self.push_error(CodegenError::new(
+                cfg.value("end").span(),
+                DetailedCodegenError::RegionEndBeforeStart(name, region.start, region.end),
+            ));
+            return true;
+        }
+        let overlapping = self
+            .regions
+            .iter()
+            .filter(|(_, other)| region.start < other.end && other.start < region.end)
+            .sorted_by_key(|(other_name, _)| other_name.value())
+            .map(|(other_name, other)| {
+                DetailedCodegenError::RegionOverlap(
+                    name.clone(),
+                    region.start,
+                    region.end,
+                    other_name.clone(),
+                    other.start,
+                    other.end,
+                )
+            })
+            .collect_vec();
+        for error in overlapping {
+            self.push_error(CodegenError::new(cfg.value("name").span(), error));
+        }
+
+        self.regions.insert(name, region);
+        true
+    }
+
+    /// Determines the end address of the segment that another segment should be placed after
+    fn end_of_preceding_segment(
+        &mut self,
+        name: &Identifier,
+        after: &IdentifierPath,
+        cfg: &ConfigMap,
+        error_on_failure: bool,
+    ) -> Option<i64> {
+        let path = IdentifierPath::from("segments").join(after).join("end");
+        if let Ok(Some(Value::Integer(end))) = self.symbols.value(&path) {
+            return Some(end);
+        }
+
+        // Wait until the preceding segment has been emitted, unless nothing else can be resolved anymore
+        if !error_on_failure {
+            return None;
+        }
+
+        let span = cfg.value("after").span();
+        if let Some(cycle) = self.segment_cycle(name) {
+            self.push_error(CodegenError::new(
+                span,
+                DetailedCodegenError::SegmentCycle(cycle),
+            ));
+            return None;
+        }
+
+        match self.segments.try_get(after.single()) {
+            // The preceding segment exists, but is empty
+            Some(segment) => Some(segment.options().target_address.as_i64()),
+            None => {
+                self.push_error(CodegenError::new(
+                    span,
+                    DetailedCodegenError::UnknownIdentifier(after.clone()),
+                ));
+                None
+            }
+        }
+    }
+
+    /// Follows the segments that a segment should be placed after, returning them if they lead back to the segment itself
+    fn segment_cycle(&self, name: &Identifier) -> Option<Vec<Identifier>> {
+        let mut chain = vec![name.clone()];
+        let mut current = name;
+        while let Some(next) = self.segment_dependencies.get(current) {
+            if chain.contains(next) {
+                chain.push(next.clone());
+                return match next == name {
+                    true => Some(chain),
+                    false => None,
+                };
+            }
+            chain.push(next.clone());
+            current = next;
+        }
+        None
+    }
+
+    fn emit_emittable<'a>(
+        &mut self,
+        emittable: Emittable<'a>,
+        error_on_failure: bool,
+    ) -> Option<Emittable<'a>> {
+        log::trace!("Processing emittable: {:?}", emittable);
+        let pc = self.segments.try_current().map(|seg| seg.current_pc());
+        let bank = self
+            .segments
+            .try_current()
+            .and_then(|seg| seg.options().bank);
+        match emittable {
+            Emittable::Label(id) => match pc {
+                Some(pc) => {
+                    match self.symbols.register(
+                        &id.data,
+                        Symbol::Label(pc, bank),
+                        Some(&id.span),
+                        false,
+                    ) {
+                        Ok(_) => {
+                            self.generated_values
+                                .insert(id.span, GeneratedValue::Address(pc));
+                            None
+                        }
+                        Err(e) => {
+                            self.push_error(e);
+                            None
+                        }
+                    }
+                }
+                None => Some(Emittable::Label(id)),
+            },
+            Emittable::AnonymousLabel(marker, name) => match pc {
+                Some(pc) => {
+                    let symbol = Symbol::Label(pc, bank);
+                    if let Err(e) = self.symbols.register(&name, symbol, &marker.span, false) {
+                        self.push_error(e);
+                    }
+                    None
+                }
+                None => Some(Emittable::AnonymousLabel(marker, name)),
+            },
+            Emittable::SegmentDefinition(cfg) => {
+                match self.define_segment(&cfg, pc, error_on_failure) {
+                    true => None,
+                    false => {
                         // try again later
                         Some(Emittable::SegmentDefinition(cfg))
                     }
                 }
             }
-            Emittable::Single(provided_pc, token) => {
+            Emittable::RegionDefinition(cfg) => {
+                match self.define_region(&cfg, pc, error_on_failure) {
+                    true => None,
+                    false => {
+                        // try again later
+                        Some(Emittable::RegionDefinition(cfg))
+                    }
+                }
+            }
+            Emittable::Single(provided_pc, token, encoding, position) => {
                 let pc = match provided_pc {
                     Some(pc) => Some(pc),
                     None => pc,
                 };
-                match self.emit_single(&token, pc, error_on_failure) {
+                self.encoding = encoding.cloned();
+                self.anonymous_labels = position;
+                let result = self.emit_single(&token, pc, error_on_failure);
+                self.encoding = None;
+                self.anonymous_labels = AnonymousLabelPosition::default();
+                match result {
                     Ok(result) => match result {
                         EmitResult::TryLater(_, _) | EmitResult::TryLaterNoData => {
-                            Some(Emittable::Single(pc, token))
+                            Some(Emittable::Single(pc, token, encoding, position))
                         }
                         EmitResult::Success(_, _) => None,
                         EmitResult::SuccessNoData => None,
+                        EmitResult::Reserve(_, _) => None,
                     },
                     Err(e) => {
                         self.push_error(e);
@@ -923,6 +1919,10 @@ impl CodegenContext {
                                 .flatten()
                         }
                     }
+                    Err(e) if error_on_failure => {
+                        self.push_error(e);
+                        None
+                    }
                     _ => {
                         log::trace!("result of ifdef undetermined");
                         Some(Emittable::If(expr, if_, else_))
@@ -932,15 +1932,51 @@ impl CodegenContext {
         }
     }
 
-    fn generate_emittables<'a>(&mut self, tokens: &'a [Token]) -> Vec<Emittable<'a>> {
+    fn generate_emittables<'a>(
+        &mut self,
+        tokens: &'a [Token],
+        encoding: Option<&'a Located<Identifier>>,
+    ) -> Vec<Emittable<'a>> {
+        // The encoding is lexically scoped, so it applies until the end of the block it was set in
+        let mut encoding = encoding;
+
+        // Anonymous labels are numbered in the direction they are looked up in, so that references can be resolved by counting
+        let mut position = AnonymousLabelPosition {
+            preceding: 0,
+            following: tokens
+                .iter()
+                .filter(|tok| matches!(tok, Token::AnonymousLabel(marker) if marker.data == '+'))
+                .count(),
+        };
+
         tokens
             .iter()
-            .map(|tok| self.generate_emittables_for_token(tok))
+            .map(|tok| {
+                if let Token::Encoding { encoding: e, .. } = tok {
+                    encoding = Some(e);
+                }
+                if let Token::AnonymousLabel(marker) = tok {
+                    let name = if marker.data == '-' {
+                        position.preceding += 1;
+                        Identifier::anonymous_label('-', position.preceding)
+                    } else {
+                        position.following -= 1;
+                        Identifier::anonymous_label('+', position.following + 1)
+                    };
+                    return vec![Emittable::AnonymousLabel(marker, name)];
+                }
+                self.generate_emittables_for_token(tok, encoding, position)
+            })
             .flatten()
             .collect_vec()
     }
 
-    fn generate_emittables_for_token<'a>(&mut self, token: &'a Token) -> Vec<Emittable<'a>> {
+    fn generate_emittables_for_token<'a>(
+        &mut self,
+        token: &'a Token,
+        encoding: Option<&'a Located<Identifier>>,
+        position: AnonymousLabelPosition,
+    ) -> Vec<Emittable<'a>> {
         match &token {
             Token::Definition { id, value, .. } => {
                 let definition_type = id.data.value();
@@ -952,31 +1988,56 @@ impl CodegenContext {
                     "segment" => {
                         // Perform some sanity checks
                         match require_segment_options_fields(self.tree.clone(), &cfg, &cfg_span) {
-                            Ok(()) => vec![Emittable::SegmentDefinition(cfg)],
+                            Ok(()) => {
+                                if let Some(after) = cfg.try_value_as_identifier_path("after") {
+                                    let name = cfg.value_as_identifier_path("name").single();
+                                    self.segment_dependencies
+                                        .insert(name.clone(), after.single().clone());
+                                }
+                                vec![Emittable::SegmentDefinition(cfg)]
+                            }
+                            Err(e) => {
+                                self.errors.push(CodegenError::Mos(e));
+                                vec![]
+                            }
+                        }
+                    }
+                    "region" => {
+                        match require_region_options_fields(self.tree.clone(), &cfg, cfg_span) {
+                            Ok(()) => vec![Emittable::RegionDefinition(cfg)],
                             Err(e) => {
                                 self.errors.push(CodegenError::Mos(e));
                                 vec![]
                             }
                         }
                     }
-                    _ => panic!("Unknown definition type: {}", id),
+                    _ => {
+                        self.push_error(CodegenError::new(
+                            id.span,
+                            DetailedCodegenError::UnknownDefinitionType(id.data.clone()),
+                        ));
+                        vec![]
+                    }
                 }
             }
             Token::Segment { id, block, .. } => {
                 let block_emittable = match block {
-                    Some(b) => Some(Box::new(self.create_block_emittable(&id.data, &b.inner))),
+                    Some(b) => Some(Box::new(
+                        self.create_block_emittable(&id.data, &b.inner, encoding),
+                    )),
                     None => None,
                 };
                 vec![Emittable::Segment(&id.data, id.span, block_emittable)]
             }
             Token::Braces { block, scope } => {
-                vec![self.create_block_emittable(&scope, &block.inner)]
+                vec![self.create_block_emittable(&scope, &block.inner, encoding)]
             }
             Token::Label { id, block, .. } => {
                 match block {
                     Some(block) => {
                         // The label contains a code block, so also emit the inner data
-                        let braces_emittable = self.create_block_emittable(&id.data, &block.inner);
+                        let braces_emittable =
+                            self.create_block_emittable(&id.data, &block.inner, encoding);
                         vec![Emittable::Label(id), braces_emittable]
                     }
                     None => {
@@ -993,14 +2054,106 @@ impl CodegenContext {
                 else_scope,
                 ..
             } => {
-                let if_ = Some(Box::new(self.create_block_emittable(if_scope, &if_.inner)));
+                let if_ = Some(Box::new(
+                    self.create_block_emittable(if_scope, &if_.inner, encoding),
+                ));
                 let else_ = match else_ {
-                    Some(e) => Some(Box::new(self.create_block_emittable(else_scope, &e.inner))),
+                    Some(e) => Some(Box::new(
+                        self.create_block_emittable(else_scope, &e.inner, encoding),
+                    )),
                     None => None,
                 };
                 vec![Emittable::If(value, if_, else_)]
             }
-            _ => vec![Emittable::Single(None, &token)],
+            _ => vec![Emittable::Single(None, &token, encoding, position)],
+        }
+    }
+
+    /// Resolves a reference to an anonymous label (e.g. `--`) to the name of the label it refers to.
+    ///
+    /// When there are no anonymous labels, a single `-` or `+` refers to the start or end of the enclosing block.
+    /// Any other reference to a label that does not exist resolves to a path that cannot be found.
+    fn anonymous_label_path(&self, path: &IdentifierPath) -> Option<IdentifierPath> {
+        if path.len() != 1 {
+            return None;
+        }
+        let name = path.single().value();
+        let direction = name.chars().next()?;
+        if !matches!(direction, '-' | '+') || name.chars().any(|c| c != direction) {
+            return None;
+        }
+
+        let available = match direction {
+            '-' => self.anonymous_labels.preceding,
+            _ => self.anonymous_labels.following,
+        };
+        let label = match (available + 1).checked_sub(name.len()) {
+            // A single `-` or `+` without any anonymous labels refers to the start or end of the block
+            Some(0) if name.len() == 1 => Identifier::new(direction.to_string()),
+            Some(0) => return Some(path.clone()),
+            Some(index) => Identifier::anonymous_label(direction, index),
+            None => return Some(path.clone()),
+        };
+        Some(label.into())
+    }
+
+    /// Reports every segment that grew beyond its end address
+    fn check_segment_limits(&mut self) {
+        let overflows = self
+            .segments
+            .keys()
+            .into_iter()
+            .sorted_by_key(|name| name.value())
+            .filter_map(|name| {
+                let segment = self.segments.get(name);
+                let (span, overrun) = segment.overflow()?;
+                let end = segment.options().end?;
+                let error = match &segment.options().region {
+                    Some(region) if self.regions[region].end == end => {
+                        DetailedCodegenError::SegmentExceedsRegion(
+                            name.clone(),
+                            region.clone(),
+                            overrun,
+                        )
+                    }
+                    _ => DetailedCodegenError::SegmentOverflow(name.clone(), end, overrun),
+                };
+                Some(CodegenError::new(span, error))
+            })
+            .collect_vec();
+        for error in overflows {
+            self.push_error(error);
+        }
+
+        // Data in I/O space is probably a mistake, unless the segment explicitly targets an I/O region
+        let io_warnings = self
+            .segments
+            .keys()
+            .into_iter()
+            .sorted_by_key(|name| name.value())
+            .filter_map(|name| {
+                let segment = self.segments.get(name);
+                let is_io_region = segment
+                    .options()
+                    .region
+                    .as_ref()
+                    .map(|region| self.regions[region].kind == RegionKind::Io)
+                    .unwrap_or_default();
+                match (segment.io_usage(), is_io_region) {
+                    (Some((span, io)), false) => Some(CodegenError::new(
+                        span,
+                        DetailedCodegenError::SegmentInIoSpace(
+                            name.clone(),
+                            io.start.into(),
+                            (io.end - 1).into(),
+                        ),
+                    )),
+                    _ => None,
+                }
+            })
+            .collect_vec();
+        for warning in io_warnings {
+            self.push_warning(warning);
         }
     }
 
@@ -1008,8 +2161,16 @@ impl CodegenContext {
         // For every segment that we have, register appropriate symbols
         for segment_name in self.segments.keys() {
             let segment = self.segments.get(segment_name);
+            let segments_path: IdentifierPath = "segments".into();
+            if let Some(bank) = segment.options().bank {
+                self.symbols.register(
+                    segments_path.join(segment_name).join("bank"),
+                    Symbol::System(bank as i64),
+                    None,
+                    true,
+                )?;
+            }
             if let Some(target_range) = segment.target_range() {
-                let segments_path: IdentifierPath = "segments".into();
                 self.symbols.register(
                     segments_path.join(segment_name).join("start"),
                     Symbol::System(target_range.start as i64),
@@ -1031,30 +2192,37 @@ impl CodegenContext {
         &mut self,
         scope_name: &'a Identifier,
         ast: &'a [Token],
+        encoding: Option<&'a Located<Identifier>>,
     ) -> Emittable<'a> {
         self.symbols.enter(scope_name.clone());
-        let e = Emittable::Nested(scope_name, self.generate_emittables(ast));
+        let e = Emittable::Nested(scope_name, self.generate_emittables(ast, encoding));
         self.symbols.leave();
         e
     }
 }
 
-#[allow(clippy::unnecessary_wraps)]
-fn is_defined(args: Vec<Option<i64>>) -> CodegenResult<Option<i64>> {
-    // We should have 1 argument and that argument should be set to Some
-    let r = match args.first() {
-        Some(Some(_)) => 1,
-        _ => 0,
-    };
-    Ok(Some(r))
-}
-
-fn codegen_impl(tree: Arc<ParseTree>, options: CodegenOptions) -> CodegenResult<CodegenContext> {
+fn codegen_impl(tree: Arc<ParseTree>, options: CodegenOptions) -> CodegenContext {
     let mut ctx = CodegenContext::new(tree, options);
-    ctx.register_fn("defined", &is_defined);
+    for function in builtin_functions() {
+        ctx.register_fn(function);
+    }
+
+    if let Some(target) = ctx.options.target {
+        if let Err(e) = ctx.register_target(target) {
+            ctx.push_error(e);
+        }
+    }
+
+    let basic_stub = ctx.options.basic_stub.clone();
+    let file_start = ctx.file_start();
+    if let (Some(stub), Some(span)) = (&basic_stub, file_start) {
+        if let Err(e) = ctx.reserve_basic_stub(stub, span) {
+            ctx.push_error(e);
+        }
+    }
 
     let tree = ctx.tree.clone();
-    let mut to_process = ctx.generate_emittables(tree.tokens());
+    let mut to_process = ctx.generate_emittables(tree.tokens(), None);
 
     // Apply passes
 
@@ -1062,6 +2230,7 @@ fn codegen_impl(tree: Arc<ParseTree>, options: CodegenOptions) -> CodegenResult<
     // After the first pass, all labels should be present so any error will be a failure then
     let mut error_on_failure = false;
     let mut num_passes = 0;
+    let mut created_default_segment = false;
 
     #[cfg(test)]
     let max_passes = 50;
@@ -1084,21 +2253,46 @@ fn codegen_impl(tree: Arc<ParseTree>, options: CodegenOptions) -> CodegenResult<
             }
 
             // Is it because there are no segments yet? Then create a default one.
-            if ctx.segments.is_empty() {
+            if !ctx.has_user_segments() {
                 log::trace!("Creating default segment");
-                let options = SegmentOptions {
-                    initial_pc: ctx.options.pc,
-                    ..Default::default()
+                let options = match &basic_stub {
+                    // Place the code directly after the BASIC stub
+                    Some(stub) => SegmentOptions {
+                        initial_pc: stub.end(),
+                        target_address: stub.end(),
+                        io_ranges: ctx.io_ranges(),
+                        ..Default::default()
+                    },
+                    None => SegmentOptions {
+                        initial_pc: ctx.options.pc,
+                        target_address: ctx.options.pc,
+                        io_ranges: ctx.io_ranges(),
+                        ..Default::default()
+                    },
                 };
                 ctx.segments
                     .insert("default", Segment::new("default", options));
+                if let Some(target) = ctx.options.target {
+                    ctx.insert_target_segments(target);
+                }
+                created_default_segment = true;
+
+                if let Some(span) = file_start {
+                    ctx.push_warning(CodegenError::new(
+                        span,
+                        DetailedCodegenError::ImplicitDefaultSegment(),
+                    ));
+                }
             } else {
                 // Emit an error on the next resolve failure
                 error_on_failure = true;
             }
         }
 
-        ctx.after_pass()?;
+        if let Err(e) = ctx.after_pass() {
+            ctx.push_error(e);
+            break;
+        }
 
         to_process = next_to_process;
         num_passes += 1;
@@ -1110,18 +2304,34 @@ fn codegen_impl(tree: Arc<ParseTree>, options: CodegenOptions) -> CodegenResult<
         );
     }
 
+    if let (Some(stub), Some(span)) = (&basic_stub, file_start) {
+        if ctx.errors.is_empty() {
+            if let Err(e) = ctx.emit_basic_stub(stub, span, created_default_segment) {
+                ctx.push_error(e);
+            }
+        }
+    }
+
+    ctx.check_segment_limits();
+
+    ctx
+}
+
+pub fn codegen(tree: Arc<ParseTree>, options: CodegenOptions) -> MosResult<CodegenContext> {
+    let mut ctx = codegen_impl(tree.clone(), options);
     if ctx.errors.is_empty() {
         Ok(ctx)
     } else {
-        Err(CodegenError::Multiple(ctx.errors))
+        let errors = std::mem::take(&mut ctx.errors);
+        Err(to_mos_error(tree, CodegenError::Multiple(errors)))
     }
 }
 
-pub fn codegen(tree: Arc<ParseTree>, options: CodegenOptions) -> MosResult<CodegenContext> {
-    match codegen_impl(tree.clone(), options) {
-        Ok(result) => Ok(result),
-        Err(error) => Err(to_mos_error(tree, error)),
-    }
+/// Performs code generation but, unlike [codegen], always returns the (possibly incomplete) context.
+///
+/// Any errors are available through [CodegenContext::errors]. This is useful when analysing source code that may contain errors.
+pub fn codegen_partial(tree: Arc<ParseTree>, options: CodegenOptions) -> CodegenContext {
+    codegen_impl(tree, options)
 }
 
 #[cfg(test)]
@@ -1197,20 +2407,91 @@ mod tests {
     }
 
     #[test]
-    fn overflowing_expressions() -> TestResult {
+    fn bitwise_expressions() -> TestResult {
+        let ctx = test_codegen(
+            r"
+            lda #$f3 & $7f
+            lda #$f0 | $0f
+            lda #17 % 5
+            lda #~$f0 & $ff
+            lda #1 && 2
+            lda #0 || 2
+            ",
+        )?;
+        assert_eq!(
+            ctx.segments().current().range_data(),
+            vec![0xa9, 0x73, 0xa9, 0xff, 0xa9, 2, 0xa9, 0x0f, 0xa9, 1, 0xa9, 1]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn operator_precedence() -> TestResult {
         let ctx = test_codegen(
             r"
-        lda #1-2
-        lda $ffff + 3
-        ",
+            lda #2 + 3 * 4
+            lda #[2 + 3] * 4
+            lda #10 - 4 - 3
+            lda #64 / 4 / 2
+            lda #1 + 2 == 3 + 4
+            lda #1 || 0 && 0
+            lda #1 << 2 + 1
+            lda #6 & 3 == 2
+            lda #$f0 | $0f ^ $ff & $0f
+            ",
         )?;
         assert_eq!(
             ctx.segments().current().range_data(),
-            vec![0xa9, 0xff, 0xad, 2, 0]
+            vec![
+                0xa9, 14, 0xa9, 20, 0xa9, 3, 0xa9, 8, 0xa9, 0, 0xa9, 1, 0xa9, 8, 0xa9, 0, 0xa9,
+                0xf0
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn overflowing_expressions() -> TestResult {
+        let ctx = test_codegen("lda #1-2\nlda $ffff - 3\n.byte -128, 255\n.word -32768, 65535")?;
+        assert_eq!(
+            ctx.segments().current().range_data(),
+            vec![0xa9, 0xff, 0xad, 0xfc, 0xff, 0x80, 0xff, 0x00, 0x80, 0xff, 0xff]
+        );
+
+        let err = test_codegen("lda $ffff + 3").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:1: error: operand size mismatch"
+        );
+        let err = test_codegen(".byte 256").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:7: error: value 256 is out of range: expected a value between -128 and 255"
+        );
+        let err = test_codegen(".word -32769").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:7: error: value -32769 is out of range: expected a value between -32768 and 65535"
         );
         Ok(())
     }
 
+    #[test]
+    fn checked_arithmetic() {
+        let err = test_codegen("lda #1 / [2 - 2]").err().unwrap();
+        assert_eq!(err.to_string(), "test.asm:1:8: error: division by zero");
+        let err = test_codegen("lda #1 % 0").err().unwrap();
+        assert_eq!(err.to_string(), "test.asm:1:8: error: division by zero");
+        let err = test_codegen("lda #1 << 64").err().unwrap();
+        assert_eq!(err.to_string(), "test.asm:1:8: error: arithmetic overflow");
+        let err = test_codegen("lda #1 >> -1").err().unwrap();
+        assert_eq!(err.to_string(), "test.asm:1:8: error: arithmetic overflow");
+        let err = test_codegen(".const big = $7fffffffffffffff\nlda #big + 1")
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "test.asm:2:10: error: arithmetic overflow");
+    }
+
     #[test]
     fn can_use_variables() -> TestResult {
         let ctx = test_codegen(".var foo=49152\nlda #>foo")?;
@@ -1246,41 +2527,410 @@ mod tests {
     }
 
     #[test]
-    fn can_use_named_scopes() -> TestResult {
+    fn can_use_anonymous_labels() -> TestResult {
         let ctx = test_codegen(
             r"
-                foo: {
-                    .var a = 1
-                    nop
-                    bar: { .var a = 2 }
-                }
-                lda #foo.a
-                lda #foo.bar.a
+                - ldx #0
+                - dex
+                  bne -
+                  bne --
+                  beq +
+                  beq ++
+                  nop
+                + nop
+                + rts
             ",
         )?;
         assert_eq!(
             ctx.segments().current().range_data(),
-            vec![0xea, 0xa9, 1, 0xa9, 2]
+            vec![
+                0xa2, 0x00, 0xca, 0xd0, 0xfd, 0xd0, 0xf9, 0xf0, 0x03, 0xf0, 0x02, 0xea, 0xea, 0x60
+            ]
         );
         Ok(())
     }
 
     #[test]
-    fn can_define_segments() -> TestResult {
-        let ctx =
-            test_codegen(".define segment {\nname = foo\nstart = 49152\nwrite = false\n}\nnop")?;
-        assert_eq!(ctx.segments().get("foo").range(), &Some(0xc000..0xc001));
-        assert_eq!(ctx.segments().get("foo").range_data(), vec![0xea]);
-        assert_eq!(ctx.segments().get("foo").options().write, false);
-        assert_eq!(ctx.segments().try_get("default").is_none(), true);
+    fn anonymous_labels_fall_back_to_block_boundaries() -> TestResult {
+        let ctx = test_codegen(
+            r"
+                {
+                    nop
+                    bne -
+                    beq +
+                }
+                nop
+            ",
+        )?;
+        assert_eq!(
+            ctx.segments().current().range_data(),
+            vec![0xea, 0xd0, 0xfd, 0xf0, 0x00, 0xea]
+        );
         Ok(())
     }
 
     #[test]
-    fn can_use_segments() -> TestResult {
-        let ctx = test_codegen(
-            r"
-                .define segment { name = a start = $1000 }
+    fn anonymous_labels_do_not_fall_back_when_skipping() {
+        let err = test_codegen(
+            "foo: {
+  - nop
+  nop
+  bne --
+}",
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:4:7: error: unknown identifier: --"
+        );
+
+        let err = test_codegen(
+            "foo: {
+  beq ++
+  + nop
+}",
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:2:7: error: unknown identifier: ++"
+        );
+    }
+
+    #[test]
+    fn cannot_skip_past_anonymous_labels() {
+        let err = test_codegen("- nop\njmp --").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:2:5: error: unknown identifier: --"
+        );
+    }
+
+    #[test]
+    fn expressions_can_continue_on_next_line() -> TestResult {
+        let ctx = test_codegen(".byte 1\n    + 2\n.byte 5\n    - 1\n- nop")?;
+        assert_eq!(
+            ctx.segments().current().range_data(),
+            vec![0x03, 0x04, 0xea]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn can_use_named_scopes() -> TestResult {
+        let ctx = test_codegen(
+            r"
+                foo: {
+                    .var a = 1
+                    nop
+                    bar: { .var a = 2 }
+                }
+                lda #foo.a
+                lda #foo.bar.a
+            ",
+        )?;
+        assert_eq!(
+            ctx.segments().current().range_data(),
+            vec![0xea, 0xa9, 1, 0xa9, 2]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn can_define_segments() -> TestResult {
+        let ctx =
+            test_codegen(".define segment {\nname = foo\nstart = 49152\nwrite = false\n}\nnop")?;
+        assert_eq!(ctx.segments().get("foo").range(), &Some(0xc000..0xc001));
+        assert_eq!(ctx.segments().get("foo").range_data(), vec![0xea]);
+        assert_eq!(ctx.segments().get("foo").options().write, false);
+        assert_eq!(ctx.segments().try_get("default").is_none(), true);
+        Ok(())
+    }
+
+    #[test]
+    fn segments_cannot_exceed_their_end() {
+        let err = test_codegen(
+            ".define segment {\nname = a\nstart = $1000\nend = $1002\n}\nnop\nnop\nlda #1",
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:8:1: error: segment 'a' exceeds its end address of $1002 by 2 byte(s)"
+        );
+    }
+
+    #[test]
+    fn segments_cannot_exceed_their_size() -> TestResult {
+        let source = ".define segment {\nname = a\nstart = $1000\nsize = 3\n}\n";
+        let ctx = test_codegen(&format!("{}.byte 1, 2, 3", source))?;
+        assert_eq!(ctx.segments().get("a").options().end, Some(0x1003.into()));
+
+        let err = test_codegen(&format!("{}.res 10", source)).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:6:6: error: segment 'a' exceeds its end address of $1003 by 7 byte(s)"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn segments_can_be_placed_after_other_segments() -> TestResult {
+        let ctx = test_codegen(
+            r"
+                .define segment { name = b after = a align = 256 fill = $ff }
+                .define segment { name = a start = $1000 }
+                .define segment { name = c after = b }
+                nop
+                .segment b { rol }
+                .segment c { asl }
+                ",
+        )?;
+        assert_eq!(ctx.segments().get("a").range(), &Some(0x1000..0x1001));
+        assert_eq!(ctx.segments().get("b").range(), &Some(0x1100..0x1101));
+        assert_eq!(ctx.segments().get("c").range(), &Some(0x1101..0x1102));
+        assert_eq!(
+            ctx.segments().get("b").options().padding,
+            Some(Padding {
+                range: 0x1001..0x1100,
+                value: 0xff
+            })
+        );
+        assert_eq!(ctx.segments().get("c").options().padding, None);
+        Ok(())
+    }
+
+    #[test]
+    fn segments_cannot_be_placed_after_each_other() {
+        let err = test_codegen(
+            r"
+                .define segment { name = a after = b }
+                .define segment { name = b after = a }
+                ",
+        )
+        .err()
+        .unwrap();
+        assert!(err
+            .to_string()
+            .contains("segments depend on each other: a -> b -> a"));
+    }
+
+    #[test]
+    fn segments_can_be_placed_in_regions() -> TestResult {
+        let source = r"
+            .define region { name = main start = $0801 end = $0804 kind = ram }
+            .define segment { name = a start = $0801 region = main }
+            ";
+        let ctx = test_codegen(&format!("{}.byte 1, 2, 3", source))?;
+        assert_eq!(ctx.segments().get("a").options().end, Some(0x0804.into()));
+
+        let err = test_codegen(&format!("{}.byte 1, 2, 3, 4", source))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:4:19: error: segment 'a' exceeds region 'main' by 1 byte(s)"
+        );
+
+        let err = test_codegen(
+            r"
+            .define region { name = main start = $0801 end = $0804 kind = ram }
+            .define segment { name = a start = $1000 region = main }
+            ",
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:3:63: error: segment 'a' does not start within region 'main' ($0801 - $0804)"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn segments_in_io_space_generate_warnings() -> TestResult {
+        let ctx = test_codegen(
+            r"
+            .define segment { name = a start = $cfff }
+            .byte 1, 2
+            ",
+        )?;
+        assert_eq!(
+            ctx.warnings().iter().map(|w| w.to_string()).collect_vec(),
+            vec!["segment 'a' places data in I/O space ($D000 - $DFFF)"]
+        );
+
+        let ctx = test_codegen(
+            r"
+            .define region { name = vic start = $d000 end = $d400 kind = io }
+            .define segment { name = a start = $d000 region = vic }
+            .byte 1, 2
+            ",
+        )?;
+        assert!(ctx.warnings().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn regions_must_have_a_known_kind() {
+        let err = test_codegen(".define region { name = a start = 0 end = 1 kind = flash }")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:52: error: unknown region kind 'flash', expected one of: ram, rom, io"
+        );
+    }
+
+    #[test]
+    fn regions_cannot_be_redefined() {
+        let err = test_codegen(
+            ".define region { name = a start = 0 end = 1 kind = ram }\n.define region { name = a start = 2 end = 3 kind = ram }",
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:2:25: error: region 'a' is already defined"
+        );
+
+        // Regions of the target can't be redefined either
+        let ast = parse_or_err(
+            Path::new("test.asm"),
+            ".define region { name = ram start = $0801 end = $1000 kind = ram }",
+        )
+        .unwrap();
+        let options = CodegenOptions {
+            target: Some(Target::C64),
+            ..Default::default()
+        };
+        let err = codegen(ast, options).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:25: error: region 'ram' is already defined"
+        );
+    }
+
+    #[test]
+    fn regions_must_end_after_their_start() {
+        let err = test_codegen(".define region { name = a start = $3000 end = $1000 kind = ram }")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:47: error: region 'a' has an end address ($1000) that is not beyond its start address ($3000)"
+        );
+    }
+
+    #[test]
+    fn regions_cannot_overlap() {
+        let err = test_codegen(
+            ".define region { name = a start = $1000 end = $2000 kind = ram }\n.define region { name = b start = $1fff end = $3000 kind = io }",
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:2:25: error: region 'b' ($1FFF - $3000) overlaps with region 'a' ($1000 - $2000)"
+        );
+
+        // Adjacent regions don't overlap
+        assert!(test_codegen(
+            ".define region { name = a start = $1000 end = $2000 kind = ram }\n.define region { name = b start = $2000 end = $3000 kind = io }",
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn unknown_definitions_are_errors() {
+        let err = test_codegen(".define foo { name = a }").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:9: error: unknown definition type 'foo', expected one of: segment, region"
+        );
+    }
+
+    #[test]
+    fn segments_can_be_placed_in_banks() -> TestResult {
+        let ctx = test_codegen(
+            r"
+            .define segment { name = a start = $8000 bank = 0 }
+            .define segment { name = b start = $8000 bank = 1 }
+            .segment a { lda #segments.b.bank }
+            .segment b { lda #segments.a.bank }
+            ",
+        )?;
+        assert_eq!(ctx.segments().get("a").options().bank, Some(0));
+        assert_eq!(ctx.segments().get("a").range_data(), vec![0xa9, 0x01]);
+        assert_eq!(ctx.segments().get("b").range_data(), vec![0xa9, 0x00]);
+
+        let err = test_codegen(".define segment { name = a start = $8000 bank = 256 }")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:49: error: value 256 is out of range: expected a value between 0 and 255"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn labels_carry_the_bank_of_their_segment() -> TestResult {
+        let ctx = test_codegen(
+            r"
+            .define segment { name = code start = $2000 }
+            .define segment { name = a start = $8000 bank = 1 }
+            .segment code { main: nop }
+            .segment a { init: nop }
+            ",
+        )?;
+        let symbols = ctx.symbol_table();
+        assert_eq!(
+            symbols.lookup("code.main", false).unwrap(),
+            Some(&Symbol::Label(0x2000.into(), None))
+        );
+        assert_eq!(
+            symbols.lookup("a.init", false).unwrap(),
+            Some(&Symbol::Label(0x8000.into(), Some(1)))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn segments_cannot_have_both_start_and_after() {
+        let err = test_codegen(".define segment { name = a start = $1000 after = b }")
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("test.asm:1:17: error: conflicting fields"));
+    }
+
+    #[test]
+    fn segments_can_be_aligned() -> TestResult {
+        let ctx = test_codegen(".define segment { name = a start = $1001 align = $40 size = 2 }")?;
+        let options = ctx.segments().get("a").options();
+        assert_eq!(options.target_address, 0x1040.into());
+        assert_eq!(options.end, Some(0x1042.into()));
+        assert_eq!(options.padding, None);
+
+        let err = test_codegen(".define segment { name = a start = $1001 align = 0 }")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:50: error: value 0 is out of range: expected a value between 1 and 65536"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn can_use_segments() -> TestResult {
+        let ctx = test_codegen(
+            r"
+                .define segment { name = a start = $1000 }
                 .define segment { name = b start = $2000 }
                 nop
                 .segment b
@@ -1351,7 +3001,7 @@ mod tests {
         );
         assert_eq!(
             err.to_string()
-                .contains("test.asm:1:17: error: required field: start"),
+                .contains("test.asm:1:17: error: required field: start or after"),
             true
         );
     }
@@ -1381,6 +3031,219 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn can_use_decimals() -> TestResult {
+        let ctx =
+            test_codegen(".const half = 0.5\n.byte 1.5, 10 * half, 3 / 2.0, -half\n.byte 3 / 2")?;
+        assert_eq!(
+            ctx.segments().current().range_data(),
+            vec![2, 5, 2, 0xff, 1]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn can_generate_basic_stub() -> TestResult {
+        let stub = BasicStub {
+            start: 0x0801.into(),
+            entry: None,
+        };
+        let ctx = test_codegen_with_stub("nop", stub.clone())?;
+        assert_eq!(ctx.segments().get("basic").range(), &Some(0x0801..0x080e));
+        assert_eq!(
+            ctx.segments().get("basic").range_data(),
+            stub.to_bytes(2062)
+        );
+        assert_eq!(ctx.segments().get("default").range(), &Some(0x080e..0x080f));
+        assert!(ctx.warnings().len() == 1);
+
+        let stub = BasicStub {
+            start: 0x1001.into(),
+            entry: Some("main".into()),
+        };
+        let ctx = test_codegen_with_stub(
+            ".define segment { name = code start = $2000 }\nnop\nmain: rts",
+            stub.clone(),
+        )?;
+        assert_eq!(
+            ctx.segments().get("basic").range_data(),
+            stub.to_bytes(0x2001)
+        );
+        assert!(ctx.segments().try_get("default").is_none());
+
+        let err = test_codegen_with_stub("nop", stub).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:1: error: unknown identifier: main"
+        );
+
+        // When segments are defined, the code does not follow the stub so an entry is required
+        let stub = BasicStub {
+            start: 0x0801.into(),
+            entry: None,
+        };
+        let err =
+            test_codegen_with_stub(".define segment { name = code start = $1000 }\nnop", stub)
+                .err()
+                .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:1: error: segments are defined, so 'basic_stub_entry' is needed to know where the BASIC stub should jump to"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn segments_cannot_use_the_name_of_the_basic_stub_segment() -> TestResult {
+        let stub = BasicStub {
+            start: 0x0801.into(),
+            entry: Some("basic.main".into()),
+        };
+        let err = test_codegen_with_stub(
+            ".define segment { name = basic start = $0801 }
+.segment basic { main: nop }",
+            stub,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:26: error: segment name 'basic' is reserved for the BASIC stub"
+        );
+
+        // Without a BASIC stub the name is available
+        let ctx = test_codegen(
+            ".define segment { name = basic start = $0801 }
+.segment basic { nop }",
+        )?;
+        assert_eq!(ctx.segments().get("basic").range_data(), vec![0xea]);
+        Ok(())
+    }
+
+    #[test]
+    fn targets_provide_constants_and_regions() -> TestResult {
+        let source = r"
+            .define segment { name = code start = $0801 region = ram }
+            lda #1
+            sta VIC.border
+            ";
+        let ast = parse_or_err(Path::new("test.asm"), source)?;
+        let options = CodegenOptions {
+            target: Some(Target::C64),
+            ..Default::default()
+        };
+        let ctx = codegen(ast, options)?;
+        assert_eq!(
+            ctx.segments().get("code").range_data(),
+            vec![0xa9, 0x01, 0x8d, 0x20, 0xd0]
+        );
+        assert_eq!(
+            ctx.segments().get("code").options().end,
+            Some(0xa000.into())
+        );
+
+        // The I/O space depends on the target
+        let source = ".define segment { name = code start = $d000 }\nnop\n.define segment { name = ppu start = $2000 }\n.segment ppu { nop }";
+        let ast = parse_or_err(Path::new("test.asm"), source)?;
+        let options = CodegenOptions {
+            target: Some(Target::Nes),
+            ..Default::default()
+        };
+        let ctx = codegen(ast, options)?;
+        assert_eq!(
+            ctx.warnings().iter().map(|w| w.to_string()).collect_vec(),
+            vec!["segment 'ppu' places data in I/O space ($2000 - $2007)"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn targets_predefine_segments_when_none_are_defined() -> TestResult {
+        let source = r"
+            .segment zeropage { foo: .byte 0 }
+            main: lda zeropage.foo
+            .segment vectors { .word 0, main, 0 }
+            ";
+        let ast = parse_or_err(Path::new("test.asm"), source)?;
+        let options = CodegenOptions {
+            pc: Target::Nes.default_pc(),
+            target: Some(Target::Nes),
+            ..Default::default()
+        };
+        let ctx = codegen(ast, options)?;
+        assert_eq!(ctx.segments().get("default").range_data(), vec![0xa5, 0x00]);
+        assert!(!ctx.segments().get("zeropage").options().write);
+        assert_eq!(
+            ctx.segments().get("vectors").range_data(),
+            vec![0x00, 0x00, 0x00, 0x80, 0x00, 0x00]
+        );
+
+        // Defining your own segments means that the target does not predefine any
+        let source = ".define segment { name = code start = $8000 }\nnop";
+        let ast = parse_or_err(Path::new("test.asm"), source)?;
+        let options = CodegenOptions {
+            target: Some(Target::Nes),
+            ..Default::default()
+        };
+        let ctx = codegen(ast, options)?;
+        assert!(ctx.segments().try_get("vectors").is_none());
+        Ok(())
+    }
+
+    fn test_codegen_with_stub(code: &str, stub: BasicStub) -> MosResult<CodegenContext> {
+        let ast = parse_or_err(Path::new("test.asm"), code)?;
+        let options = CodegenOptions {
+            basic_stub: Some(stub),
+            ..Default::default()
+        };
+        codegen(ast, options)
+    }
+
+    #[test]
+    fn can_configure_rounding() -> TestResult {
+        let ast = parse_or_err(&Path::new("test.asm"), ".byte 1.7, -1.2")?;
+        let options = CodegenOptions {
+            rounding: Rounding::Truncate,
+            ..Default::default()
+        };
+        let ctx = codegen(ast, options)?;
+        assert_eq!(ctx.segments().current().range_data(), vec![1, 0xff]);
+        Ok(())
+    }
+
+    #[test]
+    fn bitwise_operators_round_decimals() -> TestResult {
+        let ctx = test_codegen(".const foo = 258.2\n.byte 2.6 | 1, 4.4 & 4, <foo")?;
+        assert_eq!(ctx.segments().current().range_data(), vec![3, 4, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn can_query_generated_values() -> TestResult {
+        let ctx = test_codegen(".const foo=49152\nmy_label: lda #>foo")?;
+        let tokens = ctx.tree.tokens();
+        let value = |token: &Token| match token {
+            Token::VariableDefinition { id, .. } | Token::Label { id, .. } => {
+                ctx.generated_value(&id.span).cloned()
+            }
+            Token::Instruction(i) => ctx.generated_value(&i.mnemonic.span).cloned(),
+            _ => None,
+        };
+        assert_eq!(
+            value(&tokens[0]),
+            Some(GeneratedValue::Value(Value::Integer(49152)))
+        );
+        assert_eq!(
+            value(&tokens[1]),
+            Some(GeneratedValue::Address(0xc000.into()))
+        );
+        assert_eq!(
+            value(&tokens[2]),
+            Some(GeneratedValue::Bytes(0xc000.into(), vec![0xa9, 0xc0]))
+        );
+        Ok(())
+    }
+
     #[test]
     fn cannot_redefine_constants() {
         let err = test_codegen(".const foo=49152\n.const foo=foo + 5")
@@ -1417,6 +3280,15 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn functions_require_correct_arity() {
+        let err = test_codegen(".if defined(foo, bar) { nop }").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:5: error: function 'defined' expects 1 argument(s), but 2 were provided"
+        );
+    }
+
     #[test]
     fn ifndef() -> TestResult {
         let ctx =
@@ -1569,6 +3441,113 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn can_store_sized_data() -> TestResult {
+        let ctx = test_codegen(".long $123456\n.dbyte $1234\n.bword $5678")?;
+        assert_eq!(
+            ctx.segments().current().range_data(),
+            vec![0x56, 0x34, 0x12, 0x12, 0x34, 0x56, 0x78]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn can_store_split_tables() -> TestResult {
+        let ctx = test_codegen(".lohi $1234, foo\n.hilo $1234, foo\nfoo: nop")?;
+        assert_eq!(
+            ctx.segments().current().range_data(),
+            vec![0x34, 0x08, 0x12, 0xc0, 0x12, 0xc0, 0x34, 0x08, 0xea]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn can_fill_data() -> TestResult {
+        let ctx = test_codegen(".fill 3, foo\n.fill 0, 1\n.const foo = $ff")?;
+        assert_eq!(
+            ctx.segments().current().range_data(),
+            vec![0xff, 0xff, 0xff]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn can_reserve_data() -> TestResult {
+        let ctx = test_codegen("lda foo\n.res 2\nfoo: nop")?;
+        assert_eq!(
+            ctx.segments().current().range_data(),
+            vec![0xad, 0x05, 0xc0, 0, 0, 0xea]
+        );
+
+        let ctx = test_codegen(".fill 2, 1\n* = $c000\n.res 2\n.res 2")?;
+        assert_eq!(ctx.segments().current().range_data(), vec![1, 1, 0, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn cannot_fill_invalid_counts() {
+        let err = test_codegen(".fill -1, 0").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:7: error: value -1 is out of range: expected a value between 0 and 65536"
+        );
+    }
+
+    #[test]
+    fn can_store_text() -> TestResult {
+        let ctx = test_codegen(".text \"aB\", 0, 'c'\n.text petscii_upper \"aB\\n\"")?;
+        assert_eq!(
+            ctx.segments().current().range_data(),
+            vec![0x61, 0x42, 0, 0x63, 0x41, 0x42, 0x0d]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn text_encodings_are_lexically_scoped() -> TestResult {
+        let ctx = test_codegen(
+            ".encoding screen_upper\n{\n.encoding petscii_lower\nlda #'A'\n}\nlda #'A'\n.text ascii \"A\"",
+        )?;
+        assert_eq!(
+            ctx.segments().current().range_data(),
+            vec![0xa9, 0xc1, 0xa9, 0x01, 0x41]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn can_use_charmaps() -> TestResult {
+        let ctx = test_codegen(
+            ".text digits \"10\"\n.charmap digits \"0123\" = base\n.charmap digits \"-\" = 0\n.const base = $30\n.encoding digits\n.byte '-'",
+        )?;
+        assert_eq!(ctx.segments().current().range_data(), vec![0x31, 0x30, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn text_errors() {
+        let err = test_codegen(".text foo \"a\"").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:7: error: unknown encoding: foo"
+        );
+        let err = test_codegen(".text screen_upper \"\\n\"").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:20: error: character '\\n' cannot be represented in encoding 'screen_upper'"
+        );
+        let err = test_codegen("lda #\"a\"").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:6: error: expected a number, but found a string"
+        );
+        let err = test_codegen(".text 256").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "test.asm:1:7: error: value 256 is out of range: expected a value between -128 and 255"
+        );
+    }
+
     #[test]
     fn can_store_current_pc_as_data() -> TestResult {
         let ctx = test_codegen(".word *\n.word foo - *\nfoo: nop")?;
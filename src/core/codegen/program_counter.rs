@@ -1,7 +1,7 @@
 use derive_more::{Add, Display, From, Into, Sub, UpperHex};
 
 /// A simple newtype that wraps a program counter
-#[derive(Debug, PartialEq, Clone, Copy, From, Add, Sub, Display, Into, UpperHex)]
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy, From, Add, Sub, Display, Into, UpperHex)]
 pub struct ProgramCounter(usize);
 
 impl ProgramCounter {
@@ -0,0 +1,65 @@
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use codemap::Span;
+
+use crate::core::codegen::ProgramCounter;
+use crate::core::parser::{ConfigMap, ConfigMapValidatorBuilder, ParseTree};
+use crate::errors::{MosError, MosResult};
+
+/// The target addresses that contain the I/O registers, if no target machine has been configured
+pub const IO_RANGE: Range<usize> = 0xd000..0xe000;
+
+pub fn require_region_options_fields(
+    tree: Arc<ParseTree>,
+    cfg: &ConfigMap,
+    span: &Span,
+) -> MosResult<()> {
+    let errors = ConfigMapValidatorBuilder::default()
+        .require_single_value("name")
+        .require("start")
+        .require("end")
+        .require_single_value("kind")
+        .validate(cfg, span);
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(MosError::Multiple(
+            errors
+                .into_iter()
+                .map(|e| e.into_mos_error(tree.clone()))
+                .collect(),
+        ))
+    }
+}
+
+/// A named range of target addresses that segments can be placed in
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    pub start: ProgramCounter,
+    /// The first target address that is not part of the region anymore
+    pub end: ProgramCounter,
+    pub kind: RegionKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegionKind {
+    Ram,
+    Rom,
+    Io,
+}
+
+impl FromStr for RegionKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ram" => Ok(RegionKind::Ram),
+            "rom" => Ok(RegionKind::Rom),
+            "io" => Ok(RegionKind::Io),
+            _ => Err(()),
+        }
+    }
+}
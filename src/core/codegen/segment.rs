@@ -1,6 +1,8 @@
 use std::ops::Range;
 
-use crate::core::codegen::{CodegenError, CodegenResult, DetailedCodegenError, ProgramCounter};
+use crate::core::codegen::{
+    CodegenError, CodegenResult, DetailedCodegenError, ProgramCounter, IO_RANGE,
+};
 use crate::core::parser::{ConfigMap, ConfigMapValidatorBuilder, Identifier, ParseTree};
 use crate::errors::{MosError, MosResult};
 use codemap::Span;
@@ -13,9 +15,16 @@ pub fn require_segment_options_fields(
 ) -> MosResult<()> {
     let errors = ConfigMapValidatorBuilder::default()
         .require_single_value("name")
-        .require("start")
+        .require_one_of(&["start", "after"])
+        .allowed_single_value("after")
         .allowed("pc")
         .allowed("write")
+        .allowed("end")
+        .allowed("size")
+        .allowed("align")
+        .allowed("fill")
+        .allowed_single_value("region")
+        .allowed("bank")
         .validate(cfg, span);
 
     if errors.is_empty() {
@@ -36,12 +45,33 @@ pub struct Segment {
     range: Option<Range<usize>>,
     pc: usize,
     options: SegmentOptions,
+    /// The location of the first data that did not fit before the end of the segment
+    overflow: Option<Span>,
+    /// The location of the first data that was placed in I/O space, and the I/O range it was placed in
+    io_usage: Option<(Span, Range<usize>)>,
 }
 
 pub struct SegmentOptions {
     pub initial_pc: ProgramCounter,
     pub write: bool,
     pub target_address: ProgramCounter,
+    /// The first target address that may not be used by the segment anymore
+    pub end: Option<ProgramCounter>,
+    /// Bytes to emit in front of the segment, e.g. to fill the gap that is caused by aligning it
+    pub padding: Option<Padding>,
+    /// The region the segment should stay within
+    pub region: Option<Identifier>,
+    /// The bank the segment is placed in, allowing it to share its target addresses with segments in other banks
+    pub bank: Option<u8>,
+    /// The target addresses that contain I/O registers
+    pub io_ranges: Vec<Range<usize>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Padding {
+    /// The target addresses to fill
+    pub range: Range<usize>,
+    pub value: u8,
 }
 
 impl Default for SegmentOptions {
@@ -50,6 +80,11 @@ impl Default for SegmentOptions {
             initial_pc: 0x2000.into(),
             write: true,
             target_address: 0x2000.into(),
+            end: None,
+            padding: None,
+            region: None,
+            bank: None,
+            io_ranges: vec![IO_RANGE],
         }
     }
 }
@@ -62,6 +97,8 @@ impl Segment {
             range: None,
             pc: options.initial_pc.into(),
             options,
+            overflow: None,
+            io_usage: None,
         }
     }
 
@@ -74,13 +111,36 @@ impl Segment {
     }
 
     pub(crate) fn set(&mut self, span: &Span, bytes: &[u8]) -> CodegenResult<ProgramCounter> {
-        if (self.pc + bytes.len() - 1) > 0xffff {
+        let index = self.pc;
+        let pc = self.reserve(span, bytes.len())?;
+        self.data[index..index + bytes.len()].copy_from_slice(bytes);
+        Ok(pc)
+    }
+
+    /// Moves the program counter past a number of bytes that belong to the segment, without writing to them
+    pub(crate) fn reserve(&mut self, span: &Span, length: usize) -> CodegenResult<ProgramCounter> {
+        if length == 0 {
+            return Ok(self.pc.into());
+        }
+
+        if (self.pc + length - 1) > 0xffff {
             return Err(CodegenError::new(
                 *span,
                 DetailedCodegenError::SegmentOutOfRange(self.name.clone(), 0xffff.into()),
             ));
         }
 
+        let target_start = self.pc as i64 + self.target_offset();
+        let target_end = target_start + length as i64;
+        if self.io_usage.is_none() {
+            self.io_usage = self
+                .options
+                .io_ranges
+                .iter()
+                .find(|io| target_start < io.end as i64 && target_end > io.start as i64)
+                .map(|io| (*span, io.clone()));
+        }
+
         if self.range.is_none() {
             self.range = Some(self.pc..self.pc);
         }
@@ -91,22 +151,40 @@ impl Segment {
             range.start = self.pc;
         }
 
-        let index = self.pc;
-        let length = bytes.len();
-
-        for (offset, byte) in bytes.iter().enumerate() {
-            self.data[index + offset] = *byte;
-        }
-
         self.pc += length;
 
         if self.pc > range.end {
             range.end = self.pc;
         }
 
+        if let Some(end) = self.options.end {
+            let target_pc = self.pc as i64 + self.target_offset();
+            if target_pc > end.as_i64() && self.overflow.is_none() {
+                self.overflow = Some(*span);
+            }
+        }
+
         Ok(self.pc.into())
     }
 
+    /// The location of the first data that was placed in I/O space, if any
+    pub(crate) fn io_usage(&self) -> Option<(Span, Range<usize>)> {
+        self.io_usage.clone()
+    }
+
+    /// If the segment grew beyond its end address, returns where that happened and by how many bytes it did
+    pub(crate) fn overflow(&self) -> Option<(Span, usize)> {
+        let span = self.overflow?;
+        let end = self.options.end?.as_i64();
+        let target_end = self.target_range()?.end as i64;
+        Some((span, (target_end - end).max(0) as usize))
+    }
+
+    /// The difference between the target address and the program counter
+    fn target_offset(&self) -> i64 {
+        self.options.target_address.as_i64() - self.options.initial_pc.as_i64()
+    }
+
     #[cfg(test)]
     pub(crate) fn data<R: Into<Range<usize>>>(&self, range: R) -> &[u8] {
         let range = range.into();
@@ -122,7 +200,7 @@ impl Segment {
 
     pub(crate) fn target_range(&self) -> Option<Range<usize>> {
         self.range.as_ref().map(|range| {
-            let offset = self.options.target_address.as_i64() - self.options.initial_pc.as_i64();
+            let offset = self.target_offset();
             Range {
                 start: (range.start as i64 + offset) as usize,
                 end: (range.end as i64 + offset) as usize,
@@ -215,6 +293,27 @@ mod tests {
         assert_eq!(err.to_string(), "segment 'a' is out of range: beyond $FFFF");
     }
 
+    #[test]
+    fn can_detect_overflow() -> CodegenResult<()> {
+        let mut seg = Segment::new(
+            "a",
+            SegmentOptions {
+                initial_pc: 0xc000.into(),
+                target_address: 0x1000.into(),
+                end: Some(0x1004.into()),
+                ..Default::default()
+            },
+        );
+
+        seg.set(&loc(), &[1, 2, 3, 4])?;
+        assert_eq!(seg.overflow(), None);
+        seg.set(&loc(), &[5, 6])?;
+        seg.reserve(&loc(), 1)?;
+        assert_eq!(seg.overflow(), Some((loc(), 3)));
+
+        Ok(())
+    }
+
     fn loc() -> Span {
         let mut codemap = CodeMap::new();
         let f1 = codemap.add_file("test1.rs".to_string(), "abcd\nefghij\nqwerty".to_string());
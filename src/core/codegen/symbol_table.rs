@@ -1,16 +1,29 @@
-use crate::core::codegen::{CodegenError, CodegenResult, DetailedCodegenError, ProgramCounter};
+use crate::core::codegen::{
+    CodegenError, CodegenResult, DetailedCodegenError, ProgramCounter, Value,
+};
 use crate::core::parser::{Identifier, IdentifierPath};
 use codemap::Span;
 use std::collections::HashMap;
 
 #[derive(Debug, PartialEq)]
 pub enum Symbol {
-    Label(ProgramCounter),
-    Variable(i64),
-    Constant(i64),
+    /// A label, along with the bank of the segment it was defined in (if any)
+    Label(ProgramCounter, Option<u8>),
+    Variable(Value),
+    Constant(Value),
     System(i64),
 }
 
+impl Symbol {
+    pub fn value(&self) -> Value {
+        match self {
+            Symbol::Label(pc, _) => Value::Integer(pc.as_i64()),
+            Symbol::Variable(val) | Symbol::Constant(val) => *val,
+            Symbol::System(val) => Value::Integer(*val),
+        }
+    }
+}
+
 pub struct SymbolTable {
     symbols: HashMap<IdentifierPath, Symbol>,
     current: IdentifierPath,
@@ -66,34 +79,19 @@ impl SymbolTable {
         bubble_up: bool,
     ) -> CodegenResult<Option<&Symbol>> {
         let path = path.into();
-        if path.is_empty() {
-            return Ok(None);
-        }
-
-        let mut scope = self.current.clone();
-        loop {
-            let full_path = scope.join(&path).canonicalize();
-            if let Some(symbol) = self.symbols.get(&full_path) {
-                return Ok(Some(symbol));
-            }
-
-            if scope.is_empty() || !bubble_up {
-                // Didn't find anything. We are at the root or not allowed to bubble up, so bail.
-                return Ok(None);
-            }
+        let full_path = resolve_symbol_path(&self.current, &path, bubble_up, |p| {
+            self.symbols.contains_key(p)
+        });
+        Ok(full_path.and_then(|p| self.symbols.get(&p)))
+    }
 
-            scope.pop();
-        }
+    pub fn value(&self, path: &IdentifierPath) -> CodegenResult<Option<Value>> {
+        Ok(self.lookup(path, true)?.map(Symbol::value))
     }
 
-    pub fn value(&self, path: &IdentifierPath) -> CodegenResult<Option<i64>> {
-        Ok(self
-            .lookup(path, true)?
-            .map(|s| match s {
-                Symbol::Label(pc) => Some(pc.as_i64()),
-                Symbol::Variable(val) | Symbol::Constant(val) | Symbol::System(val) => Some(*val),
-            })
-            .flatten())
+    /// Like [SymbolTable::value], but only looks in the current scope
+    pub fn local_value(&self, path: &IdentifierPath) -> CodegenResult<Option<Value>> {
+        Ok(self.lookup(path, false)?.map(Symbol::value))
     }
 
     pub fn enter<ID: Into<Identifier>>(&mut self, id: ID) {
@@ -109,6 +107,36 @@ impl SymbolTable {
     }
 }
 
+/// Resolves a path, relative to a scope, to the full path of the symbol it refers to.
+///
+/// `exists` should determine if a symbol exists at a full path.
+/// If `bubble_up` is set, the lookup will bubble up to parent scopes if the symbol is not found in the current scope.
+pub fn resolve_symbol_path<F: Fn(&IdentifierPath) -> bool>(
+    scope: &IdentifierPath,
+    path: &IdentifierPath,
+    bubble_up: bool,
+    exists: F,
+) -> Option<IdentifierPath> {
+    if path.is_empty() {
+        return None;
+    }
+
+    let mut scope = scope.clone();
+    loop {
+        let full_path = scope.join(path).canonicalize();
+        if exists(&full_path) {
+            return Some(full_path);
+        }
+
+        if scope.is_empty() || !bubble_up {
+            // Didn't find anything. We are at the root or not allowed to bubble up, so bail.
+            return None;
+        }
+
+        scope.pop();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{Symbol, SymbolTable};
@@ -125,36 +153,51 @@ mod tests {
         st.enter("foo");
         reg(&mut st, "A", 100)?;
 
-        assert_eq!(st.lookup("A", true)?, Some(&Symbol::Constant(100)));
+        assert_eq!(st.lookup("A", true)?, Some(&Symbol::Constant(100.into())));
 
         st.enter("bar");
         reg(&mut st, "A", 555)?;
-        assert_eq!(st.lookup("A", true)?, Some(&Symbol::Constant(555)));
-        assert_eq!(st.lookup("B", true)?, Some(&Symbol::Constant(2)));
+        assert_eq!(st.lookup("A", true)?, Some(&Symbol::Constant(555.into())));
+        assert_eq!(st.lookup("B", true)?, Some(&Symbol::Constant(2.into())));
         assert_eq!(st.lookup("B", false)?, None);
         st.leave();
 
-        assert_eq!(st.lookup("B", true)?, Some(&Symbol::Constant(2)));
-        assert_eq!(st.lookup("super.A", true)?, Some(&Symbol::Constant(1)));
+        assert_eq!(st.lookup("B", true)?, Some(&Symbol::Constant(2.into())));
+        assert_eq!(
+            st.lookup("super.A", true)?,
+            Some(&Symbol::Constant(1.into()))
+        );
         assert_eq!(
             st.lookup("super.foo.A", true)?,
-            Some(&Symbol::Constant(100))
+            Some(&Symbol::Constant(100.into()))
         );
 
         st.leave();
 
-        assert_eq!(st.lookup("foo.bar.A", true)?, Some(&Symbol::Constant(555)));
+        assert_eq!(
+            st.lookup("foo.bar.A", true)?,
+            Some(&Symbol::Constant(555.into()))
+        );
 
-        assert_eq!(st.lookup("A", true)?, Some(&Symbol::Constant(1)));
-        assert_eq!(st.lookup("foo.A", true)?, Some(&Symbol::Constant(100)));
-        assert_eq!(st.lookup("super.A", true)?, Some(&Symbol::Constant(1)));
+        assert_eq!(st.lookup("A", true)?, Some(&Symbol::Constant(1.into())));
+        assert_eq!(
+            st.lookup("foo.A", true)?,
+            Some(&Symbol::Constant(100.into()))
+        );
+        assert_eq!(
+            st.lookup("super.A", true)?,
+            Some(&Symbol::Constant(1.into()))
+        );
 
         assert_eq!(st.lookup("foo2.A", true)?, None);
         st.enter("foo2");
         reg(&mut st, "A", 200)?;
-        assert_eq!(st.lookup("A", true)?, Some(&Symbol::Constant(200)));
+        assert_eq!(st.lookup("A", true)?, Some(&Symbol::Constant(200.into())));
         st.leave();
-        assert_eq!(st.lookup("foo2.A", true)?, Some(&Symbol::Constant(200)));
+        assert_eq!(
+            st.lookup("foo2.A", true)?,
+            Some(&Symbol::Constant(200.into()))
+        );
 
         Ok(())
     }
@@ -162,14 +205,14 @@ mod tests {
     #[test]
     fn can_register_nested_symbols() -> TestResult {
         let mut st = SymbolTable::new();
-        st.register("a.a", Symbol::Constant(1), &empty_span(), false)?;
-        st.register("a.b", Symbol::Constant(2), &empty_span(), false)?;
-        assert_eq!(st.lookup("a.a", true)?, Some(&Symbol::Constant(1)));
-        assert_eq!(st.lookup("a.b", true)?, Some(&Symbol::Constant(2)));
+        st.register("a.a", Symbol::Constant(1.into()), &empty_span(), false)?;
+        st.register("a.b", Symbol::Constant(2.into()), &empty_span(), false)?;
+        assert_eq!(st.lookup("a.a", true)?, Some(&Symbol::Constant(1.into())));
+        assert_eq!(st.lookup("a.b", true)?, Some(&Symbol::Constant(2.into())));
         Ok(())
     }
 
     fn reg<'a>(st: &'a mut SymbolTable, id: &'a str, val: i64) -> TestResult {
-        st.register(id, Symbol::Constant(val), &empty_span(), false)
+        st.register(id, Symbol::Constant(val.into()), &empty_span(), false)
     }
 }
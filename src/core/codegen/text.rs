@@ -0,0 +1,134 @@
+use crate::core::parser::Identifier;
+
+/// The ways in which characters can be translated into bytes
+#[derive(Debug, Clone, PartialEq)]
+pub enum Encoding {
+    Ascii,
+    /// PETSCII, as used when the C64 is in its default uppercase/graphics mode
+    PetsciiUpper,
+    /// PETSCII, as used when the C64 is in its lowercase/uppercase mode
+    PetsciiLower,
+    /// C64 screen codes in uppercase/graphics mode
+    ScreenUpper,
+    /// C64 screen codes in lowercase/uppercase mode
+    ScreenLower,
+    /// A character map that was defined using `.charmap`
+    Charmap(Identifier),
+}
+
+impl Encoding {
+    /// Looks up an encoding by name. Any name that is not a builtin encoding refers to a character map.
+    pub fn from_name(name: &Identifier) -> Self {
+        match name.value().to_lowercase().as_str() {
+            "ascii" => Self::Ascii,
+            "petscii_upper" => Self::PetsciiUpper,
+            "petscii_lower" => Self::PetsciiLower,
+            "screen_upper" => Self::ScreenUpper,
+            "screen_lower" => Self::ScreenLower,
+            _ => Self::Charmap(name.clone()),
+        }
+    }
+
+    /// Encodes a character using one of the builtin encodings. Character maps are not handled here.
+    pub fn encode(&self, c: char) -> Option<u8> {
+        match self {
+            Self::Ascii if c.is_ascii() => Some(c as u8),
+            Self::Ascii => None,
+            Self::PetsciiUpper => match c {
+                'a'..='z' => Some(c as u8 - b'a' + 0x41),
+                _ => petscii(c),
+            },
+            Self::PetsciiLower => match c {
+                'a'..='z' => Some(c as u8 - b'a' + 0x41),
+                'A'..='Z' => Some(c as u8 - b'A' + 0xc1),
+                _ => petscii(c),
+            },
+            Self::ScreenUpper => match c {
+                'a'..='z' => Some(c as u8 - b'a' + 1),
+                _ => screen(c),
+            },
+            Self::ScreenLower => match c {
+                'a'..='z' => Some(c as u8 - b'a' + 1),
+                'A'..='Z' => Some(c as u8 - b'A' + 0x41),
+                _ => screen(c),
+            },
+            Self::Charmap(_) => None,
+        }
+    }
+}
+
+/// The PETSCII characters that are the same in both character sets
+fn petscii(c: char) -> Option<u8> {
+    match c {
+        '\n' | '\r' => Some(0x0d),
+        ' '..='@' | 'A'..='Z' | '[' | ']' => Some(c as u8),
+        '£' => Some(0x5c),
+        '↑' => Some(0x5e),
+        '←' => Some(0x5f),
+        'π' => Some(0xff),
+        _ => None,
+    }
+}
+
+/// The screen codes that are the same in both character sets
+fn screen(c: char) -> Option<u8> {
+    match c {
+        '@' => Some(0x00),
+        'A'..='Z' => Some(c as u8 - b'A' + 1),
+        '[' => Some(0x1b),
+        '£' => Some(0x1c),
+        ']' => Some(0x1d),
+        '↑' => Some(0x1e),
+        '←' => Some(0x1f),
+        ' '..='?' => Some(c as u8),
+        'π' => Some(0x5e),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Encoding;
+
+    fn encode(encoding: Encoding, s: &str) -> Option<Vec<u8>> {
+        s.chars().map(|c| encoding.encode(c)).collect()
+    }
+
+    #[test]
+    fn builtin_encodings() {
+        assert_eq!(
+            encode(Encoding::Ascii, "aB1!"),
+            Some(vec![0x61, 0x42, 0x31, 0x21])
+        );
+        assert_eq!(encode(Encoding::Ascii, "£"), None);
+        assert_eq!(
+            encode(Encoding::PetsciiUpper, "aB1!\n"),
+            Some(vec![0x41, 0x42, 0x31, 0x21, 0x0d])
+        );
+        assert_eq!(
+            encode(Encoding::PetsciiLower, "aB£"),
+            Some(vec![0x41, 0xc2, 0x5c])
+        );
+        assert_eq!(
+            encode(Encoding::ScreenUpper, "@aB1 "),
+            Some(vec![0x00, 0x01, 0x02, 0x31, 0x20])
+        );
+        assert_eq!(
+            encode(Encoding::ScreenLower, "aB1"),
+            Some(vec![0x01, 0x42, 0x31])
+        );
+        assert_eq!(encode(Encoding::ScreenUpper, "\n"), None);
+    }
+
+    #[test]
+    fn lookup_by_name() {
+        assert_eq!(
+            Encoding::from_name(&"PETSCII_UPPER".into()),
+            Encoding::PetsciiUpper
+        );
+        assert_eq!(
+            Encoding::from_name(&"foo".into()),
+            Encoding::Charmap("foo".into())
+        );
+    }
+}
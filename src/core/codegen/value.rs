@@ -0,0 +1,92 @@
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+
+/// The result of evaluating an expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Decimal(f64),
+}
+
+impl Value {
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            Value::Integer(val) => *val as f64,
+            Value::Decimal(val) => *val,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.as_f64() == 0.0
+    }
+
+    /// Converts the value into an integer, rounding decimals if needed. Returns `None` if the value does not fit.
+    pub fn to_integer(self, rounding: Rounding) -> Option<i64> {
+        match self {
+            Value::Integer(val) => Some(val),
+            Value::Decimal(val) => {
+                let rounded = match rounding {
+                    Rounding::Nearest => val.round(),
+                    Rounding::Floor => val.floor(),
+                    Rounding::Ceil => val.ceil(),
+                    Rounding::Truncate => val.trunc(),
+                };
+                // The upper bound itself can't be represented as an i64, hence the exclusive range
+                if rounded >= i64::MIN as f64 && rounded < i64::MAX as f64 {
+                    Some(rounded as i64)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(val: i64) -> Self {
+        Value::Integer(val)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Value::Integer(val) => write!(f, "{}", val),
+            Value::Decimal(val) => write!(f, "{}", val),
+        }
+    }
+}
+
+/// How decimal values are converted into integers, e.g. when they are emitted
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    /// Round to the nearest integer, with halfway cases rounded away from zero
+    #[default]
+    Nearest,
+    /// Round towards negative infinity
+    Floor,
+    /// Round towards positive infinity
+    Ceil,
+    /// Round towards zero
+    Truncate,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Rounding, Value};
+
+    #[test]
+    fn rounding() {
+        let round = |val: f64, rounding| Value::Decimal(val).to_integer(rounding);
+        assert_eq!(round(1.5, Rounding::Nearest), Some(2));
+        assert_eq!(round(-1.5, Rounding::Nearest), Some(-2));
+        assert_eq!(round(1.7, Rounding::Floor), Some(1));
+        assert_eq!(round(-1.2, Rounding::Floor), Some(-2));
+        assert_eq!(round(1.2, Rounding::Ceil), Some(2));
+        assert_eq!(round(-1.7, Rounding::Truncate), Some(-1));
+        assert_eq!(round(1e30, Rounding::Nearest), None);
+        assert_eq!(round(f64::NAN, Rounding::Nearest), None);
+        assert_eq!(Value::Integer(5).to_integer(Rounding::Floor), Some(5));
+    }
+}
@@ -0,0 +1,308 @@
+use std::cmp::{max, min};
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use itertools::Itertools;
+use serde::Deserialize;
+
+use crate::core::io::SegmentMerger;
+use crate::errors::{MosError, MosResult};
+
+const SIGNATURE: &[u8; 16] = b"C64 CARTRIDGE   ";
+const HEADER_LEN: u32 = 0x40;
+const CHIP_HEADER_LEN: u32 = 0x10;
+const VERSION: u16 = 0x0100;
+
+/// The cartridge hardware that a CRT file is created for
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub enum CartridgeType {
+    #[serde(rename = "normal_8k")]
+    Normal8k,
+    #[serde(rename = "normal_16k")]
+    Normal16k,
+    #[serde(rename = "ultimax")]
+    Ultimax,
+    #[serde(rename = "easyflash")]
+    EasyFlash,
+    #[serde(rename = "magic_desk")]
+    MagicDesk,
+}
+
+/// An address range in which the cartridge ROM is visible to the C64
+struct Window {
+    name: &'static str,
+    range: Range<usize>,
+    /// The address that is stored in the CHIP packet
+    load_address: u16,
+}
+
+impl CartridgeType {
+    fn name(&self) -> &'static str {
+        match self {
+            CartridgeType::Normal8k => "normal_8k",
+            CartridgeType::Normal16k => "normal_16k",
+            CartridgeType::Ultimax => "ultimax",
+            CartridgeType::EasyFlash => "easyflash",
+            CartridgeType::MagicDesk => "magic_desk",
+        }
+    }
+
+    fn hardware_type(&self) -> u16 {
+        match self {
+            CartridgeType::EasyFlash => 32,
+            CartridgeType::MagicDesk => 19,
+            _ => 0,
+        }
+    }
+
+    /// The state of the EXROM and GAME lines when the cartridge starts
+    fn lines(&self) -> (u8, u8) {
+        match self {
+            CartridgeType::Normal8k | CartridgeType::MagicDesk => (0, 1),
+            CartridgeType::Normal16k => (0, 0),
+            CartridgeType::Ultimax | CartridgeType::EasyFlash => (1, 0),
+        }
+    }
+
+    fn num_banks(&self) -> usize {
+        match self {
+            CartridgeType::EasyFlash => 64,
+            CartridgeType::MagicDesk => 128,
+            _ => 1,
+        }
+    }
+
+    fn chip_type(&self) -> u16 {
+        match self {
+            // Flash memory
+            CartridgeType::EasyFlash => 2,
+            _ => 0,
+        }
+    }
+
+    fn windows(&self) -> Vec<Window> {
+        let roml = Window {
+            name: "ROML",
+            range: 0x8000..0xa000,
+            load_address: 0x8000,
+        };
+        match self {
+            CartridgeType::Normal8k | CartridgeType::MagicDesk => vec![roml],
+            CartridgeType::Normal16k => vec![Window {
+                name: "ROML/ROMH",
+                range: 0x8000..0xc000,
+                load_address: 0x8000,
+            }],
+            CartridgeType::Ultimax => vec![
+                roml,
+                Window {
+                    name: "ROMH",
+                    range: 0xe000..0x10000,
+                    load_address: 0xe000,
+                },
+            ],
+            CartridgeType::EasyFlash => vec![
+                roml,
+                Window {
+                    name: "ROMH",
+                    range: 0xe000..0x10000,
+                    load_address: 0xa000,
+                },
+            ],
+        }
+    }
+}
+
+/// Creates a CRT image containing the merged targets. Every bank of the cartridge is filled from the segments in the same bank.
+/// Segments without a bank are placed in bank 0.
+pub fn to_crt(cartridge: CartridgeType, name: &str, merger: &SegmentMerger) -> MosResult<Vec<u8>> {
+    let windows = cartridge.windows();
+    let mut errors = vec![];
+
+    // The contents of each chip, by (bank, window index)
+    let mut chips: BTreeMap<(usize, usize), Vec<u8>> = BTreeMap::new();
+    for (bank, target) in merger.banks()? {
+        let bank = bank as usize;
+        if bank >= cartridge.num_banks() {
+            errors.push(MosError::BuildError(format!(
+                "bank {} does not exist on a '{}' cartridge, which has {} bank(s)",
+                bank,
+                cartridge.name(),
+                cartridge.num_banks()
+            )));
+            continue;
+        }
+
+        for (segment_name, segment) in target.sources() {
+            let range = match segment.target_range() {
+                Some(range) => range,
+                None => continue,
+            };
+            let fits = windows
+                .iter()
+                .any(|w| range.start >= w.range.start && range.end <= w.range.end);
+            if !fits {
+                errors.push(MosError::BuildError(format!(
+                    "in bank {}: segment '{}' (${:04x} - ${:04x}) does not fit in the cartridge windows: {}",
+                    bank,
+                    segment_name,
+                    range.start,
+                    range.end,
+                    windows
+                        .iter()
+                        .map(|w| format!("{} (${:04x} - ${:04x})", w.name, w.range.start, w.range.end))
+                        .join(", ")
+                )));
+            }
+        }
+
+        let range = match target.range() {
+            Some(range) => range,
+            None => continue,
+        };
+        for (index, window) in windows.iter().enumerate() {
+            let from = max(range.start, window.range.start);
+            let to = min(range.end, window.range.end);
+            if from < to {
+                // Unused parts of the ROM are left in their erased state
+                let chip = chips
+                    .entry((bank, index))
+                    .or_insert_with(|| vec![0xff; window.range.len()]);
+                chip[from - window.range.start..to - window.range.start]
+                    .copy_from_slice(&target.data()[from..to]);
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(MosError::Multiple(errors));
+    }
+
+    let (exrom, game) = cartridge.lines();
+    let mut name_bytes = name.as_bytes().to_vec();
+    name_bytes.resize(32, 0);
+
+    let mut bytes = vec![];
+    bytes.extend(SIGNATURE);
+    bytes.extend(&HEADER_LEN.to_be_bytes());
+    bytes.extend(&VERSION.to_be_bytes());
+    bytes.extend(&cartridge.hardware_type().to_be_bytes());
+    bytes.push(exrom);
+    bytes.push(game);
+    bytes.extend(&[0; 6]);
+    bytes.extend(&name_bytes);
+
+    for ((bank, index), data) in chips {
+        bytes.extend(b"CHIP");
+        bytes.extend(&(CHIP_HEADER_LEN + data.len() as u32).to_be_bytes());
+        bytes.extend(&cartridge.chip_type().to_be_bytes());
+        bytes.extend(&(bank as u16).to_be_bytes());
+        bytes.extend(&windows[index].load_address.to_be_bytes());
+        bytes.extend(&(data.len() as u16).to_be_bytes());
+        bytes.extend(&data);
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::core::codegen::{codegen, CodegenContext, CodegenOptions};
+    use crate::core::io::{to_crt, CartridgeType, SegmentMerger};
+    use crate::core::parser::parse_or_err;
+    use crate::errors::MosResult;
+
+    #[test]
+    fn can_create_easyflash_cartridge() -> MosResult<()> {
+        let ctx = test_codegen(
+            r"
+            .define segment { name = boot start = $e000 bank = 0 }
+            .define segment { name = data start = $8000 bank = 1 }
+            .segment boot { sei }
+            .segment data { .byte 1, 2 }
+            ",
+        )?;
+        let merger = merge(&ctx);
+        let crt = to_crt(CartridgeType::EasyFlash, "test", &merger)?;
+
+        assert_eq!(&crt[0..16], b"C64 CARTRIDGE   ");
+        // Header length, version, hardware type, EXROM and GAME
+        assert_eq!(&crt[16..26], &[0, 0, 0, 0x40, 1, 0, 0, 32, 1, 0]);
+        assert_eq!(&crt[32..36], b"test");
+        assert_eq!(crt.len(), 0x40 + 2 * (0x10 + 0x2000));
+
+        // Bank 0, ROMH
+        let chip = &crt[0x40..];
+        assert_eq!(&chip[0..4], b"CHIP");
+        assert_eq!(
+            &chip[4..16],
+            &[0, 0, 0x20, 0x10, 0, 2, 0, 0, 0xa0, 0, 0x20, 0]
+        );
+        assert_eq!(&chip[16..18], &[0x78, 0xff]);
+
+        // Bank 1, ROML
+        let chip = &chip[0x2010..];
+        assert_eq!(&chip[10..14], &[0, 1, 0x80, 0]);
+        assert_eq!(&chip[16..19], &[1, 2, 0xff]);
+        Ok(())
+    }
+
+    #[test]
+    fn segments_must_fit_in_cartridge_windows() -> MosResult<()> {
+        let ctx = test_codegen(
+            r"
+            .define segment { name = code start = $9fff }
+            .define segment { name = data start = $8000 bank = 1 }
+            .segment code { .byte 1, 2 }
+            .segment data { nop }
+            ",
+        )?;
+        let merger = merge(&ctx);
+        let err = to_crt(CartridgeType::Normal8k, "test", &merger)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(
+            err.contains("bank 1 does not exist on a 'normal_8k' cartridge, which has 1 bank(s)")
+        );
+        assert!(err.contains("in bank 0: segment 'code' ($9fff - $a001) does not fit in the cartridge windows: ROML ($8000 - $a000)"));
+        Ok(())
+    }
+
+    #[test]
+    fn segments_without_bank_may_not_overlap_bank_0() -> MosResult<()> {
+        let ctx = test_codegen(
+            r"
+            .define segment { name = code start = $8000 }
+            .define segment { name = data start = $8001 bank = 0 }
+            .segment code { .byte 1, 2 }
+            .segment data { nop }
+            ",
+        )?;
+        let merger = merge(&ctx);
+        assert_eq!(
+            to_crt(CartridgeType::Normal8k, "test", &merger)
+                .err()
+                .unwrap()
+                .to_string(),
+            "error: in bank 0: segment 'data' ($8001 - $8002) overlaps with: segment 'code' ($8000 - $8002)"
+        );
+        Ok(())
+    }
+
+    fn test_codegen(source: &str) -> MosResult<CodegenContext> {
+        let tree = parse_or_err("test.asm".as_ref(), source)?;
+        codegen(tree, CodegenOptions::default())
+    }
+
+    fn merge(ctx: &CodegenContext) -> SegmentMerger<'_> {
+        let mut merger = SegmentMerger::new(PathBuf::from("test.prg"));
+        for name in ctx.segments().keys() {
+            merger.merge(name, ctx.segments().get(name)).unwrap();
+        }
+        merger
+    }
+}
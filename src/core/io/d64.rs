@@ -0,0 +1,260 @@
+use crate::errors::{MosError, MosResult};
+
+const TRACKS: u8 = 35;
+const DIRECTORY_TRACK: u8 = 18;
+const SECTOR_SIZE: usize = 256;
+/// The number of data bytes in a sector, since the first two bytes link to the next sector
+const SECTOR_DATA_SIZE: usize = SECTOR_SIZE - 2;
+/// The distance between consecutive sectors of a file, giving the drive time to process a sector before reading the next
+const INTERLEAVE: usize = 10;
+const DIRECTORY_ENTRY_SIZE: usize = 32;
+const FILE_TYPE_PRG: u8 = 0x82;
+/// PETSCII shifted space, used to pad names
+const PADDING: u8 = 0xa0;
+
+/// A file to store on a disk image
+pub struct DiskFile {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Creates a D64 disk image containing the provided files
+pub fn to_d64(disk_name: &str, disk_id: &str, files: &[DiskFile]) -> MosResult<Vec<u8>> {
+    let mut disk = Disk::new();
+
+    // The BAM
+    disk.allocate_on(DIRECTORY_TRACK, 0);
+
+    let mut entries = vec![];
+    for file in files {
+        let (first, num_sectors) = disk.write_file(file)?;
+        entries.push((file, first, num_sectors));
+    }
+
+    let mut previous_directory_sector: Option<u8> = None;
+    for chunk in entries.chunks(SECTOR_SIZE / DIRECTORY_ENTRY_SIZE) {
+        let sector = disk
+            .allocate_on(DIRECTORY_TRACK, 1)
+            .ok_or_else(|| MosError::BuildError("disk image directory is full".into()))?;
+        if let Some(previous) = previous_directory_sector {
+            disk.set_link(DIRECTORY_TRACK, previous, DIRECTORY_TRACK, sector);
+        }
+        disk.set_link(DIRECTORY_TRACK, sector, 0, 0xff);
+
+        let data = disk.sector_mut(DIRECTORY_TRACK, sector);
+        for (index, (file, (track, first_sector), num_sectors)) in chunk.iter().enumerate() {
+            let entry = &mut data[index * DIRECTORY_ENTRY_SIZE..(index + 1) * DIRECTORY_ENTRY_SIZE];
+            entry[2] = FILE_TYPE_PRG;
+            entry[3] = *track;
+            entry[4] = *first_sector;
+            entry[5..21].copy_from_slice(&to_petscii(&file.name, 16));
+            entry[30..32].copy_from_slice(&(*num_sectors as u16).to_le_bytes());
+        }
+        previous_directory_sector = Some(sector);
+    }
+
+    // An empty directory still needs a directory sector
+    if previous_directory_sector.is_none() {
+        disk.allocate_on(DIRECTORY_TRACK, 1);
+        disk.set_link(DIRECTORY_TRACK, 1, 0, 0xff);
+    }
+
+    disk.write_bam(disk_name, disk_id);
+    Ok(disk.data)
+}
+
+struct Disk {
+    data: Vec<u8>,
+    /// For every track, which sectors are in use
+    used: Vec<Vec<bool>>,
+}
+
+impl Disk {
+    fn new() -> Self {
+        let used = (1..=TRACKS)
+            .map(|track| vec![false; sectors_per_track(track)])
+            .collect::<Vec<_>>();
+        let num_sectors = used.iter().map(|t| t.len()).sum::<usize>();
+        Self {
+            data: vec![0; num_sectors * SECTOR_SIZE],
+            used,
+        }
+    }
+
+    fn sector_mut(&mut self, track: u8, sector: u8) -> &mut [u8] {
+        let offset =
+            ((1..track).map(sectors_per_track).sum::<usize>() + sector as usize) * SECTOR_SIZE;
+        &mut self.data[offset..offset + SECTOR_SIZE]
+    }
+
+    fn set_link(&mut self, track: u8, sector: u8, next_track: u8, next_sector: u8) {
+        let data = self.sector_mut(track, sector);
+        data[0] = next_track;
+        data[1] = next_sector;
+    }
+
+    /// Marks the first free sector on a track, starting at the preferred sector, as used
+    fn allocate_on(&mut self, track: u8, preferred: usize) -> Option<u8> {
+        let used = &mut self.used[track as usize - 1];
+        let sector = (0..used.len())
+            .map(|offset| (preferred + offset) % used.len())
+            .find(|sector| !used[*sector])?;
+        used[sector] = true;
+        Some(sector as u8)
+    }
+
+    /// Allocates the sector that follows the previous sector of a file
+    fn allocate_file_sector(&mut self, previous: Option<(u8, u8)>) -> Option<(u8, u8)> {
+        let (start_track, preferred) = match previous {
+            Some((track, sector)) => (track, sector as usize + INTERLEAVE),
+            None => (1, 0),
+        };
+        (start_track..=TRACKS)
+            .filter(|track| *track != DIRECTORY_TRACK)
+            .find_map(|track| {
+                let preferred = if track == start_track { preferred } else { 0 };
+                self.allocate_on(track, preferred)
+                    .map(|sector| (track, sector))
+            })
+    }
+
+    /// Writes the file to a chain of sectors, returning its first sector and the number of sectors used
+    fn write_file(&mut self, file: &DiskFile) -> MosResult<((u8, u8), usize)> {
+        let disk_full = || {
+            MosError::BuildError(format!(
+                "disk image is full, could not write: {}",
+                file.name
+            ))
+        };
+        let chunks = file.data.chunks(SECTOR_DATA_SIZE).collect::<Vec<_>>();
+        let mut sectors = vec![];
+        for _ in 0..chunks.len().max(1) {
+            let sector = self
+                .allocate_file_sector(sectors.last().copied())
+                .ok_or_else(disk_full)?;
+            sectors.push(sector);
+        }
+
+        for (index, (track, sector)) in sectors.iter().enumerate() {
+            let chunk = chunks.get(index).copied().unwrap_or_default();
+            match sectors.get(index + 1) {
+                Some((next_track, next_sector)) => {
+                    self.set_link(*track, *sector, *next_track, *next_sector)
+                }
+                // The last sector contains the index of the last used byte
+                None => self.set_link(*track, *sector, 0, chunk.len() as u8 + 1),
+            }
+            self.sector_mut(*track, *sector)[2..2 + chunk.len()].copy_from_slice(chunk);
+        }
+
+        Ok((sectors[0], sectors.len()))
+    }
+
+    fn write_bam(&mut self, disk_name: &str, disk_id: &str) {
+        let free = self
+            .used
+            .iter()
+            .map(|track| {
+                let mut entry = [0u8; 4];
+                for (sector, used) in track.iter().enumerate() {
+                    if !used {
+                        entry[0] += 1;
+                        entry[1 + sector / 8] |= 1 << (sector % 8);
+                    }
+                }
+                entry
+            })
+            .collect::<Vec<_>>();
+
+        let bam = self.sector_mut(DIRECTORY_TRACK, 0);
+        bam[0] = DIRECTORY_TRACK;
+        bam[1] = 1;
+        // DOS version
+        bam[2] = b'A';
+        for (index, entry) in free.iter().enumerate() {
+            bam[4 + index * 4..8 + index * 4].copy_from_slice(entry);
+        }
+        bam[0x90..0xa0].copy_from_slice(&to_petscii(disk_name, 16));
+        bam[0xa0..0xa2].copy_from_slice(&[PADDING, PADDING]);
+        bam[0xa2..0xa4].copy_from_slice(&to_petscii(disk_id, 2));
+        bam[0xa4] = PADDING;
+        // DOS type
+        bam[0xa5..0xa7].copy_from_slice(b"2A");
+        bam[0xa7..0xab].copy_from_slice(&[PADDING; 4]);
+    }
+}
+
+fn sectors_per_track(track: u8) -> usize {
+    match track {
+        1..=17 => 21,
+        18..=24 => 19,
+        25..=30 => 18,
+        _ => 17,
+    }
+}
+
+/// Converts a name to (unshifted) PETSCII, padded to the provided length
+fn to_petscii(name: &str, len: usize) -> Vec<u8> {
+    let mut bytes = name
+        .chars()
+        .take(len)
+        .map(|c| match c {
+            'a'..='z' => c.to_ascii_uppercase() as u8,
+            ' '..='_' => c as u8,
+            _ => b'?',
+        })
+        .collect::<Vec<_>>();
+    bytes.resize(len, PADDING);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::io::d64::{to_d64, DiskFile, DIRECTORY_TRACK, SECTOR_SIZE};
+    use crate::errors::MosResult;
+
+    #[test]
+    fn can_create_disk_image() -> MosResult<()> {
+        let files = [
+            DiskFile {
+                name: "game".into(),
+                data: (0..300).map(|i| i as u8).collect(),
+            },
+            DiskFile {
+                name: "level 1".into(),
+                data: vec![0x00, 0x10],
+            },
+        ];
+        let d64 = to_d64("my disk", "mo", &files)?;
+        assert_eq!(d64.len(), 174848);
+
+        let sector = |track: u8, sector: usize| {
+            let offset = (if track == DIRECTORY_TRACK { 357 } else { 0 } + sector) * SECTOR_SIZE;
+            &d64[offset..offset + SECTOR_SIZE]
+        };
+
+        // BAM: track 1 has three sectors in use
+        let bam = sector(DIRECTORY_TRACK, 0);
+        assert_eq!(&bam[0..3], &[18, 1, b'A']);
+        assert_eq!(&bam[4..8], &[18, 0b1111_1100, 0b1111_1011, 0b0001_1111]);
+        assert_eq!(&bam[0x90..0x98], b"MY DISK\xa0");
+        assert_eq!(&bam[0xa2..0xa7], b"MO\xa02A");
+        // Track 18 has the BAM and one directory sector in use
+        assert_eq!(bam[4 + 17 * 4], 17);
+
+        let directory = sector(DIRECTORY_TRACK, 1);
+        assert_eq!(&directory[0..2], &[0, 0xff]);
+        assert_eq!(&directory[2..9], &[0x82, 1, 0, b'G', b'A', b'M', b'E']);
+        assert_eq!(&directory[30..32], &[2, 0]);
+        assert_eq!(&directory[34..37], &[0x82, 1, 1]);
+        assert_eq!(&directory[62..64], &[1, 0]);
+
+        // The first file continues in sector 10, which contains the remaining 46 bytes
+        assert_eq!(&sector(1, 0)[0..4], &[1, 10, 0, 1]);
+        assert_eq!(&sector(1, 10)[0..3], &[0, 47, 254]);
+
+        // The second file is placed in the first free sector
+        assert_eq!(&sector(1, 1)[0..4], &[0, 3, 0x00, 0x10]);
+        Ok(())
+    }
+}
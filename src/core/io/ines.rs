@@ -0,0 +1,292 @@
+use std::ops::Range;
+
+use serde::Deserialize;
+
+use crate::core::io::SegmentMerger;
+use crate::errors::{MosError, MosResult};
+
+const SIGNATURE: &[u8; 4] = b"NES\x1a";
+const PRG_WINDOW: Range<usize> = 0x8000..0x10000;
+const PRG_UNIT: usize = 0x4000;
+const CHR_WINDOW: Range<usize> = 0x0000..0x2000;
+const CHR_UNIT: usize = 0x2000;
+/// The NMI, RESET and IRQ vectors
+const VECTORS: Range<usize> = 0xfffa..0x10000;
+
+/// The header fields and bank layout of an iNES file
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "snake_case")]
+pub struct NesOptions {
+    pub mapper: u16,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    /// Whether to write a NES 2.0 header instead of an iNES header
+    pub nes2: bool,
+    /// The banks that make up CHR ROM. All other banks make up PRG ROM.
+    pub chr_banks: Vec<u8>,
+}
+
+impl Default for NesOptions {
+    fn default() -> Self {
+        Self {
+            mapper: 0,
+            mirroring: Mirroring::Horizontal,
+            battery: false,
+            nes2: false,
+            chr_banks: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    FourScreen,
+}
+
+/// Creates an iNES (or NES 2.0) file containing the merged targets. PRG ROM is made up of all banks that are not CHR banks, ordered by bank.
+/// Segments without a bank are placed in bank 0.
+/// Every bank takes up as many 16KB (PRG) or 8KB (CHR) units as its data covers.
+pub fn to_ines(options: &NesOptions, merger: &SegmentMerger) -> MosResult<Vec<u8>> {
+    let max_mapper = if options.nes2 { 0xfff } else { 0xff };
+    if options.mapper > max_mapper {
+        return Err(MosError::BuildError(format!(
+            "mapper {} is not supported by the {} format, which supports mappers up to {}",
+            options.mapper,
+            if options.nes2 { "NES 2.0" } else { "iNES" },
+            max_mapper
+        )));
+    }
+
+    let mut errors = vec![];
+    let mut prg: Vec<u8> = vec![];
+    let mut chr: Vec<u8> = vec![];
+    let mut vectors_bank = None;
+
+    for (bank, target) in merger.banks()? {
+        let range = match target.range() {
+            Some(range) => range,
+            None => continue,
+        };
+        let is_chr = options.chr_banks.contains(&bank);
+        let (kind, window, unit, rom) = match is_chr {
+            true => ("CHR", CHR_WINDOW, CHR_UNIT, &mut chr),
+            false => ("PRG", PRG_WINDOW, PRG_UNIT, &mut prg),
+        };
+
+        if range.start < window.start || range.end > window.end {
+            errors.push(MosError::BuildError(format!(
+                "in bank {}: data (${:04x} - ${:04x}) does not fit in the {} ROM window (${:04x} - ${:04x})",
+                bank, range.start, range.end, kind, window.start, window.end
+            )));
+            continue;
+        }
+
+        let from = range.start / unit * unit;
+        let to = range.end.div_ceil(unit) * unit;
+        rom.extend(&target.data()[from..to]);
+
+        if !is_chr {
+            let has_vectors = VECTORS.clone().all(|address| {
+                target.sources().iter().any(|(_, segment)| {
+                    segment
+                        .target_range()
+                        .map(|r| r.contains(&address))
+                        .unwrap_or_default()
+                })
+            });
+            vectors_bank = Some((bank, has_vectors));
+        }
+    }
+
+    // The last PRG bank is mapped to $C000 - $FFFF at startup, so it needs to provide the vectors
+    match vectors_bank {
+        Some((_, true)) => (),
+        Some((bank, false)) => errors.push(MosError::BuildError(format!(
+            "in bank {}: the interrupt vectors (${:04x} - ${:04x}) are not defined",
+            bank,
+            VECTORS.start,
+            VECTORS.end - 1
+        ))),
+        None => errors.push(MosError::BuildError("no PRG ROM data to write".into())),
+    }
+
+    if !errors.is_empty() {
+        return Err(MosError::Multiple(errors));
+    }
+
+    let prg_units = prg.len() / PRG_UNIT;
+    let chr_units = chr.len() / CHR_UNIT;
+    let mapper = options.mapper;
+
+    let mut flags6 = ((mapper & 0x0f) as u8) << 4;
+    match options.mirroring {
+        Mirroring::Horizontal => (),
+        Mirroring::Vertical => flags6 |= 0b0001,
+        Mirroring::FourScreen => flags6 |= 0b1000,
+    }
+    if options.battery {
+        flags6 |= 0b0010;
+    }
+    let mut flags7 = (mapper & 0xf0) as u8;
+
+    let mut bytes = vec![];
+    bytes.extend(SIGNATURE);
+    if options.nes2 {
+        flags7 |= 0b1000;
+        bytes.push(prg_units as u8);
+        bytes.push(chr_units as u8);
+        bytes.push(flags6);
+        bytes.push(flags7);
+        bytes.push((mapper >> 8) as u8);
+        bytes.push((((chr_units >> 8) as u8) << 4) | (prg_units >> 8) as u8);
+        // 8KB of PRG RAM, which is battery-backed if requested
+        bytes.push(if options.battery { 0x70 } else { 0x07 });
+        // 8KB of CHR RAM if there is no CHR ROM
+        bytes.push(if chr_units == 0 { 0x07 } else { 0x00 });
+        bytes.extend(&[0; 4]);
+    } else {
+        bytes.push(prg_units as u8);
+        bytes.push(chr_units as u8);
+        bytes.push(flags6);
+        bytes.push(flags7);
+        bytes.extend(&[0; 8]);
+    }
+    bytes.extend(prg);
+    bytes.extend(chr);
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::core::codegen::{codegen, CodegenContext, CodegenOptions};
+    use crate::core::io::{to_ines, Mirroring, NesOptions, SegmentMerger};
+    use crate::core::parser::parse_or_err;
+    use crate::errors::MosResult;
+
+    #[test]
+    fn can_create_ines_file() -> MosResult<()> {
+        let ctx = test_codegen(
+            r"
+            .define segment { name = code start = $c000 }
+            .define segment { name = vectors start = $fffa }
+            .define segment { name = tiles start = $0000 bank = 1 }
+            .segment code { sei }
+            .segment vectors { .word $c000, $c000, $c000 }
+            .segment tiles { .byte 1, 2 }
+            ",
+        )?;
+        let merger = merge(&ctx);
+        let options = NesOptions {
+            mapper: 0x12,
+            mirroring: Mirroring::Vertical,
+            battery: true,
+            chr_banks: vec![1],
+            ..Default::default()
+        };
+        let nes = to_ines(&options, &merger)?;
+
+        assert_eq!(
+            &nes[0..16],
+            &[0x4e, 0x45, 0x53, 0x1a, 1, 1, 0x23, 0x10, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(nes.len(), 16 + 0x4000 + 0x2000);
+        assert_eq!(nes[16], 0x78);
+        assert_eq!(&nes[16 + 0x3ffa..16 + 0x3ffc], &[0x00, 0xc0]);
+        assert_eq!(&nes[16 + 0x4000..16 + 0x4002], &[1, 2]);
+
+        let nes2 = to_ines(
+            &NesOptions {
+                nes2: true,
+                ..options
+            },
+            &merger,
+        )?;
+        assert_eq!(&nes2[7..12], &[0x18, 0, 0, 0x70, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn vectors_and_windows_are_validated() -> MosResult<()> {
+        let ctx = test_codegen(
+            r"
+            .define segment { name = code start = $8000 }
+            .define segment { name = tiles start = $3000 bank = 1 }
+            .segment code { sei }
+            .segment tiles { .byte 1, 2 }
+            ",
+        )?;
+        let merger = merge(&ctx);
+        let options = NesOptions {
+            chr_banks: vec![1],
+            ..Default::default()
+        };
+        let err = to_ines(&options, &merger).err().unwrap().to_string();
+        assert!(err.contains(
+            "in bank 1: data ($3000 - $3002) does not fit in the CHR ROM window ($0000 - $2000)"
+        ));
+        assert!(err.contains("in bank 0: the interrupt vectors ($fffa - $ffff) are not defined"));
+
+        let options = NesOptions {
+            mapper: 256,
+            ..Default::default()
+        };
+        let err = to_ines(&options, &merger).err().unwrap().to_string();
+        assert!(err.contains(
+            "mapper 256 is not supported by the iNES format, which supports mappers up to 255"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn segments_without_bank_are_merged_into_bank_0() -> MosResult<()> {
+        let ctx = test_codegen(
+            r"
+            .define segment { name = code start = $c000 }
+            .define segment { name = vectors start = $fffa bank = 0 }
+            .segment code { sei }
+            .segment vectors { .word $c000, $c000, $c000 }
+            ",
+        )?;
+        let nes = to_ines(&NesOptions::default(), &merge(&ctx))?;
+        assert_eq!(nes[4], 1);
+        assert_eq!(nes.len(), 16 + 0x4000);
+        assert_eq!(nes[16], 0x78);
+        assert_eq!(&nes[16 + 0x3ffa..16 + 0x3ffc], &[0x00, 0xc0]);
+
+        let ctx = test_codegen(
+            r"
+            .define segment { name = code start = $fff0 }
+            .define segment { name = vectors start = $fffa bank = 0 }
+            .segment code { .fill 12, 0 }
+            .segment vectors { .word $c000, $c000, $c000 }
+            ",
+        )?;
+        assert_eq!(
+            to_ines(&NesOptions::default(), &merge(&ctx))
+                .err()
+                .unwrap()
+                .to_string(),
+            "error: in bank 0: segment 'vectors' ($fffa - $10000) overlaps with: segment 'code' ($fff0 - $fffc)"
+        );
+        Ok(())
+    }
+
+    fn test_codegen(source: &str) -> MosResult<CodegenContext> {
+        let tree = parse_or_err("test.asm".as_ref(), source)?;
+        codegen(tree, CodegenOptions::default())
+    }
+
+    fn merge(ctx: &CodegenContext) -> SegmentMerger<'_> {
+        let mut merger = SegmentMerger::new(PathBuf::from("test.bin"));
+        for name in ctx.segments().keys() {
+            merger.merge(name, ctx.segments().get(name)).unwrap();
+        }
+        merger
+    }
+}
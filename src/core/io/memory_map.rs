@@ -0,0 +1,222 @@
+use std::ops::Range;
+
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::core::codegen::SegmentMap;
+use crate::core::io::SegmentMerger;
+use crate::LINE_ENDING;
+
+/// The number of bytes of the address space that are represented by a single character in the memory map bar
+const BYTES_PER_CELL: usize = 1024;
+
+/// An overview of where all segments ended up in memory
+#[derive(Debug, Serialize)]
+pub struct MemoryMap {
+    pub segments: Vec<MemoryMapSegment>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MemoryMapSegment {
+    pub name: String,
+    /// The target addresses the segment occupies, if it contains any data
+    pub target_range: Option<Range<usize>>,
+    /// The program counter range the segment was assembled for, if it contains any data
+    pub pc_range: Option<Range<usize>>,
+    pub used: usize,
+    /// The number of bytes left before the segment reaches its end address, if it has one
+    pub free: Option<usize>,
+    /// The output target the segment was merged into, if it is written at all
+    pub target: Option<String>,
+}
+
+impl MemoryMap {
+    pub fn new(segments: &SegmentMap, merger: &SegmentMerger) -> Self {
+        let segments = segments
+            .keys()
+            .into_iter()
+            .map(|name| {
+                let segment = segments.get(name);
+                let target_range = segment.target_range();
+                let used = target_range.as_ref().map(|r| r.len()).unwrap_or_default();
+                let used_end = match &target_range {
+                    Some(range) => range.end,
+                    None => usize::from(segment.options().target_address),
+                };
+                let free = segment
+                    .options()
+                    .end
+                    .map(|end| usize::from(end).saturating_sub(used_end));
+                let target = merger.target_of(name).map(|path| {
+                    path.file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string()
+                });
+
+                MemoryMapSegment {
+                    name: name.to_string(),
+                    target_range,
+                    pc_range: segment.range().clone(),
+                    used,
+                    free,
+                    target,
+                }
+            })
+            .sorted_by_key(|s| (s.target_range.as_ref().map(|r| r.start), s.name.clone()))
+            .collect();
+
+        Self { segments }
+    }
+
+    /// Renders the memory map as a table, followed by a bar that shows the usage of the entire address space
+    pub fn to_text(&self) -> String {
+        let header = [
+            "Segment",
+            "Target range",
+            "PC range",
+            "Used",
+            "Free",
+            "Output",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect_vec();
+        let rows = self
+            .segments
+            .iter()
+            .map(|s| {
+                vec![
+                    s.name.clone(),
+                    format_range(&s.target_range),
+                    format_range(&s.pc_range),
+                    s.used.to_string(),
+                    s.free.map(|f| f.to_string()).unwrap_or_else(|| "-".into()),
+                    s.target.clone().unwrap_or_else(|| "-".into()),
+                ]
+            })
+            .collect_vec();
+
+        let widths = (0..header.len())
+            .map(|col| {
+                std::iter::once(&header)
+                    .chain(rows.iter())
+                    .map(|row| row[col].len())
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect_vec();
+
+        let mut lines = std::iter::once(&header)
+            .chain(rows.iter())
+            .map(|row| {
+                row.iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                    .join("  ")
+                    .trim_end()
+                    .to_string()
+            })
+            .collect_vec();
+        lines.push("".into());
+        lines.extend(self.to_bar());
+        lines.join(LINE_ENDING)
+    }
+
+    /// Renders the 64KB address space as a bar, in which every segment is represented by a letter
+    fn to_bar(&self) -> Vec<String> {
+        let mut cells = vec!['.'; 0x10000 / BYTES_PER_CELL];
+        let mut legend = vec![];
+        for (index, segment) in self.segments.iter().enumerate() {
+            let letter = (b'A' + (index % 26) as u8) as char;
+            legend.push(format!("{} = {}", letter, segment.name));
+
+            if let Some(range) = &segment.target_range {
+                let first = range.start / BYTES_PER_CELL;
+                let last = (range.end - 1) / BYTES_PER_CELL;
+                for cell in &mut cells[first..=last] {
+                    *cell = match *cell {
+                        '.' => letter,
+                        _ => '*',
+                    };
+                }
+            }
+        }
+
+        vec![
+            format!("$0000 [{}] $FFFF", cells.iter().collect::<String>()),
+            format!(
+                "Every character is {} bytes, '*' is shared by multiple segments",
+                BYTES_PER_CELL
+            ),
+            legend.join(", "),
+        ]
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+fn format_range(range: &Option<Range<usize>>) -> String {
+    match range {
+        Some(range) => format!("${:04X} - ${:04X}", range.start, range.end),
+        None => "-".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::core::codegen::{codegen, CodegenOptions};
+    use crate::core::io::{MemoryMap, SegmentMerger};
+    use crate::core::parser::parse;
+    use crate::errors::MosResult;
+    use itertools::Itertools;
+
+    #[test]
+    fn can_generate_memory_map() -> MosResult<()> {
+        let source = r"
+            .define segment { name = code start = $0801 end = $1000 }
+            .define segment { name = data start = $1000 pc = $4000 }
+            .define segment { name = scratch start = $c000 write = false }
+            nop
+            .segment data { .byte 1, 2, 3 }
+            .segment scratch { .res 2048 }
+        ";
+        let (tree, error) = parse("test.asm".as_ref(), source);
+        assert!(error.is_none());
+        let ctx = codegen(tree, CodegenOptions::default())?;
+
+        let mut merger = SegmentMerger::new(PathBuf::from("target/test.prg"));
+        for name in ctx.segments().keys() {
+            let segment = ctx.segments().get(name);
+            if segment.options().write {
+                merger.merge(name, segment)?;
+            }
+        }
+
+        let map = MemoryMap::new(ctx.segments(), &merger);
+        assert_eq!(
+            map.to_text().lines().collect_vec(),
+            vec![
+                "Segment  Target range   PC range       Used  Free  Output",
+                "code     $0801 - $0802  $0801 - $0802  1     2046  test.prg",
+                "data     $1000 - $1003  $4000 - $4003  3     -     test.prg",
+                "scratch  $C000 - $C800  $C000 - $C800  2048  -     -",
+                "",
+                "$0000 [..A.B...........................................CC..............] $FFFF",
+                "Every character is 1024 bytes, '*' is shared by multiple segments",
+                "A = code, B = data, C = scratch",
+            ]
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&map.to_json()).unwrap();
+        assert_eq!(json["segments"][1]["name"], "data");
+        assert_eq!(json["segments"][1]["pc_range"]["start"], 0x4000);
+        assert_eq!(json["segments"][1]["target"], "test.prg");
+        assert_eq!(json["segments"][2]["target"], serde_json::Value::Null);
+        Ok(())
+    }
+}
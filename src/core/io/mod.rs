@@ -1,6 +1,21 @@
+pub use crt::*;
+pub use d64::*;
+pub use ines::*;
+pub use memory_map::*;
+pub use output::*;
 pub use segment_merger::*;
 pub use vice::*;
 
+/// Contains code related to creating C64 cartridge images
+mod crt;
+/// Contains code related to creating D64 disk images
+mod d64;
+/// Contains code related to creating NES ROM images
+mod ines;
+/// Contains code related to reporting where segments are located in memory
+mod memory_map;
+/// Contains code related to writing merged targets to disk
+mod output;
 /// Contains code related to the merging of segments when creating the final output binaries
 mod segment_merger;
 /// Contains code related to IO with the VICE emulator
@@ -0,0 +1,73 @@
+use crate::core::io::TargetSegment;
+use crate::core::target::OutputFormat;
+
+/// The address that Atari DOS jumps to after loading an executable
+const ATARI_RUN_ADDRESS: u16 = 0x02e0;
+
+/// Converts the data of a merged target into the bytes that should be written to disk
+pub fn to_output_bytes(format: OutputFormat, target: &TargetSegment) -> Vec<u8> {
+    let range = match target.range() {
+        Some(range) => range,
+        None => return vec![],
+    };
+    let start = range.start as u16;
+
+    let mut bytes = vec![];
+    match format {
+        OutputFormat::Prg => {
+            bytes.extend(&start.to_le_bytes());
+            bytes.extend(target.range_data());
+        }
+        OutputFormat::Bin => bytes.extend(target.range_data()),
+        OutputFormat::Xex => {
+            let end = (range.end - 1) as u16;
+            bytes.extend(&[0xff, 0xff]);
+            bytes.extend(&start.to_le_bytes());
+            bytes.extend(&end.to_le_bytes());
+            bytes.extend(target.range_data());
+
+            // Start the program at the beginning of the data
+            bytes.extend(&ATARI_RUN_ADDRESS.to_le_bytes());
+            bytes.extend(&(ATARI_RUN_ADDRESS + 1).to_le_bytes());
+            bytes.extend(&start.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::core::codegen::{codegen, CodegenOptions};
+    use crate::core::io::{to_output_bytes, SegmentMerger};
+    use crate::core::parser::parse_or_err;
+    use crate::core::target::OutputFormat;
+    use crate::errors::MosResult;
+
+    #[test]
+    fn can_write_output_formats() -> MosResult<()> {
+        let tree = parse_or_err("test.asm".as_ref(), "nop\nrts")?;
+        let options = CodegenOptions {
+            pc: 0x2000.into(),
+            ..Default::default()
+        };
+        let ctx = codegen(tree, options)?;
+        let mut merger = SegmentMerger::new(PathBuf::from("test"));
+        merger.merge(ctx.segments().keys()[0], ctx.segments().current())?;
+        let target = merger.targets().values().next().unwrap();
+
+        assert_eq!(
+            to_output_bytes(OutputFormat::Prg, target),
+            vec![0x00, 0x20, 0xea, 0x60]
+        );
+        assert_eq!(to_output_bytes(OutputFormat::Bin, target), vec![0xea, 0x60]);
+        assert_eq!(
+            to_output_bytes(OutputFormat::Xex, target),
+            vec![
+                0xff, 0xff, 0x00, 0x20, 0x01, 0x20, 0xea, 0x60, 0xe0, 0x02, 0xe1, 0x02, 0x00, 0x20
+            ]
+        );
+        Ok(())
+    }
+}
@@ -1,8 +1,8 @@
 use std::cmp::{max, min};
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::ops::Range;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use itertools::Itertools;
 
@@ -18,9 +18,20 @@ pub struct TargetSegment<'a> {
     range: Option<Range<usize>>,
     /// Which segments are the source of the data in this target segment?
     sources: HashMap<&'a Identifier, &'a Segment>,
+    /// The bank that all segments in this target are placed in
+    bank: Option<u8>,
 }
 
 impl<'a> TargetSegment<'a> {
+    fn new(bank: Option<u8>) -> Self {
+        Self {
+            data: [0; 65536],
+            range: None,
+            sources: HashMap::new(),
+            bank,
+        }
+    }
+
     pub fn range(&self) -> &Option<Range<usize>> {
         &self.range
     }
@@ -32,11 +43,33 @@ impl<'a> TargetSegment<'a> {
         }
     }
 
+    pub fn bank(&self) -> Option<u8> {
+        self.bank
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The segments that were merged into this target, ordered by name
+    pub fn sources(&self) -> Vec<(&'a Identifier, &'a Segment)> {
+        self.sources
+            .iter()
+            .map(|(name, segment)| (*name, *segment))
+            .sorted_by_key(|(name, _)| name.value())
+            .collect()
+    }
+
     fn merge(&mut self, segment_name: &'a Identifier, segment: &'a Segment) {
-        let target_range = segment.target_range().unwrap();
+        let mut target_range = segment.target_range().unwrap();
         self.sources.insert(segment_name, segment);
         self.data[target_range.clone()].copy_from_slice(segment.range_data());
 
+        if let Some(padding) = &segment.options().padding {
+            self.data[padding.range.clone()].fill(padding.value);
+            target_range.start = min(target_range.start, padding.range.start);
+        }
+
         match &mut self.range {
             Some(br) => {
                 br.start = min(br.start, target_range.start);
@@ -100,6 +133,14 @@ impl<'a> SegmentMerger<'a> {
         &self.targets
     }
 
+    /// Which target has a segment been merged into?
+    pub fn target_of(&self, segment_name: &Identifier) -> Option<&PathBuf> {
+        self.targets
+            .iter()
+            .find(|(_, target)| target.sources.contains_key(segment_name))
+            .map(|(path, _)| path)
+    }
+
     /// Have there been any errors during merging?
     pub fn has_errors(&self) -> bool {
         !self.errors.is_empty()
@@ -113,33 +154,23 @@ impl<'a> SegmentMerger<'a> {
     /// Merge a segment into the existing merged segments, taking care to see it doesn't overlap with already present segments
     pub fn merge(&mut self, segment_name: &'a Identifier, segment: &'a Segment) -> MosResult<()> {
         if let Some(seg_range) = segment.target_range() {
-            let target_name = &self.default_target;
+            let target_name = &match segment.options().bank {
+                Some(bank) => bank_target(&self.default_target, bank),
+                None => self.default_target.clone(),
+            };
             let target = match self.targets.entry(target_name.clone()) {
                 Entry::Occupied(o) => o.into_mut(),
-                Entry::Vacant(e) => e.insert(TargetSegment {
-                    data: [0; 65536],
-                    range: None,
-                    sources: HashMap::new(),
-                }),
+                Entry::Vacant(e) => e.insert(TargetSegment::new(segment.options().bank)),
             };
 
             let overlaps = target.overlaps_with_sources(&seg_range);
             if !overlaps.is_empty() {
-                let overlaps = overlaps
-                    .into_iter()
-                    .map(|(name, segment)| {
-                        let sr = segment.range().as_ref().unwrap();
-                        format!("segment '{}' (${:04x} - ${:04x})", name, sr.start, sr.end)
-                    })
-                    .join(", ");
-                self.errors.push(MosError::BuildError(format!(
-                    "in target '{}': segment '{}' (${:04x} - ${:04x}) overlaps with: {}",
-                    target_name.to_string_lossy(),
+                self.errors.push(overlap_error(
+                    format!("in target '{}'", target_name.to_string_lossy()),
                     segment_name,
-                    seg_range.start,
-                    seg_range.end,
-                    overlaps
-                )));
+                    &seg_range,
+                    overlaps,
+                ));
             }
 
             target.merge(segment_name, segment);
@@ -147,4 +178,126 @@ impl<'a> SegmentMerger<'a> {
 
         Ok(())
     }
+
+    /// Merges the segments of all targets by bank, for output formats that contain all banks in a single image.
+    /// Segments without a bank are placed in bank 0, so they may not overlap with the segments that are explicitly placed in bank 0.
+    pub fn banks(&self) -> MosResult<BTreeMap<u8, TargetSegment<'a>>> {
+        let mut banks: BTreeMap<u8, TargetSegment<'a>> = BTreeMap::new();
+        let mut errors = vec![];
+
+        let sources = self
+            .targets
+            .values()
+            .flat_map(|target| target.sources())
+            .sorted_by_key(|(name, _)| name.value());
+        for (segment_name, segment) in sources {
+            let bank = segment.options().bank.unwrap_or_default();
+            let seg_range = segment.target_range().unwrap();
+            let target = banks
+                .entry(bank)
+                .or_insert_with(|| TargetSegment::new(Some(bank)));
+
+            let overlaps = target.overlaps_with_sources(&seg_range);
+            if !overlaps.is_empty() {
+                errors.push(overlap_error(
+                    format!("in bank {}", bank),
+                    segment_name,
+                    &seg_range,
+                    overlaps,
+                ));
+            }
+
+            target.merge(segment_name, segment);
+        }
+
+        match errors.is_empty() {
+            true => Ok(banks),
+            false => Err(MosError::Multiple(errors)),
+        }
+    }
+}
+
+fn overlap_error(
+    location: String,
+    segment_name: &Identifier,
+    seg_range: &Range<usize>,
+    overlaps: Vec<(&Identifier, &Segment)>,
+) -> MosError {
+    let overlaps = overlaps
+        .into_iter()
+        .map(|(name, segment)| {
+            let sr = segment.range().as_ref().unwrap();
+            format!("segment '{}' (${:04x} - ${:04x})", name, sr.start, sr.end)
+        })
+        .join(", ");
+    MosError::BuildError(format!(
+        "{}: segment '{}' (${:04x} - ${:04x}) overlaps with: {}",
+        location, segment_name, seg_range.start, seg_range.end, overlaps
+    ))
+}
+
+/// Segments in a bank are merged into their own target, e.g. `main.prg` becomes `main-bank1.prg` for bank 1
+pub fn bank_target(default_target: &Path, bank: u8) -> PathBuf {
+    let stem = default_target
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let file_name = match default_target.extension() {
+        Some(ext) => format!("{}-bank{}.{}", stem, bank, ext.to_string_lossy()),
+        None => format!("{}-bank{}", stem, bank),
+    };
+    default_target.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use itertools::Itertools;
+
+    use crate::core::codegen::{codegen, CodegenOptions};
+    use crate::core::io::SegmentMerger;
+    use crate::core::parser::parse_or_err;
+    use crate::errors::MosResult;
+
+    #[test]
+    fn banked_segments_are_merged_into_separate_targets() -> MosResult<()> {
+        let source = r"
+            .define segment { name = main start = $0801 }
+            .define segment { name = a start = $8000 bank = 0 }
+            .define segment { name = b start = $8000 bank = 1 }
+            .define segment { name = c start = $8000 bank = 1 }
+            nop
+            .segment a { asl }
+            .segment b { rol }
+            .segment c { lsr }
+        ";
+        let tree = parse_or_err("test.asm".as_ref(), source)?;
+        let ctx = codegen(tree, CodegenOptions::default())?;
+
+        let mut merger = SegmentMerger::new(PathBuf::from("target/test.prg"));
+        for name in ctx
+            .segments()
+            .keys()
+            .into_iter()
+            .sorted_by_key(|n| n.value())
+        {
+            merger.merge(name, ctx.segments().get(name))?;
+        }
+
+        let target = |path: &str| merger.targets().get(&PathBuf::from(path)).unwrap();
+        assert_eq!(target("target/test.prg").range(), &Some(0x0801..0x0802));
+        assert_eq!(target("target/test-bank0.prg").range_data(), &[0x0a]);
+        assert_eq!(
+            target("target/test-bank1.prg").range(),
+            &Some(0x8000..0x8001)
+        );
+
+        // Segments in the same bank may still not overlap
+        assert_eq!(
+            merger.errors().first().unwrap().to_string(),
+            "error: in target 'target/test-bank1.prg': segment 'c' ($8000 - $8001) overlaps with: segment 'b' ($8000 - $8001)"
+        );
+        Ok(())
+    }
 }
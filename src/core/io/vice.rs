@@ -1,17 +1,25 @@
 use crate::core::codegen::{Symbol, SymbolTable};
 use crate::LINE_ENDING;
 use itertools::Itertools;
+use std::collections::BTreeMap;
 
-pub fn to_vice_symbols(table: &SymbolTable) -> String {
-    table
-        .symbols()
-        .iter()
-        .filter_map(|(path, symbol)| match symbol {
-            Symbol::Label(pc) => Some(format!("al C:{:X} .{}", pc, path)),
-            _ => None,
-        })
-        .sorted()
-        .join(LINE_ENDING)
+/// Generates VICE symbols for every bank that contains labels. Labels that are not in a bank are stored under `None`.
+pub fn to_vice_symbols(table: &SymbolTable) -> BTreeMap<Option<u8>, String> {
+    let mut banks: BTreeMap<Option<u8>, Vec<String>> = BTreeMap::new();
+    banks.insert(None, vec![]);
+    for (path, symbol) in table.symbols() {
+        if let Symbol::Label(pc, bank) = symbol {
+            banks
+                .entry(*bank)
+                .or_default()
+                .push(format!("al C:{:X} .{}", pc, path));
+        }
+    }
+
+    banks
+        .into_iter()
+        .map(|(bank, lines)| (bank, lines.into_iter().sorted().join(LINE_ENDING)))
+        .collect()
 }
 
 #[cfg(test)]
@@ -24,17 +32,53 @@ mod tests {
     #[test]
     fn can_generate_vice_symbols() -> CodegenResult<()> {
         let mut st = SymbolTable::new();
-        st.register("foo", Symbol::Label(0x1234.into()), &empty_span(), false)?;
+        st.register(
+            "foo",
+            Symbol::Label(0x1234.into(), None),
+            &empty_span(),
+            false,
+        )?;
         st.register(
             "scope.foo",
-            Symbol::Label(0xCAFE.into()),
+            Symbol::Label(0xCAFE.into(), None),
             &empty_span(),
             false,
         )?;
+        let symbols = to_vice_symbols(&st);
+        assert_eq!(symbols.keys().collect_vec(), &[&None]);
         assert_eq!(
-            to_vice_symbols(&st).lines().collect_vec(),
+            symbols[&None].lines().collect_vec(),
             &["al C:1234 .foo", "al C:CAFE .scope.foo"]
         );
         Ok(())
     }
+
+    #[test]
+    fn vice_symbols_are_generated_per_bank() -> CodegenResult<()> {
+        let mut st = SymbolTable::new();
+        st.register(
+            "foo",
+            Symbol::Label(0x1234.into(), None),
+            &empty_span(),
+            false,
+        )?;
+        st.register(
+            "bank0.init",
+            Symbol::Label(0x8000.into(), Some(0)),
+            &empty_span(),
+            false,
+        )?;
+        st.register(
+            "bank1.init",
+            Symbol::Label(0x8000.into(), Some(1)),
+            &empty_span(),
+            false,
+        )?;
+        let symbols = to_vice_symbols(&st);
+        assert_eq!(symbols.keys().collect_vec(), &[&None, &Some(0), &Some(1)]);
+        assert_eq!(symbols[&None], "al C:1234 .foo");
+        assert_eq!(symbols[&Some(0)], "al C:8000 .bank0.init");
+        assert_eq!(symbols[&Some(1)], "al C:8000 .bank1.init");
+        Ok(())
+    }
 }
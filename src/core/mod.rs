@@ -4,5 +4,8 @@ pub mod codegen;
 /// Contains code related to IO, file formats, and so on
 pub mod io;
 
+/// Profiles for the machines that programs can be assembled for
+pub mod target;
+
 /// Parses source files and translates them into a stream of [parser::Token]
 pub mod parser;
@@ -150,6 +150,23 @@ pub struct Instruction {
     pub operand: Option<Operand>,
 }
 
+impl Instruction {
+    /// The span of the entire instruction, including its operand
+    pub fn span(&self) -> Span {
+        let mut span = self.mnemonic.span;
+        if let Some(operand) = &self.operand {
+            span = span.merge(operand.expr.span);
+            for c in operand.lchar.iter().chain(&operand.rchar) {
+                span = span.merge(c.span);
+            }
+            if let Some(suffix) = &operand.suffix {
+                span = span.merge(suffix.register.span);
+            }
+        }
+        span
+    }
+}
+
 /// The addressing mode for the instruction
 #[derive(Debug, Clone, PartialEq)]
 pub enum AddressingMode {
@@ -227,12 +244,18 @@ pub enum BinaryOp {
     Mul,
     /// Division
     Div,
+    /// Modulo
+    Mod,
     /// Shift left
     Shl,
     /// Shift right
     Shr,
     /// Exclusive or
     Xor,
+    /// Bitwise and
+    BitAnd,
+    /// Bitwise or
+    BitOr,
     /// Equals
     Eq,
     /// Not equals
@@ -251,6 +274,33 @@ pub enum BinaryOp {
     Or,
 }
 
+impl BinaryOp {
+    /// The precedence of the operator, where operators with a higher precedence bind more tightly.
+    /// The levels are the same as in C.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or => 0,
+            BinaryOp::And => 1,
+            BinaryOp::BitOr => 2,
+            BinaryOp::Xor => 3,
+            BinaryOp::BitAnd => 4,
+            BinaryOp::Eq | BinaryOp::Ne => 5,
+            BinaryOp::Gt | BinaryOp::GtEq | BinaryOp::Lt | BinaryOp::LtEq => 6,
+            BinaryOp::Shl | BinaryOp::Shr => 7,
+            BinaryOp::Add | BinaryOp::Sub => 8,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => 9,
+        }
+    }
+
+    /// Bitwise operators (including shifts) operate on the bits of integers
+    pub fn is_bitwise(&self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Shl | BinaryOp::Shr | BinaryOp::Xor | BinaryOp::BitAnd | BinaryOp::BitOr
+        )
+    }
+}
+
 impl Display for BinaryOp {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         match &self {
@@ -258,9 +308,12 @@ impl Display for BinaryOp {
             BinaryOp::Sub => write!(f, "-"),
             BinaryOp::Mul => write!(f, "*"),
             BinaryOp::Div => write!(f, "/"),
+            BinaryOp::Mod => write!(f, "%"),
             BinaryOp::Shl => write!(f, "<<"),
             BinaryOp::Shr => write!(f, ">>"),
             BinaryOp::Xor => write!(f, "^"),
+            BinaryOp::BitAnd => write!(f, "&"),
+            BinaryOp::BitOr => write!(f, "|"),
             BinaryOp::Eq => write!(f, "=="),
             BinaryOp::Ne => write!(f, "!="),
             BinaryOp::Gt => write!(f, ">"),
@@ -304,6 +357,42 @@ pub enum ExpressionFactor {
         ty: Located<NumberType>,
         value: Located<Number>,
     },
+    StringLiteral(Located<StringLiteral>),
+    CharLiteral(Located<CharLiteral>),
+}
+
+/// A string literal such as `"hello"`, containing the string with its escape sequences resolved
+#[derive(Debug, Clone, PartialEq)]
+pub struct StringLiteral(pub String);
+
+impl Display for StringLiteral {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let escaped = self.0.chars().map(|c| escape_char(c, '"')).join("");
+        write!(f, "\"{}\"", escaped)
+    }
+}
+
+/// A character literal such as `'a'`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharLiteral(pub char);
+
+impl Display for CharLiteral {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "'{}'", escape_char(self.0, '\''))
+    }
+}
+
+/// Turns a character back into the way it should be written between quotes
+fn escape_char(c: char, quote: char) -> String {
+    match c {
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        '\0' => "\\0".to_string(),
+        '\\' => "\\\\".to_string(),
+        c if c == quote => format!("\\{}", c),
+        c => c.to_string(),
+    }
 }
 
 /// A wrapper that stores the original number string and its radix, so that any zero-prefixes are kept
@@ -324,6 +413,18 @@ impl Number {
         i64::from_str_radix(&self.data, self.radix).ok().unwrap()
     }
 
+    /// Only decimal numbers may contain a fractional part, e.g. `1.5`
+    pub fn is_integer(&self) -> bool {
+        !self.data.contains('.')
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        match self.is_integer() {
+            true => self.value() as f64,
+            false => self.data.parse().unwrap(),
+        }
+    }
+
     pub fn from_type<S: AsRef<str>>(ty: NumberType, data: S) -> Self {
         let radix = match ty {
             NumberType::Hex => 16,
@@ -342,6 +443,7 @@ bitflags::bitflags! {
     pub struct ExpressionFactorFlags: u8 {
         const NOT = 0b00000001;
         const NEG = 0b00000010;
+        const INVERT = 0b00000100;
     }
 }
 
@@ -357,7 +459,12 @@ impl Display for ExpressionFactorFlags {
         } else {
             ""
         };
-        write!(f, "{}{}", not, neg)
+        let invert = if self.contains(ExpressionFactorFlags::INVERT) {
+            "~"
+        } else {
+            ""
+        };
+        write!(f, "{}{}{}", not, neg, invert)
     }
 }
 
@@ -370,18 +477,26 @@ pub enum Expression {
         flags: ExpressionFactorFlags,
         tag_not: Option<Located<char>>,
         tag_neg: Option<Located<char>>,
+        tag_invert: Option<Located<char>>,
     },
 }
 
 impl Expression {
-    /// Grab the trivia that comes before the first item in the token (e.g. stuff that could be to the right of the previous token)
-    pub fn trivia(&self) -> Option<&Vec<Trivia>> {
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::BinaryExpression(expr) => expr.lhs.span.merge(expr.rhs.span),
+            Expression::Factor { factor, .. } => factor.span,
+        }
+    }
+
+    /// Grab the trivia that comes before the first item in the expression, including the location of the trivia
+    pub fn located_trivia(&self) -> Option<&Located<Vec<Trivia>>> {
         let t = match self {
             Expression::BinaryExpression(expr) => &expr.lhs.trivia,
             Expression::Factor { factor, .. } => &factor.trivia,
         };
 
-        t.as_ref().map(|t| &t.data)
+        t.as_deref()
     }
 }
 
@@ -402,21 +517,37 @@ impl Display for VariableType {
 }
 
 /// The size of a data directive (e.g. `.byte 1, 2, 3`)
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DataSize {
     Byte,
     Word,
+    /// A 24-bit value
+    Long,
     Dword,
+    /// A big-endian word
+    Dbyte,
+    /// A big-endian word (same as [DataSize::Dbyte])
+    Bword,
+    /// Words that are split into a table of low bytes, followed by a table of high bytes
+    LoHi,
+    /// Words that are split into a table of high bytes, followed by a table of low bytes
+    HiLo,
 }
 
 impl DataSize {
     pub fn byte_len(&self) -> usize {
         match self {
             DataSize::Byte => 1,
-            DataSize::Word => 2,
+            DataSize::Word | DataSize::Dbyte | DataSize::Bword => 2,
+            DataSize::LoHi | DataSize::HiLo => 2,
+            DataSize::Long => 3,
             DataSize::Dword => 4,
         }
     }
+
+    pub fn is_big_endian(&self) -> bool {
+        matches!(self, DataSize::Dbyte | DataSize::Bword)
+    }
 }
 
 impl Display for DataSize {
@@ -424,7 +555,12 @@ impl Display for DataSize {
         match self {
             Self::Byte => write!(f, ".byte"),
             Self::Word => write!(f, ".word"),
+            Self::Long => write!(f, ".long"),
             Self::Dword => write!(f, ".dword"),
+            Self::Dbyte => write!(f, ".dbyte"),
+            Self::Bword => write!(f, ".bword"),
+            Self::LoHi => write!(f, ".lohi"),
+            Self::HiLo => write!(f, ".hilo"),
         }
     }
 }
@@ -437,6 +573,13 @@ pub struct Block {
     pub rparen: Located<char>,
 }
 
+impl Block {
+    /// The span of the block, from the opening brace up to and including the closing brace
+    pub fn span(&self) -> Span {
+        self.lparen.span.merge(self.rparen.span)
+    }
+}
+
 /// Tokens that, together, make up all possible source text
 #[derive(Debug, PartialEq)]
 pub enum Token {
@@ -444,10 +587,19 @@ pub enum Token {
         tag: Located<String>,
         value: Located<Expression>,
     },
+    /// An anonymous label (`-` or `+`) that can be referred to by position
+    AnonymousLabel(Located<char>),
     Braces {
         block: Block,
         scope: Identifier,
     },
+    Charmap {
+        tag: Located<String>,
+        id: Located<Identifier>,
+        chars: Located<StringLiteral>,
+        eq: Located<char>,
+        value: Located<Expression>,
+    },
     Config(Block),
     ConfigPair {
         key: Located<String>,
@@ -463,9 +615,20 @@ pub enum Token {
         id: Located<Identifier>,
         value: Option<Box<Token>>,
     },
+    Encoding {
+        tag: Located<String>,
+        encoding: Located<Identifier>,
+    },
     Eof(Located<()>),
     Error(Located<String>),
     Expression(Expression),
+    /// Repeats a byte a number of times, e.g. `.fill 10, $ff`
+    Fill {
+        tag: Located<String>,
+        count: Located<Expression>,
+        comma: Located<char>,
+        value: Located<Expression>,
+    },
     If {
         tag_if: Located<String>,
         value: Located<Expression>,
@@ -491,11 +654,21 @@ pub enum Token {
         eq: Located<char>,
         value: Located<Expression>,
     },
+    /// Reserves a number of bytes without writing to them, e.g. `.res 10`
+    Reserve {
+        tag: Located<String>,
+        count: Located<Expression>,
+    },
     Segment {
         tag: Located<String>,
         id: Located<Identifier>,
         block: Option<Block>,
     },
+    Text {
+        tag: Located<String>,
+        encoding: Option<Located<Identifier>>,
+        values: Vec<ArgItem>,
+    },
     VariableDefinition {
         ty: Located<VariableType>,
         id: Located<Identifier>,
@@ -507,28 +680,91 @@ pub enum Token {
 impl Token {
     /// Grab the trivia that comes before the first item in the token (e.g. stuff that could be to the right of the previous token)
     pub fn trivia(&self) -> Option<&Vec<Trivia>> {
+        self.located_trivia().map(|t| &t.data)
+    }
+
+    /// Like [Token::trivia], but includes the location of the trivia
+    pub fn located_trivia(&self) -> Option<&Located<Vec<Trivia>>> {
         let t = match self {
             Token::Align { tag, .. } => &tag.trivia,
+            Token::AnonymousLabel(marker) => &marker.trivia,
             Token::Braces { block, .. } => &block.lparen.trivia,
+            Token::Charmap { tag, .. } => &tag.trivia,
             Token::Config(block) => &block.lparen.trivia,
             Token::ConfigPair { key, .. } => &key.trivia,
             Token::Data { size, .. } => &size.trivia,
             Token::Definition { tag, .. } => &tag.trivia,
+            Token::Encoding { tag, .. } => &tag.trivia,
             Token::Eof(empty) => &empty.trivia,
             Token::Error(invalid) => &invalid.trivia,
             Token::Expression(expr) => {
-                return expr.trivia();
+                return expr.located_trivia();
             }
+            Token::Fill { tag, .. } => &tag.trivia,
             Token::If { tag_if, .. } => &tag_if.trivia,
             Token::Instruction(i) => &i.mnemonic.trivia,
             Token::Include { tag, .. } => &tag.trivia,
             Token::Label { id, .. } => &id.trivia,
             Token::ProgramCounterDefinition { star, .. } => &star.trivia,
+            Token::Reserve { tag, .. } => &tag.trivia,
             Token::Segment { tag, .. } => &tag.trivia,
+            Token::Text { tag, .. } => &tag.trivia,
             Token::VariableDefinition { ty, .. } => &ty.trivia,
         };
 
-        t.as_ref().map(|t| &t.data)
+        t.as_deref()
+    }
+
+    /// The span of the entire token
+    pub fn span(&self) -> Span {
+        match self {
+            Token::Align { tag, value } => tag.span.merge(value.span),
+            Token::AnonymousLabel(marker) => marker.span,
+            Token::Braces { block, .. } | Token::Config(block) => block.span(),
+            Token::Charmap { tag, value, .. } => tag.span.merge(value.span),
+            Token::ConfigPair { key, value, .. } => key.span.merge(value.span),
+            Token::Data { values, size } => match values.last() {
+                Some((expr, _)) => size.span.merge(expr.span),
+                None => size.span,
+            },
+            Token::Definition { tag, id, value } => match value.as_deref() {
+                Some(value) => tag.span.merge(value.span()),
+                None => tag.span.merge(id.span),
+            },
+            Token::Encoding { tag, encoding } => tag.span.merge(encoding.span),
+            Token::Eof(empty) => empty.span,
+            Token::Error(invalid) => invalid.span,
+            Token::Expression(expr) => expr.span(),
+            Token::Fill { tag, value, .. } => tag.span.merge(value.span),
+            Token::If {
+                tag_if, if_, else_, ..
+            } => match else_ {
+                Some(else_) => tag_if.span.merge(else_.span()),
+                None => tag_if.span.merge(if_.span()),
+            },
+            Token::Include { tag, filename, .. } => tag.span.merge(filename.span),
+            Token::Instruction(i) => i.span(),
+            Token::Label { id, colon, block } => match block {
+                Some(block) => id.span.merge(block.span()),
+                None => id.span.merge(colon.span),
+            },
+            Token::ProgramCounterDefinition { star, value, .. } => star.span.merge(value.span),
+            Token::Reserve { tag, count } => tag.span.merge(count.span),
+            Token::Segment { tag, id, block } => match block {
+                Some(block) => tag.span.merge(block.span()),
+                None => tag.span.merge(id.span),
+            },
+            Token::Text {
+                tag,
+                encoding,
+                values,
+            } => match (values.last(), encoding) {
+                (Some((expr, _)), _) => tag.span.merge(expr.span),
+                (None, Some(encoding)) => tag.span.merge(encoding.span),
+                (None, None) => tag.span,
+            },
+            Token::VariableDefinition { ty, value, .. } => ty.span.merge(value.span),
+        }
     }
 
     pub(crate) fn as_config_map(&self) -> ConfigMap {
@@ -668,6 +904,8 @@ impl Display for ExpressionFactor {
                 write!(f, "{}{}", modifier, path)
             }
             Self::Number { ty, value } => write!(f, "{}{}", ty, value),
+            Self::StringLiteral(s) => write!(f, "{}", s),
+            Self::CharLiteral(c) => write!(f, "{}", c),
         }
     }
 }
@@ -721,6 +959,7 @@ impl Display for Expression {
                 factor,
                 tag_not,
                 tag_neg,
+                tag_invert,
                 ..
             } => {
                 let not = match tag_not {
@@ -731,7 +970,11 @@ impl Display for Expression {
                     Some(n) => format!("{}", n),
                     None => "".to_string(),
                 };
-                write!(f, "{}{}{}", not, neg, factor)
+                let invert = match tag_invert {
+                    Some(n) => format!("{}", n),
+                    None => "".to_string(),
+                };
+                write!(f, "{}{}{}{}", not, neg, invert, factor)
             }
         }
     }
@@ -755,9 +998,27 @@ impl Display for Token {
             Token::Align { tag, value } => {
                 write!(f, "{}{}", format!("{}", tag).to_uppercase(), value)
             }
+            Token::AnonymousLabel(marker) => write!(f, "{}", marker),
             Token::Braces { block, .. } | Token::Config(block) => {
                 write!(f, "{}", block)
             }
+            Token::Charmap {
+                tag,
+                id,
+                chars,
+                eq,
+                value,
+            } => {
+                write!(
+                    f,
+                    "{}{}{}{}{}",
+                    format!("{}", tag).to_uppercase(),
+                    id,
+                    chars,
+                    eq,
+                    value
+                )
+            }
             Token::ConfigPair { key, eq, value } => {
                 write!(f, "{}{}{}", key, eq, value)
             }
@@ -776,6 +1037,9 @@ impl Display for Token {
                     .unwrap_or_else(|| "".to_string());
                 write!(f, "{}{}{}", format!("{}", tag).to_uppercase(), id, value)
             }
+            Token::Encoding { tag, encoding } => {
+                write!(f, "{}{}", format!("{}", tag).to_uppercase(), encoding)
+            }
             Token::Eof(triv) => {
                 write!(f, "{}", format_trivia(&triv.trivia))
             }
@@ -783,6 +1047,21 @@ impl Display for Token {
                 write!(f, "{}", str)
             }
             Token::Expression(e) => write!(f, "{}", e),
+            Token::Fill {
+                tag,
+                count,
+                comma,
+                value,
+            } => {
+                write!(
+                    f,
+                    "{}{}{}{}",
+                    format!("{}", tag).to_uppercase(),
+                    count,
+                    comma,
+                    value
+                )
+            }
             Token::If {
                 tag_if,
                 value,
@@ -867,6 +1146,9 @@ impl Display for Token {
             Token::ProgramCounterDefinition { star, eq, value } => {
                 write!(f, "{}{}{}", star, eq, value)
             }
+            Token::Reserve { tag, count } => {
+                write!(f, "{}{}", format!("{}", tag).to_uppercase(), count)
+            }
             Token::Segment { tag, id, block } => {
                 let block = match block {
                     Some(i) => format!("{}", i),
@@ -874,6 +1156,23 @@ impl Display for Token {
                 };
                 write!(f, "{}{}{}", format!("{}", tag).to_uppercase(), id, block)
             }
+            Token::Text {
+                tag,
+                encoding,
+                values,
+            } => {
+                let encoding = match encoding {
+                    Some(e) => format!("{}", e),
+                    None => "".to_string(),
+                };
+                write!(
+                    f,
+                    "{}{}{}",
+                    format!("{}", tag).to_uppercase(),
+                    encoding,
+                    format_arglist(values)
+                )
+            }
             Token::VariableDefinition { ty, id, eq, value } => {
                 write!(
                     f,
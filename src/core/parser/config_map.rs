@@ -81,6 +81,7 @@ pub struct ConfigMapValidatorBuilder {
     allowed: HashSet<String>,
     required: HashSet<String>,
     required_single: HashSet<String>,
+    required_one_of: Vec<Vec<String>>,
 }
 
 impl Default for ConfigMapValidatorBuilder {
@@ -95,6 +96,7 @@ impl ConfigMapValidatorBuilder {
             allowed: HashSet::new(),
             required: HashSet::new(),
             required_single: HashSet::new(),
+            required_one_of: vec![],
         }
     }
 
@@ -109,6 +111,21 @@ impl ConfigMapValidatorBuilder {
         self
     }
 
+    /// Requires exactly one of the provided keys to be present
+    pub fn require_one_of(mut self, keys: &[&str]) -> Self {
+        self.allowed.extend(keys.iter().map(|key| key.to_string()));
+        self.required_one_of
+            .push(keys.iter().map(|key| key.to_string()).collect());
+        self
+    }
+
+    /// Allows a key that, if present, must contain a single identifier
+    pub fn allowed_single_value(mut self, key: &str) -> Self {
+        self.allowed.insert(key.into());
+        self.required_single.insert(key.into());
+        self
+    }
+
     pub fn require_single_value(mut self, key: &str) -> Self {
         self.allowed.insert(key.into());
         self.required.insert(key.into());
@@ -130,6 +147,29 @@ impl ConfigMapValidatorBuilder {
             })
             .collect_vec();
 
+        // Check if exactly one key of every group of mutually exclusive keys is present
+        let required_one_of_fields = self
+            .required_one_of
+            .iter()
+            .filter_map(|keys| {
+                let present = keys
+                    .iter()
+                    .filter(|key| cfg.items.contains_key(key.as_str()))
+                    .collect_vec();
+                match present.len() {
+                    0 => Some(ParseError {
+                        span: *span,
+                        message: format!("required field: {}", keys.join(" or ")),
+                    }),
+                    1 => None,
+                    _ => Some(ParseError {
+                        span: *span,
+                        message: format!("conflicting fields: {}", present.iter().join(", ")),
+                    }),
+                }
+            })
+            .collect_vec();
+
         // Check if the provided keys are present and only contain a single identifier (and no deeper nested path).
         // If not, errors will be generated based on the provided span.
         let required_single_fields = self
@@ -164,6 +204,7 @@ impl ConfigMapValidatorBuilder {
             .collect_vec();
 
         let mut result = required_fields;
+        result.extend(required_one_of_fields);
         result.extend(required_single_fields);
         result.extend(incorrect_fields);
         result
@@ -14,6 +14,11 @@ impl Identifier {
         Identifier::new(format!("$$scope_{}", index))
     }
 
+    /// The name of the `index`th anonymous label in a scope, where `direction` is either `-` or `+`
+    pub fn anonymous_label(direction: char, index: usize) -> Self {
+        Identifier::new(format!("$$anonymous{}{}", direction, index))
+    }
+
     pub fn value(&self) -> &str {
         &self.0
     }
@@ -122,6 +127,10 @@ impl IdentifierPath {
         self.0.len()
     }
 
+    pub fn parts(&self) -> &[Identifier] {
+        &self.0
+    }
+
     pub fn single(&self) -> &Identifier {
         assert_eq!(self.len(), 1);
         self.0.first().unwrap()
@@ -3,10 +3,12 @@ use codemap::Span;
 use itertools::Itertools;
 use nom::branch::alt;
 use nom::bytes::complete::{is_a, is_not, tag, tag_no_case, take, take_till, take_till1};
-use nom::character::complete::{alpha1, alphanumeric1, anychar, char, hex_digit1, none_of, space1};
-use nom::combinator::{all_consuming, map, not, opt, recognize, rest};
+use nom::character::complete::{
+    alpha1, alphanumeric1, anychar, char, hex_digit1, none_of, one_of, space1,
+};
+use nom::combinator::{all_consuming, eof as end_of_input, map, not, opt, peek, recognize, rest};
 use nom::multi::{many0, many1, separated_list1};
-use nom::sequence::{pair, tuple};
+use nom::sequence::{pair, preceded, tuple};
 use nom::InputTake;
 use std::path::Path;
 use std::rc::Rc;
@@ -227,11 +229,21 @@ fn identifier_name(input: LocatedSpan) -> IResult<Identifier> {
     )(input)
 }
 
-/// Tries to parse a scope identifier ('-' or '+', not followed by any other alphanumeric)
+/// Determines if a string can be used as the name of a symbol
+pub fn is_valid_identifier(name: &str) -> bool {
+    let state = State::new("", name);
+    let input = LocatedSpan::new_extra(name, state);
+    all_consuming(identifier_name)(input).is_ok() && !name.eq_ignore_ascii_case("super")
+}
+
+/// Tries to parse an anonymous label reference (one or more '-' or '+', not followed by any other alphanumeric)
 fn identifier_scope(input: LocatedSpan) -> IResult<Identifier> {
     map_once(
-        tuple((alt((char('-'), char('+'))), not(alphanumeric1))),
-        move |(id, _)| Identifier::new(id.to_string()),
+        tuple((
+            alt((recognize(many1(char('-'))), recognize(many1(char('+'))))),
+            not(alphanumeric1),
+        )),
+        move |(id, _): (LocatedSpan, _)| Identifier::new(id.fragment().to_string()),
     )(input)
 }
 
@@ -411,6 +423,14 @@ fn error(input: LocatedSpan) -> IResult<Token> {
     )(input)
 }
 
+/// Tries to parse an anonymous label, which is a `-` or `+` at the start of a statement
+fn anonymous_label(input: LocatedSpan) -> IResult<Token> {
+    map_once(
+        tuple((ws(one_of("-+")), not(one_of("-+")))),
+        move |(marker, _)| Token::AnonymousLabel(marker),
+    )(input)
+}
+
 /// Tries to parse a label in the form of `foo:`
 fn label(input: LocatedSpan) -> IResult<Token> {
     map_once(
@@ -426,7 +446,12 @@ fn data(input: LocatedSpan) -> IResult<Token> {
             alt((
                 map(ws(tag_no_case(".byte")), |t| t.map(|_| DataSize::Byte)),
                 map(ws(tag_no_case(".word")), |t| t.map(|_| DataSize::Word)),
+                map(ws(tag_no_case(".long")), |t| t.map(|_| DataSize::Long)),
                 map(ws(tag_no_case(".dword")), |t| t.map(|_| DataSize::Dword)),
+                map(ws(tag_no_case(".dbyte")), |t| t.map(|_| DataSize::Dbyte)),
+                map(ws(tag_no_case(".bword")), |t| t.map(|_| DataSize::Bword)),
+                map(ws(tag_no_case(".lohi")), |t| t.map(|_| DataSize::LoHi)),
+                map(ws(tag_no_case(".hilo")), |t| t.map(|_| DataSize::HiLo)),
             )),
             expect(arg_list, "expected expression"),
         )),
@@ -437,6 +462,56 @@ fn data(input: LocatedSpan) -> IResult<Token> {
     )(input)
 }
 
+/// Tries to parse a text statement such as `.text petscii_upper "hello"`
+fn text(input: LocatedSpan) -> IResult<Token> {
+    map_once(
+        tuple((
+            ws(tag_no_case(".text")),
+            opt(map(
+                tuple((ws(identifier_name), peek(ws(one_of("\"'"))))),
+                |(encoding, _)| encoding,
+            )),
+            expect(arg_list, "expected expression"),
+        )),
+        move |(tag, encoding, values)| Token::Text {
+            tag: tag.map_into(|_| ".text".into()),
+            encoding,
+            values: values.unwrap_or_default(),
+        },
+    )(input)
+}
+
+/// Tries to parse an encoding directive such as `.encoding screen_upper`
+fn encoding(input: LocatedSpan) -> IResult<Token> {
+    map_once(
+        tuple((ws(tag_no_case(".encoding")), ws(identifier_name))),
+        move |(tag, encoding)| Token::Encoding {
+            tag: tag.map_into(|_| ".encoding".into()),
+            encoding,
+        },
+    )(input)
+}
+
+/// Tries to parse a character map definition such as `.charmap digits "0123456789" = $30`
+fn charmap(input: LocatedSpan) -> IResult<Token> {
+    map_once(
+        tuple((
+            ws(tag_no_case(".charmap")),
+            ws(identifier_name),
+            ws(string_literal_inner),
+            ws(char('=')),
+            expression,
+        )),
+        move |(tag, id, chars, eq, value)| Token::Charmap {
+            tag: tag.map_into(|_| ".charmap".into()),
+            id,
+            chars,
+            eq,
+            value,
+        },
+    )(input)
+}
+
 #[doc(hidden)]
 fn varconst_impl<'a, 'b>(
     input: LocatedSpan<'a>,
@@ -566,6 +641,35 @@ fn align(input: LocatedSpan) -> IResult<Token> {
     )(input)
 }
 
+/// Tries to parse a fill directive, of the form `.fill 10, $ff`
+fn fill(input: LocatedSpan) -> IResult<Token> {
+    map_once(
+        tuple((
+            ws(tag_no_case(".fill")),
+            ws(expression),
+            ws(char(',')),
+            ws(expression),
+        )),
+        move |(tag, count, comma, value)| Token::Fill {
+            tag: tag.map_into(|_| ".fill".into()),
+            count: count.flatten(),
+            comma,
+            value: value.flatten(),
+        },
+    )(input)
+}
+
+/// Tries to parse a reserve directive, of the form `.res 10`
+fn reserve(input: LocatedSpan) -> IResult<Token> {
+    map_once(
+        tuple((ws(tag_no_case(".res")), ws(expression))),
+        move |(tag, count)| Token::Reserve {
+            tag: tag.map_into(|_| ".res".into()),
+            count: count.flatten(),
+        },
+    )(input)
+}
+
 /// Tries to parse an include directive, of the form `.include "foo.bin"`
 fn include(input: LocatedSpan) -> IResult<Token> {
     let filename = recognize(many1(none_of("\"\r\n")));
@@ -599,10 +703,16 @@ fn statement(input: LocatedSpan) -> IResult<Token> {
         pc_definition,
         config_definition,
         label,
+        anonymous_label,
         data,
+        text,
+        encoding,
+        charmap,
         segment,
         if_,
         align,
+        fill,
+        reserve,
         include,
     ))(input)
 }
@@ -641,7 +751,10 @@ fn number(input: LocatedSpan) -> IResult<Located<ExpressionFactor>> {
                 )),
                 tuple((
                     located(|input| value(NumberType::Dec)(input)),
-                    ws(recognize(many1(is_a("0123456789")))),
+                    ws(recognize(pair(
+                        many1(is_a("0123456789")),
+                        opt(pair(char('.'), many1(is_a("0123456789")))),
+                    ))),
                 )),
             )),
             move |(ty, value)| {
@@ -655,6 +768,62 @@ fn number(input: LocatedSpan) -> IResult<Located<ExpressionFactor>> {
     })(input)
 }
 
+/// Parses a single character inside a quoted string or character literal, resolving escape sequences
+fn quoted_char<'a>(quote: char) -> impl FnMut(LocatedSpan<'a>) -> IResult<'a, char> {
+    move |input| {
+        alt((
+            preceded(
+                char('\\'),
+                alt((
+                    map(char('n'), |_| '\n'),
+                    map(char('r'), |_| '\r'),
+                    map(char('t'), |_| '\t'),
+                    map(char('0'), |_| '\0'),
+                    char('\\'),
+                    char(quote),
+                )),
+            ),
+            none_of_char(quote),
+        ))(input)
+    }
+}
+
+fn none_of_char<'a>(quote: char) -> impl FnMut(LocatedSpan<'a>) -> IResult<'a, char> {
+    move |input: LocatedSpan<'a>| match input.fragment().chars().next() {
+        Some(c) if c != quote && c != '\\' && c != '\r' && c != '\n' => anychar(input),
+        _ => Err(nom::Err::Error(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::NoneOf,
+        ))),
+    }
+}
+
+fn string_literal_inner(input: LocatedSpan) -> IResult<StringLiteral> {
+    map(
+        tuple((char('"'), many0(quoted_char('"')), char('"'))),
+        |(_, chars, _)| StringLiteral(chars.into_iter().collect()),
+    )(input)
+}
+
+/// Parses a string literal, such as `"hello"`
+fn string_literal(input: LocatedSpan) -> IResult<Located<ExpressionFactor>> {
+    located(|input| {
+        map_once(ws(string_literal_inner), move |s| {
+            ExpressionFactor::StringLiteral(s)
+        })(input)
+    })(input)
+}
+
+/// Parses a character literal, such as `'a'`
+fn char_literal(input: LocatedSpan) -> IResult<Located<ExpressionFactor>> {
+    located(|input| {
+        map_once(
+            ws(tuple((char('\''), quoted_char('\''), char('\'')))),
+            move |c| ExpressionFactor::CharLiteral(c.map(|(_, c, _)| CharLiteral(*c))),
+        )(input)
+    })(input)
+}
+
 /// Deals with parentheses encountered in expressions
 fn expression_parens(input: LocatedSpan) -> IResult<Located<ExpressionFactor>> {
     located(|input| {
@@ -732,6 +901,8 @@ fn expression_factor_inner(input: LocatedSpan) -> IResult<Located<ExpressionFact
         identifier_value,
         current_pc,
         expression_parens,
+        string_literal,
+        char_literal,
     ))(input)
 }
 
@@ -745,14 +916,16 @@ fn expression_factor(input: LocatedSpan) -> IResult<Located<Expression>> {
                 flags: ExpressionFactorFlags::empty(),
                 tag_not: None,
                 tag_neg: None,
+                tag_invert: None,
             }),
             map(
                 tuple((
                     opt(ws(char('!'))),
                     opt(ws(char('-'))),
+                    opt(ws(char('~'))),
                     expression_factor_inner,
                 )),
-                move |(tag_not, tag_neg, factor)| {
+                move |(tag_not, tag_neg, tag_invert, factor)| {
                     let mut flags = ExpressionFactorFlags::empty();
                     if tag_not.is_some() {
                         flags.set(ExpressionFactorFlags::NOT, true);
@@ -760,11 +933,15 @@ fn expression_factor(input: LocatedSpan) -> IResult<Located<Expression>> {
                     if tag_neg.is_some() {
                         flags.set(ExpressionFactorFlags::NEG, true);
                     }
+                    if tag_invert.is_some() {
+                        flags.set(ExpressionFactorFlags::INVERT, true);
+                    }
                     Expression::Factor {
                         factor: Box::new(factor),
                         flags,
                         tag_not,
                         tag_neg,
+                        tag_invert,
                     }
                 },
             ),
@@ -772,64 +949,104 @@ fn expression_factor(input: LocatedSpan) -> IResult<Located<Expression>> {
     })(input)
 }
 
-/// Folds back a list of expressions and operations into a single [Expression] token
-fn fold_expressions(
-    initial: Located<Expression>,
-    remainder: Vec<(Located<BinaryOp>, Located<Expression>)>,
-) -> Located<Expression> {
-    remainder.into_iter().fold(initial, |acc, pair| {
-        let (op, expr) = pair;
+/// Parses any binary operator. Longer operators are tried first, so that e.g. `<<` is not parsed as `<`.
+fn binary_operator(input: LocatedSpan) -> IResult<BinaryOp> {
+    alt((
+        alt((
+            map(tag("<<"), |_| BinaryOp::Shl),
+            map(tag(">>"), |_| BinaryOp::Shr),
+            map(tag("=="), |_| BinaryOp::Eq),
+            map(tag("!="), |_| BinaryOp::Ne),
+            map(tag(">="), |_| BinaryOp::GtEq),
+            map(tag("<="), |_| BinaryOp::LtEq),
+            map(tag("&&"), |_| BinaryOp::And),
+            map(tag("||"), |_| BinaryOp::Or),
+        )),
+        alt((
+            map(tag("*"), |_| BinaryOp::Mul),
+            map(tag("/"), |_| BinaryOp::Div),
+            map(tag("%"), |_| BinaryOp::Mod),
+            map(tag("+"), |_| BinaryOp::Add),
+            map(tag("-"), |_| BinaryOp::Sub),
+            map(tag(">"), |_| BinaryOp::Gt),
+            map(tag("<"), |_| BinaryOp::Lt),
+            map(tag("&"), |_| BinaryOp::BitAnd),
+            map(tag("|"), |_| BinaryOp::BitOr),
+            map(tag("^"), |_| BinaryOp::Xor),
+        )),
+    ))(input)
+}
 
-        Located::new(
-            acc.span,
+/// Parses an expression using precedence climbing, only consuming operators that bind at least as tightly as `min_precedence`
+fn expression_with_precedence(
+    input: LocatedSpan,
+    min_precedence: u8,
+) -> IResult<Located<Expression>> {
+    let (mut input, mut lhs) = expression_factor(input)?;
+
+    loop {
+        let (remaining, op) = match ws(binary_operator)(input.clone()) {
+            Ok((remaining, op)) if starts_anonymous_label(&op, remaining.clone()) => break,
+            Ok((remaining, op)) if op.data.precedence() >= min_precedence => (remaining, op),
+            _ => break,
+        };
+
+        // All operators are left-associative, so the right hand side may only contain operators that bind more tightly
+        let (remaining, rhs) = match expression_with_precedence(remaining, op.data.precedence() + 1)
+        {
+            Ok(result) => result,
+            Err(nom::Err::Error(_)) => break,
+            Err(e) => return Err(e),
+        };
+
+        lhs = Located::new(
+            lhs.span.merge(rhs.span),
             Expression::BinaryExpression(BinaryExpression {
                 op,
-                lhs: Box::new(acc),
-                rhs: Box::new(expr),
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
             }),
-        )
-    })
-}
+        );
+        input = remaining;
+    }
 
-/// Parses a term in an expression, containing operators of the highest precedence, e.g. a multiplication
-fn expression_term(input: LocatedSpan) -> IResult<Located<Expression>> {
-    let (input, initial) = expression_factor(input)?;
+    Ok((input, lhs))
+}
 
-    let (input, remainder) = many0(tuple((
-        ws(alt((
-            map(tag("*"), |_| BinaryOp::Mul),
-            map(tag("/"), |_| BinaryOp::Div),
-            map(tag("<<"), |_| BinaryOp::Shl),
-            map(tag(">>"), |_| BinaryOp::Shr),
-            map(tag("^"), |_| BinaryOp::Xor),
-        ))),
-        expression_factor,
-    )))(input)?;
+/// A `-` or `+` at the start of a line is an anonymous label instead of a binary operator, but only if it is
+/// followed by the end of the line or by a statement. Otherwise the expression continues on the next line.
+fn starts_anonymous_label(op: &Located<BinaryOp>, remaining: LocatedSpan) -> bool {
+    let on_new_line = op
+        .trivia
+        .as_ref()
+        .map(|t| t.data.contains(&Trivia::NewLine))
+        .unwrap_or_default();
+    if !on_new_line || !matches!(op.data, BinaryOp::Add | BinaryOp::Sub) {
+        return false;
+    }
 
-    Ok((input, fold_expressions(initial, remainder)))
+    let end_of_line = tuple((
+        many0(trivia_impl()),
+        alt((end_of_input, tag("\n"), tag("\r\n"), tag("}"))),
+    ));
+    let followed_by_statement = preceded(
+        space1,
+        alt((
+            recognize(tuple((
+                alt((mnemonic, implied_mnemonic)),
+                not(alt((alphanumeric1, tag("_")))),
+            ))),
+            recognize(tuple((identifier_name, char(':')))),
+            tag("."),
+            tag("{"),
+        )),
+    );
+    peek(alt((recognize(end_of_line), followed_by_statement)))(remaining).is_ok()
 }
 
-/// Parses a (sub)expression containing operators of the lowest precedence
+/// Parses a (sub)expression, taking operator precedence into account
 pub fn expression(input: LocatedSpan) -> IResult<Located<Expression>> {
-    let (input, initial) = expression_term(input)?;
-
-    let (input, remainder) = many0(tuple((
-        ws(alt((
-            map(tag("+"), |_| BinaryOp::Add),
-            map(tag("-"), |_| BinaryOp::Sub),
-            map(tag("=="), |_| BinaryOp::Eq),
-            map(tag("!="), |_| BinaryOp::Ne),
-            map(tag(">="), |_| BinaryOp::GtEq),
-            map(tag("<="), |_| BinaryOp::LtEq),
-            map(tag(">"), |_| BinaryOp::Gt),
-            map(tag("<"), |_| BinaryOp::Lt),
-            map(tag("&&"), |_| BinaryOp::And),
-            map(tag("||"), |_| BinaryOp::Or),
-        ))),
-        expression_term,
-    )))(input)?;
-
-    Ok((input, fold_expressions(initial, remainder)))
+    expression_with_precedence(input, 0)
 }
 
 /// Parses an input file and returns a hopefully parsed file
@@ -880,6 +1097,73 @@ mod test {
         check("nop\n  nop", "NOP\n  NOP");
     }
 
+    #[test]
+    fn parse_anonymous_labels() {
+        check("- dex\n  bne -", "- DEX\n  BNE -");
+        check("+\nbne --\nbeq ++", "+\nBNE --\nBEQ ++");
+        check("lda #1\n- nop", "LDA #1\n- NOP");
+        check("lda #1 - 2", "LDA #1 - 2");
+        check("- // comment\n  nop", "- // comment\n  NOP");
+        check("{\n  nop\n  -\n}", "{\n  NOP\n  -\n}");
+    }
+
+    #[test]
+    fn parse_expression_continued_on_next_line() {
+        check(".byte 1\n    + 2", ".BYTE 1\n    + 2");
+        check("lda #1\n  - foo", "LDA #1\n  - foo");
+        check("lda #1\n  -2", "LDA #1\n  -2");
+        check(".byte 1\n  - lda_value", ".BYTE 1\n  - lda_value");
+        check(
+            ".define segment {\n  name = a\n  start = $1000\n    + $10\n}",
+            ".DEFINE segment {\n  name = a\n  start = $1000\n    + $10\n}",
+        );
+    }
+
+    #[test]
+    fn parse_anonymous_labels_after_data() {
+        let tokens = |src: &str| {
+            let (tree, error) = parse(&Path::new("test.asm"), src);
+            assert!(error.is_none());
+            tree
+        };
+        assert!(matches!(
+            tokens(".byte 1, 2\n- nop").tokens(),
+            [
+                Token::Data { .. },
+                Token::AnonymousLabel(_),
+                Token::Instruction(_),
+                Token::Eof(_)
+            ]
+        ));
+        assert!(matches!(
+            tokens(".byte 1\n+\n").tokens(),
+            [Token::Data { .. }, Token::AnonymousLabel(_), Token::Eof(_)]
+        ));
+        assert!(matches!(
+            tokens(".byte 1\n- .byte 2").tokens(),
+            [
+                Token::Data { .. },
+                Token::AnonymousLabel(_),
+                Token::Data { .. },
+                Token::Eof(_)
+            ]
+        ));
+        assert!(matches!(
+            tokens(".byte 1\n+ foo: nop").tokens(),
+            [
+                Token::Data { .. },
+                Token::AnonymousLabel(_),
+                Token::Label { .. },
+                Token::Instruction(_),
+                Token::Eof(_)
+            ]
+        ));
+        assert!(matches!(
+            tokens(".byte 1\n- 2").tokens(),
+            [Token::Data { .. }, Token::Eof(_)]
+        ));
+    }
+
     #[test]
     fn parse_expression() {
         check("lda #1 + 2", "LDA #1 + 2");
@@ -892,17 +1176,53 @@ mod test {
         check("lda #1 >> 4", "LDA #1 >> 4");
         check("lda #1 || 2", "LDA #1 || 2");
         check("lda #1 && 2", "LDA #1 && 2");
+        check("lda #1 & 2", "LDA #1 & 2");
+        check("lda #1 | 2", "LDA #1 | 2");
+        check("lda #1 % 2", "LDA #1 % 2");
         check("lda  %11101", "LDA  %11101");
+        check(".const foo = 1.25 * 2", ".CONST foo = 1.25 * 2");
         check(
             "lda  %11101   +   [  $ff  * -12367 ] / foo",
             "LDA  %11101   +   [  $ff  * -12367 ] / foo",
         );
     }
 
+    #[test]
+    fn parse_operator_precedence() {
+        fn explicit(expr: &Expression) -> String {
+            match expr {
+                Expression::BinaryExpression(e) => format!(
+                    "[{} {} {}]",
+                    explicit(&e.lhs.data),
+                    e.op.data,
+                    explicit(&e.rhs.data)
+                ),
+                Expression::Factor { .. } => expr.to_string().trim().to_string(),
+            }
+        }
+        let check_precedence = |src: &str, expected: &str| {
+            assert_eq!(explicit(&invoke(src, expression)), expected);
+        };
+
+        check_precedence("1 + 2 * 3", "[1 + [2 * 3]]");
+        check_precedence("1 * 2 + 3", "[[1 * 2] + 3]");
+        check_precedence("1 - 2 - 3", "[[1 - 2] - 3]");
+        check_precedence("a + 1 == b", "[[a + 1] == b]");
+        check_precedence("x && y == 1", "[x && [y == 1]]");
+        check_precedence("a || b && c", "[a || [b && c]]");
+        check_precedence("1 << 2 + 3", "[1 << [2 + 3]]");
+        check_precedence("a < b == c > d", "[[a < b] == [c > d]]");
+        check_precedence("a | b ^ c & d", "[a | [b ^ [c & d]]]");
+        check_precedence("a & b == c", "[a & [b == c]]");
+        check_precedence("[1 + 2] * 3", "[[1 + 2] * 3]");
+        check_precedence("a % 2 + -b", "[[a % 2] + -b]");
+    }
+
     #[test]
     fn parse_expression_factor_flags() {
         check("lda #-foo", "LDA #-foo");
         check("lda #!foo", "LDA #!foo");
+        check("lda #~foo", "LDA #~foo");
     }
 
     #[test]
@@ -921,6 +1241,17 @@ mod test {
         check("lda   super.a", "LDA   super.a");
     }
 
+    #[test]
+    fn validate_identifiers() {
+        assert!(is_valid_identifier("foo_1"));
+        assert!(is_valid_identifier("_foo"));
+        assert!(!is_valid_identifier("1foo"));
+        assert!(!is_valid_identifier("foo.bar"));
+        assert!(!is_valid_identifier("foo bar"));
+        assert!(!is_valid_identifier(""));
+        assert!(!is_valid_identifier("super"));
+    }
+
     #[test]
     fn parse_identifier_scopes() {
         check("lda -", "LDA -");
@@ -1044,6 +1375,24 @@ mod test {
             ".byte 123\n.word foo\n.dword 12345678\n.word 1 + 2,   3, 4 * 4",
             ".BYTE 123\n.WORD foo\n.DWORD 12345678\n.WORD 1 + 2,   3, 4 * 4",
         );
+        check(
+            ".long 1\n.dbyte 2\n.bword 3\n.lohi a, b\n.hilo a, b",
+            ".LONG 1\n.DBYTE 2\n.BWORD 3\n.LOHI a, b\n.HILO a, b",
+        );
+        check(".fill  10 ,  $ff\n.res 4", ".FILL  10 ,  $ff\n.RES 4");
+    }
+
+    #[test]
+    fn parse_text() {
+        check(
+            ".text \"hello\"\n.text   petscii_upper \"a\\\"b\\n\", 'c'\n.encoding screen_upper",
+            ".TEXT \"hello\"\n.TEXT   petscii_upper \"a\\\"b\\n\", 'c'\n.ENCODING screen_upper",
+        );
+        check(
+            ".charmap digits \"0123\" = $30",
+            ".CHARMAP digits \"0123\" = $30",
+        );
+        check("lda #'\\''", "LDA #'\\''");
     }
 
     #[test]
@@ -0,0 +1,299 @@
+use serde::Deserialize;
+
+use crate::core::codegen::{ProgramCounter, Region, RegionKind};
+
+/// The machine a program is assembled for
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub enum Target {
+    C64,
+    Vic20,
+    C128,
+    Plus4,
+    Pet,
+    Nes,
+    Apple2,
+    Atari8,
+}
+
+/// How the data of a target is written to disk
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// The data, prefixed by its load address
+    Prg,
+    /// Just the data
+    Bin,
+    /// An Atari executable, consisting of a single segment with a start and end address
+    Xex,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Prg => "prg",
+            OutputFormat::Bin => "bin",
+            OutputFormat::Xex => "xex",
+        }
+    }
+}
+
+impl Target {
+    /// Where BASIC programs are loaded, if the machine has BASIC at all
+    pub fn basic_start(&self) -> Option<ProgramCounter> {
+        let start = match self {
+            Target::C64 => 0x0801,
+            Target::Vic20 | Target::Plus4 => 0x1001,
+            Target::C128 => 0x1c01,
+            Target::Pet => 0x0401,
+            Target::Nes | Target::Apple2 | Target::Atari8 => return None,
+        };
+        Some(start.into())
+    }
+
+    /// Where code is placed when no segments have been defined
+    pub fn default_pc(&self) -> ProgramCounter {
+        match self.basic_start() {
+            Some(start) => start,
+            None => match self {
+                Target::Nes => 0x8000.into(),
+                Target::Apple2 => 0x0803.into(),
+                _ => 0x2000.into(),
+            },
+        }
+    }
+
+    /// Segments that are created next to the default segment, when no segments have been defined
+    ///
+    /// Every segment consists of a name, a start address, an end address and whether it is written to the output.
+    pub fn segments(&self) -> Vec<(&'static str, ProgramCounter, ProgramCounter, bool)> {
+        let segments: &[(&str, usize, usize, bool)] = match self {
+            // The zero page locations that are not used by BASIC and the kernal
+            Target::C64 | Target::Vic20 | Target::C128 => &[("zeropage", 0xfb, 0xff, false)],
+            Target::Nes => &[
+                ("zeropage", 0x0000, 0x0100, false),
+                ("vectors", 0xfffa, 0x10000, true),
+            ],
+            Target::Plus4 | Target::Pet | Target::Apple2 | Target::Atari8 => &[],
+        };
+
+        segments
+            .iter()
+            .map(|(name, start, end, write)| (*name, (*start).into(), (*end).into(), *write))
+            .collect()
+    }
+
+    pub fn output_format(&self) -> OutputFormat {
+        match self {
+            Target::Nes | Target::Apple2 => OutputFormat::Bin,
+            Target::Atari8 => OutputFormat::Xex,
+            _ => OutputFormat::Prg,
+        }
+    }
+
+    /// The default memory layout of the machine
+    pub fn regions(&self) -> Vec<(&'static str, Region)> {
+        use RegionKind::*;
+        let regions: &[(&str, usize, usize, RegionKind)] = match self {
+            Target::C64 => &[
+                ("ram", 0x0801, 0xa000, Ram),
+                ("basic_rom", 0xa000, 0xc000, Rom),
+                ("high_ram", 0xc000, 0xd000, Ram),
+                ("io", 0xd000, 0xe000, Io),
+                ("kernal_rom", 0xe000, 0x10000, Rom),
+            ],
+            Target::Vic20 => &[
+                ("ram", 0x1001, 0x1e00, Ram),
+                ("io", 0x9000, 0x9400, Io),
+                ("basic_rom", 0xc000, 0xe000, Rom),
+                ("kernal_rom", 0xe000, 0x10000, Rom),
+            ],
+            Target::C128 => &[
+                ("ram", 0x1c01, 0x4000, Ram),
+                ("basic_rom", 0x4000, 0xc000, Rom),
+                ("io", 0xd000, 0xe000, Io),
+                ("kernal_rom", 0xe000, 0x10000, Rom),
+            ],
+            Target::Plus4 => &[
+                ("ram", 0x1001, 0x8000, Ram),
+                ("basic_rom", 0x8000, 0xc000, Rom),
+                ("kernal_rom", 0xc000, 0xfd00, Rom),
+                ("io", 0xfd00, 0xff40, Io),
+            ],
+            Target::Pet => &[
+                ("ram", 0x0401, 0x8000, Ram),
+                ("basic_rom", 0xb000, 0xe000, Rom),
+                ("io", 0xe800, 0xe900, Io),
+                ("kernal_rom", 0xf000, 0x10000, Rom),
+            ],
+            Target::Nes => &[
+                ("ram", 0x0000, 0x0800, Ram),
+                ("ppu", 0x2000, 0x2008, Io),
+                ("apu", 0x4000, 0x4018, Io),
+                ("prg_rom", 0x8000, 0x10000, Rom),
+            ],
+            Target::Apple2 => &[
+                ("ram", 0x0800, 0xc000, Ram),
+                ("io", 0xc000, 0xc100, Io),
+                ("rom", 0xd000, 0x10000, Rom),
+            ],
+            Target::Atari8 => &[
+                ("ram", 0x2000, 0xa000, Ram),
+                ("io", 0xd000, 0xd800, Io),
+                ("os_rom", 0xd800, 0x10000, Rom),
+            ],
+        };
+
+        regions
+            .iter()
+            .map(|(name, start, end, kind)| {
+                let region = Region {
+                    start: ProgramCounter::new(*start),
+                    end: ProgramCounter::new(*end),
+                    kind: *kind,
+                };
+                (*name, region)
+            })
+            .collect()
+    }
+
+    /// Constants for the hardware registers of the machine, e.g. `VIC.border`
+    pub fn constants(&self) -> Vec<(&'static str, i64)> {
+        let vic = [
+            ("VIC.control1", 0xd011),
+            ("VIC.raster", 0xd012),
+            ("VIC.sprite_enable", 0xd015),
+            ("VIC.control2", 0xd016),
+            ("VIC.memory", 0xd018),
+            ("VIC.irq_status", 0xd019),
+            ("VIC.irq_enable", 0xd01a),
+            ("VIC.border", 0xd020),
+            ("VIC.background", 0xd021),
+        ];
+        let sid = [
+            ("SID.v1_freq", 0xd400),
+            ("SID.v1_pulse", 0xd402),
+            ("SID.v1_control", 0xd404),
+            ("SID.v1_attack_decay", 0xd405),
+            ("SID.v1_sustain_release", 0xd406),
+            ("SID.v2_freq", 0xd407),
+            ("SID.v2_pulse", 0xd409),
+            ("SID.v2_control", 0xd40b),
+            ("SID.v2_attack_decay", 0xd40c),
+            ("SID.v2_sustain_release", 0xd40d),
+            ("SID.v3_freq", 0xd40e),
+            ("SID.v3_pulse", 0xd410),
+            ("SID.v3_control", 0xd412),
+            ("SID.v3_attack_decay", 0xd413),
+            ("SID.v3_sustain_release", 0xd414),
+            ("SID.volume", 0xd418),
+        ];
+        let cia = [
+            ("CIA1.data_a", 0xdc00),
+            ("CIA1.data_b", 0xdc01),
+            ("CIA1.irq_control", 0xdc0d),
+            ("CIA2.data_a", 0xdd00),
+            ("CIA2.data_b", 0xdd01),
+            ("CIA2.irq_control", 0xdd0d),
+        ];
+
+        let constants: Vec<(&str, i64)> = match self {
+            Target::C64 => [&vic[..], &sid[..], &cia[..]].concat(),
+            Target::C128 => [&vic[..], &sid[..], &cia[..], &[("MMU.config", 0xd500)]].concat(),
+            Target::Vic20 => vec![
+                ("VIC.volume", 0x900e),
+                ("VIC.color", 0x900f),
+                ("VIA1.data_b", 0x9110),
+                ("VIA1.data_a", 0x9111),
+                ("VIA2.data_b", 0x9120),
+                ("VIA2.data_a", 0x9121),
+            ],
+            Target::Plus4 => vec![
+                ("TED.control1", 0xff06),
+                ("TED.control2", 0xff07),
+                ("TED.raster", 0xff0b),
+                ("TED.background", 0xff15),
+                ("TED.border", 0xff19),
+            ],
+            Target::Pet => vec![
+                ("PIA1.data_a", 0xe810),
+                ("PIA1.data_b", 0xe812),
+                ("PIA2.data_a", 0xe820),
+                ("PIA2.data_b", 0xe822),
+                ("VIA.data_b", 0xe840),
+                ("VIA.data_a", 0xe841),
+            ],
+            Target::Nes => vec![
+                ("PPU.ctrl", 0x2000),
+                ("PPU.mask", 0x2001),
+                ("PPU.status", 0x2002),
+                ("PPU.oam_addr", 0x2003),
+                ("PPU.oam_data", 0x2004),
+                ("PPU.scroll", 0x2005),
+                ("PPU.addr", 0x2006),
+                ("PPU.data", 0x2007),
+                ("APU.pulse1_control", 0x4000),
+                ("APU.pulse2_control", 0x4004),
+                ("APU.triangle_control", 0x4008),
+                ("APU.noise_control", 0x400c),
+                ("APU.dmc_control", 0x4010),
+                ("APU.oam_dma", 0x4014),
+                ("APU.status", 0x4015),
+                ("APU.joy1", 0x4016),
+                ("APU.joy2", 0x4017),
+            ],
+            Target::Apple2 => vec![
+                ("IO.keyboard", 0xc000),
+                ("IO.keyboard_strobe", 0xc010),
+                ("IO.speaker", 0xc030),
+                ("IO.graphics", 0xc050),
+                ("IO.text", 0xc051),
+                ("IO.page1", 0xc054),
+                ("IO.page2", 0xc055),
+            ],
+            Target::Atari8 => vec![
+                ("GTIA.colpf0", 0xd016),
+                ("GTIA.colpf1", 0xd017),
+                ("GTIA.colpf2", 0xd018),
+                ("GTIA.colpf3", 0xd019),
+                ("GTIA.colbk", 0xd01a),
+                ("POKEY.audf1", 0xd200),
+                ("POKEY.audc1", 0xd201),
+                ("POKEY.random", 0xd20a),
+                ("ANTIC.dmactl", 0xd400),
+                ("ANTIC.wsync", 0xd40a),
+                ("ANTIC.vcount", 0xd40b),
+                ("ANTIC.nmien", 0xd40e),
+            ],
+        };
+        constants
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::codegen::RegionKind;
+    use crate::core::target::Target;
+
+    #[test]
+    fn regions_do_not_overlap() {
+        let targets = [
+            Target::C64,
+            Target::Vic20,
+            Target::C128,
+            Target::Plus4,
+            Target::Pet,
+            Target::Nes,
+            Target::Apple2,
+            Target::Atari8,
+        ];
+        for target in &targets {
+            let regions = target.regions();
+            for (a, (_, ra)) in regions.iter().enumerate() {
+                for (_, rb) in regions.iter().skip(a + 1) {
+                    assert!(ra.end.as_i64() <= rb.start.as_i64(), "{:?}", target);
+                }
+            }
+            assert!(regions.iter().any(|(_, r)| r.kind == RegionKind::Io));
+        }
+    }
+}
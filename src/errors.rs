@@ -18,6 +18,8 @@ pub enum MosError {
         message: String,
     },
     Io(#[from] std::io::Error),
+    /// A language server request that could not be fulfilled, such as renaming a symbol to an invalid name
+    InvalidRequest(String),
     Parser {
         tree: Arc<ParseTree>,
         span: codemap::Span,
@@ -116,6 +118,7 @@ impl MosError {
             MosError::Protocol(err) => format_error(use_color, err),
             MosError::Crossbeam(err) => format_error(use_color, err),
             MosError::BuildError(message) => format_error(use_color, message),
+            MosError::InvalidRequest(message) => format_error(use_color, message),
             MosError::Unknown => format_error(use_color, "unknown error"),
             MosError::Multiple(errors) => errors
                 .iter()
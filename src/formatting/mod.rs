@@ -130,6 +130,17 @@ impl CodeFormatter {
                             return fmt;
                         }
 
+                        // Anonymous labels stay on the same line as the statement that follows them
+                        if let Token::AnonymousLabel(_) = &tokens[token_idx] {
+                            let on_same_line = !next_token
+                                .trivia()
+                                .map(|t| t.contains(&Trivia::NewLine))
+                                .unwrap_or_default();
+                            if on_same_line {
+                                return fmt.spc();
+                            }
+                        }
+
                         let (newline_if_same, newline_if_diff) = match token {
                             Token::If { .. } => (true, true),
                             Token::ProgramCounterDefinition { .. } => (false, false),
@@ -250,7 +261,24 @@ impl CodeFormatter {
     fn format_token(&mut self, token: &Token) -> Fmt {
         match token {
             Token::Align { tag, value } => Fmt::new().push(&tag.data).spc().fmt(self, value),
+            Token::AnonymousLabel(marker) => Fmt::new().fmt(self, marker),
             Token::Braces { block, .. } => Fmt::new().fmt(self, block),
+            Token::Charmap {
+                tag,
+                id,
+                chars,
+                eq,
+                value,
+            } => Fmt::new()
+                .push(&tag.data)
+                .spc()
+                .fmt(self, id)
+                .spc()
+                .fmt(self, chars.map(|c| c.to_string()))
+                .spc()
+                .fmt(self, eq)
+                .spc()
+                .fmt(self, value),
             Token::Config(block) => Fmt::new().fmt(self, block),
             Token::ConfigPair { key, eq, value } => Fmt::new()
                 .push(&key.data)
@@ -268,6 +296,21 @@ impl CodeFormatter {
                 .fmt(self, id)
                 .spc()
                 .fmt(self, value),
+            Token::Fill {
+                tag,
+                count,
+                comma,
+                value,
+            } => Fmt::new()
+                .push(&tag.data)
+                .spc()
+                .fmt(self, count)
+                .fmt(self, comma)
+                .spc()
+                .fmt(self, value),
+            Token::Encoding { tag, encoding } => {
+                Fmt::new().push(&tag.data).spc().fmt(self, encoding)
+            }
             Token::Eof(_) => Fmt::new(),
             Token::Error(invalid) => Fmt::new()
                 .push(format_trivia(&invalid.trivia))
@@ -316,6 +359,7 @@ impl CodeFormatter {
                 .fmt(self, colon)
                 .spc()
                 .fmt(self, block),
+            Token::Reserve { tag, count } => Fmt::new().push(&tag.data).spc().fmt(self, count),
             Token::ProgramCounterDefinition { star, eq, value } => Fmt::new()
                 .push(&star.data.to_string())
                 .spc()
@@ -328,6 +372,16 @@ impl CodeFormatter {
                 .fmt(self, id)
                 .spc_if_next()
                 .fmt(self, block),
+            Token::Text {
+                tag,
+                encoding,
+                values,
+            } => Fmt::new()
+                .push(&tag.data)
+                .spc()
+                .fmt(self, encoding)
+                .spc_if_next()
+                .fmt(self, values),
             Token::VariableDefinition { ty, id, eq, value } => Fmt::new()
                 .push(&ty.data.to_string())
                 .spc()
@@ -385,9 +439,11 @@ impl CodeFormatter {
                 flags: _,
                 tag_not,
                 tag_neg,
+                tag_invert,
             } => Fmt::new()
                 .fmt(self, tag_not)
                 .fmt(self, tag_neg)
+                .fmt(self, tag_invert)
                 .fmt(self, factor),
             Expression::BinaryExpression(b) => Fmt::new()
                 .fmt(self, &b.lhs)
@@ -424,6 +480,8 @@ impl CodeFormatter {
             ExpressionFactor::Number { ty, value } => Fmt::new()
                 .fmt(self, ty.map(|t| t.to_string()))
                 .fmt(self, value.map(|v| v.to_string())),
+            ExpressionFactor::StringLiteral(s) => Fmt::new().fmt(self, s.map(|s| s.to_string())),
+            ExpressionFactor::CharLiteral(c) => Fmt::new().fmt(self, c.map(|c| c.to_string())),
         }
     }
 
@@ -595,6 +653,14 @@ impl Formattable for &Located<Identifier> {
     }
 }
 
+impl Formattable for &Option<Located<Identifier>> {
+    fn format(&self, formatter: &mut CodeFormatter) -> Fmt {
+        self.as_ref()
+            .map(|id| formatter.format_located(&id.map(|i| i.to_string())))
+            .unwrap_or_default()
+    }
+}
+
 impl Formattable for &Located<&str> {
     fn format(&self, formatter: &mut CodeFormatter) -> Fmt {
         formatter.format_located(self)
@@ -1,17 +1,21 @@
+use crate::core::codegen::{resolve_symbol_path, CodegenContext};
 use crate::core::parser::{
-    Expression, ExpressionFactor, IdentifierPath, Located, ParseTree, Token,
+    Expression, ExpressionFactor, Identifier, IdentifierPath, Located, ParseTree, Token,
 };
 use crate::errors::MosError;
 use codemap::{File, Span, SpanLoc};
 use lsp_types::{Position, Url};
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 pub struct Analysis {
     pub tree: Arc<ParseTree>,
     pub error: Option<MosError>,
     pub definitions: DefinitionMap,
+    /// The result of code generation. Only available if the source could be parsed without errors.
+    pub codegen: Option<CodegenContext>,
 }
 
 #[derive(Clone)]
@@ -95,6 +99,8 @@ impl From<AnalysisSpan> for lsp_types::DocumentHighlight {
 pub struct DefinitionMap {
     tree: Arc<ParseTree>,
     map: HashMap<IdentifierPath, Definition>,
+    /// The filenames of all `.include` directives, resolved relative to the file they are used in
+    includes: Vec<(Span, PathBuf)>,
 }
 
 impl DefinitionMap {
@@ -102,6 +108,7 @@ impl DefinitionMap {
         let mut d = Self {
             tree,
             map: HashMap::new(),
+            includes: vec![],
         };
 
         DefinitionGenerator::generate(&mut d);
@@ -114,12 +121,21 @@ impl DefinitionMap {
     }
 
     pub fn find(&self, file: &str, pos: Position) -> Option<&Definition> {
-        for def in self.map.values() {
+        self.find_with_path(file, pos).map(|(_, def)| def)
+    }
+
+    /// Finds the definition at the provided position, together with the full path of the symbol it defines
+    pub fn find_with_path(
+        &self,
+        file: &str,
+        pos: Position,
+    ) -> Option<(&IdentifierPath, &Definition)> {
+        for (path, def) in &self.map {
             if let Some(def_location) = &def.location {
                 if self.span_contains(file, def_location, pos)
                     || def.usages.iter().any(|s| self.span_contains(file, s, pos))
                 {
-                    return Some(def);
+                    return Some((path, def));
                 }
             }
         }
@@ -127,6 +143,23 @@ impl DefinitionMap {
         None
     }
 
+    /// Gets the definition of a symbol, but only if the symbol is actually defined somewhere
+    pub fn get(&self, path: &IdentifierPath) -> Option<&Definition> {
+        self.map.get(path).filter(|def| def.location.is_some())
+    }
+
+    /// Iterates over all symbols that are actually defined somewhere
+    pub fn iter(&self) -> impl Iterator<Item = (&IdentifierPath, &Definition)> {
+        self.map.iter().filter(|(_, def)| def.location.is_some())
+    }
+
+    pub fn find_include(&self, file: &str, pos: Position) -> Option<&PathBuf> {
+        self.includes
+            .iter()
+            .find(|(span, _)| self.span_contains(file, span, pos))
+            .map(|(_, path)| path)
+    }
+
     fn span_contains(&self, file: &str, span: &Span, pos: Position) -> bool {
         let loc = self.tree.code_map().look_up_span(*span);
         loc.file.name() == file
@@ -159,13 +192,18 @@ impl Definition {
 }
 
 impl Analysis {
-    pub fn new(tree: Arc<ParseTree>, error: Option<MosError>) -> Self {
+    pub fn new(
+        tree: Arc<ParseTree>,
+        error: Option<MosError>,
+        codegen: Option<CodegenContext>,
+    ) -> Self {
         let definitions = DefinitionMap::new(tree.clone());
 
         Self {
             tree,
             error,
             definitions,
+            codegen,
         }
     }
 
@@ -176,11 +214,34 @@ impl Analysis {
     pub fn find(&self, file: &str, pos: Position) -> Option<&Definition> {
         self.definitions.find(&file, pos)
     }
+
+    /// Finds the definition at the provided position, together with the full path of the symbol it defines
+    pub fn find_with_path(
+        &self,
+        file: &str,
+        pos: Position,
+    ) -> Option<(&IdentifierPath, &Definition)> {
+        self.definitions.find_with_path(file, pos)
+    }
+
+    /// Finds the file that is included by an `.include` directive at the provided position
+    pub fn find_include(&self, file: &str, pos: Position) -> Option<&PathBuf> {
+        self.definitions.find_include(file, pos)
+    }
+}
+
+/// Definitions are generated in two passes: first all definitions are registered, so that usages can be resolved
+/// to their definitions in the second pass (also when they are forward references).
+#[derive(Clone, Copy, PartialEq)]
+enum Pass {
+    Definitions,
+    Usages,
 }
 
 struct DefinitionGenerator<'a> {
     defs: &'a mut DefinitionMap,
     scope: IdentifierPath,
+    pass: Pass,
 }
 
 impl<'a> DefinitionGenerator<'a> {
@@ -189,19 +250,54 @@ impl<'a> DefinitionGenerator<'a> {
         let mut gen = DefinitionGenerator {
             defs,
             scope: IdentifierPath::empty(),
+            pass: Pass::Definitions,
         };
         gen.gen_def_tokens(tree.tokens());
+        gen.pass = Pass::Usages;
+        gen.gen_def_tokens(tree.tokens());
     }
 
     fn gen_def_tokens(&mut self, tokens: &[Token]) {
         tokens.iter().for_each(|token| self.gen_def_token(token));
     }
 
+    fn gen_def_scoped_tokens(&mut self, scope: &Identifier, tokens: &[Token]) {
+        self.scope.push(scope);
+        self.gen_def_tokens(tokens);
+        self.scope.pop();
+    }
+
     fn gen_def_token(&mut self, token: &Token) {
         match token {
-            Token::Braces { block, .. } | Token::Config(block) => self.gen_def_tokens(&block.inner),
-            Token::If { value, .. } => {
+            Token::Align { value, .. }
+            | Token::ProgramCounterDefinition { value, .. }
+            | Token::Reserve { count: value, .. } => {
+                self.gen_def_expression(value);
+            }
+            Token::Fill { count, value, .. } => {
+                self.gen_def_expression(count);
+                self.gen_def_expression(value);
+            }
+            Token::Braces { block, scope } => self.gen_def_scoped_tokens(scope, &block.inner),
+            Token::Charmap { value, .. } => self.gen_def_expression(value),
+            Token::Data { values, .. } | Token::Text { values, .. } => {
+                for (value, _) in values {
+                    self.gen_def_expression(value);
+                }
+            }
+            Token::If {
+                value,
+                if_,
+                if_scope,
+                else_,
+                else_scope,
+                ..
+            } => {
                 self.gen_def_expression(value);
+                self.gen_def_scoped_tokens(if_scope, &if_.inner);
+                if let Some(else_) = else_ {
+                    self.gen_def_scoped_tokens(else_scope, &else_.inner);
+                }
             }
             Token::Instruction(i) => {
                 if let Some(o) = &i.operand {
@@ -209,27 +305,97 @@ impl<'a> DefinitionGenerator<'a> {
                 }
             }
             Token::Label { id, block, .. } => {
-                self.defs
-                    .get_or_create_mut(&self.scope.join(&id.data))
-                    .location = Some(id.span);
+                self.gen_def_location(self.scope.join(&id.data), id.span);
 
                 if let Some(b) = block {
-                    self.scope.push(&id.data);
-                    self.gen_def_tokens(&b.inner);
-                    self.scope.pop();
+                    self.gen_def_scoped_tokens(&id.data, &b.inner);
                 }
             }
-            Token::VariableDefinition { id, .. } => {
-                self.defs
-                    .get_or_create_mut(&self.scope.join(&id.data))
-                    .location = Some(id.span);
+            Token::VariableDefinition { id, value, .. } => {
+                self.gen_def_location(self.scope.join(&id.data), id.span);
+                self.gen_def_expression(value);
+            }
+            Token::Definition {
+                id,
+                value: Some(value),
+                ..
+            } if id.data.value() == "segment" => self.gen_def_config(value, segment_path),
+            Token::Definition {
+                id,
+                value: Some(value),
+                ..
+            } if id.data.value() == "region" => self.gen_def_config(value, region_path),
+            Token::Segment { id, block, .. } => {
+                self.gen_def_usage(segment_path(&id.data), id.span);
+
+                if let Some(b) = block {
+                    self.gen_def_scoped_tokens(&id.data, &b.inner);
+                }
+            }
+            Token::Include { filename, .. } if self.pass == Pass::Definitions => {
+                let source_file = self.defs.tree.code_map().look_up_span(filename.span).file;
+                let path = match Path::new(source_file.name()).parent() {
+                    Some(parent) => parent.join(&filename.data),
+                    None => PathBuf::from(&filename.data),
+                };
+                self.defs.includes.push((filename.span, path));
             }
             _ => (),
         }
     }
 
+    /// Generates definitions for a configuration map that defines something that can be referred to by its `name`
+    fn gen_def_config(&mut self, value: &Token, path: fn(&Identifier) -> IdentifierPath) {
+        let block = match value {
+            Token::Config(block) => block,
+            _ => return,
+        };
+
+        for pair in &block.inner {
+            if let Token::ConfigPair { key, value, .. } = pair {
+                match (key.data.as_str(), &value.data) {
+                    (key @ ("name" | "after" | "region"), token) => {
+                        if let Some(ExpressionFactor::IdentifierValue { path: id, .. }) =
+                            token.try_as_factor()
+                        {
+                            if id.data.len() == 1 {
+                                let id_path = match key {
+                                    "after" => segment_path(id.data.single()),
+                                    "region" => region_path(id.data.single()),
+                                    _ => path(id.data.single()),
+                                };
+                                match key {
+                                    "name" => self.gen_def_location(id_path, id.span),
+                                    _ => self.gen_def_usage(id_path, id.span),
+                                }
+                            }
+                        }
+                    }
+                    (_, Token::Expression(expr)) => self.gen_def_expression_data(expr),
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    fn gen_def_location(&mut self, path: IdentifierPath, span: Span) {
+        if self.pass == Pass::Definitions {
+            self.defs.get_or_create_mut(&path).location = Some(span);
+        }
+    }
+
+    fn gen_def_usage(&mut self, path: IdentifierPath, span: Span) {
+        if self.pass == Pass::Usages {
+            self.defs.get_or_create_mut(&path).usages.push(span);
+        }
+    }
+
     fn gen_def_expression(&mut self, expr: &Located<Expression>) {
-        match &expr.data {
+        self.gen_def_expression_data(&expr.data);
+    }
+
+    fn gen_def_expression_data(&mut self, expr: &Expression) {
+        match expr {
             Expression::Factor { factor, .. } => self.gen_def_expression_factor(&factor),
             Expression::BinaryExpression(bexp) => {
                 self.gen_def_expression(&bexp.lhs);
@@ -243,16 +409,51 @@ impl<'a> DefinitionGenerator<'a> {
             ExpressionFactor::ExprParens { inner, .. } => {
                 self.gen_def_expression(inner);
             }
+            ExpressionFactor::FunctionCall { args, .. } => {
+                for (arg, _) in args {
+                    self.gen_def_expression(arg);
+                }
+            }
             ExpressionFactor::IdentifierValue { path, .. } => {
-                let full_path = self.scope.join(&path.data).canonicalize();
-                let def = self.defs.get_or_create_mut(&full_path);
-                def.usages.push(path.span);
+                // References to segment symbols, e.g. 'segments.foo.start', are usages of the segment itself
+                if let [segments, name, _] = path.data.parts() {
+                    if segments.value() == "segments" {
+                        let start = segments.value().len() as u64 + 1;
+                        let span = path.span.subspan(start, start + name.value().len() as u64);
+                        self.gen_def_usage(segment_path(name), span);
+                        return;
+                    }
+                }
+
+                // Resolve the symbol in the same way as code generation does. If it can't be resolved, the
+                // usage is still registered so that all usages of the unknown symbol can be found.
+                let defs = &self.defs;
+                let full_path = resolve_symbol_path(&self.scope, &path.data, true, |p| {
+                    matches!(
+                        defs.map.get(p),
+                        Some(Definition {
+                            location: Some(_),
+                            ..
+                        })
+                    )
+                })
+                .unwrap_or_else(|| self.scope.join(&path.data).canonicalize());
+                self.gen_def_usage(full_path, path.span);
             }
             _ => (),
         }
     }
 }
 
+/// Segments are stored in the definition map as if they were symbols in the 'segments' scope
+fn segment_path(name: &Identifier) -> IdentifierPath {
+    IdentifierPath::from("segments").join(name)
+}
+
+fn region_path(name: &Identifier) -> IdentifierPath {
+    IdentifierPath::from("regions").join(name)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::core::parser::parse;
@@ -282,8 +483,102 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn can_find_symbols_in_outer_scopes() -> MosResult<()> {
+        let analysis = analysis("foo: nop\nbar: {\n  { lda foo }\n}");
+        let def = analysis.find("test.asm", Position::new(2, 9)).unwrap();
+        assert_eq!(
+            analysis.look_up_span(def.location.unwrap()).to_string(),
+            "test.asm:1:1: 1:4"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn can_find_usages_in_all_expressions() -> MosResult<()> {
+        let analysis = analysis(
+            ".const foo = 1\n.var bar = foo\n.byte foo\n.align foo\n* = foo\n.if foo { nop }\n.define segment {\n  name = a\n  start = foo\n}\nlda defined(foo)",
+        );
+        let def = analysis.find("test.asm", Position::new(0, 8)).unwrap();
+        let usages = def
+            .usages
+            .iter()
+            .map(|span| analysis.look_up_span(*span).to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            usages,
+            vec![
+                "test.asm:2:12: 2:15",
+                "test.asm:3:7: 3:10",
+                "test.asm:4:8: 4:11",
+                "test.asm:5:5: 5:8",
+                "test.asm:6:5: 6:8",
+                "test.asm:9:11: 9:14",
+                "test.asm:11:13: 11:16"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn can_find_segment_definitions() -> MosResult<()> {
+        let analysis = analysis(
+            ".define segment {\n  name = foo\n  start = $1000\n}\n.segment foo { nop }\nlda #<segments.foo.start",
+        );
+        let def = analysis.find("test.asm", Position::new(4, 10)).unwrap();
+        assert_eq!(
+            analysis.look_up_span(def.location.unwrap()).to_string(),
+            "test.asm:2:10: 2:13"
+        );
+        let usages = def
+            .usages
+            .iter()
+            .map(|span| analysis.look_up_span(*span).to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(usages, vec!["test.asm:5:10: 5:13", "test.asm:6:16: 6:19"]);
+
+        let def = analysis.find("test.asm", Position::new(5, 17)).unwrap();
+        assert_eq!(
+            analysis.look_up_span(def.location.unwrap()).to_string(),
+            "test.asm:2:10: 2:13"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn can_find_region_definitions() -> MosResult<()> {
+        let analysis = analysis(
+            ".define region { name = foo start = 0 end = 1 kind = ram }\n.define segment { name = bar start = 0 region = foo }\n.define segment { name = baz after = bar }",
+        );
+        let def = analysis.find("test.asm", Position::new(1, 48)).unwrap();
+        assert_eq!(
+            analysis.look_up_span(def.location.unwrap()).to_string(),
+            "test.asm:1:25: 1:28"
+        );
+
+        let def = analysis.find("test.asm", Position::new(2, 37)).unwrap();
+        assert_eq!(
+            analysis.look_up_span(def.location.unwrap()).to_string(),
+            "test.asm:2:26: 2:29"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn can_find_includes() -> MosResult<()> {
+        let analysis = analysis("nop\n.include \"foo.bin\"");
+        assert_eq!(
+            analysis
+                .find_include("test.asm", Position::new(1, 12))
+                .map(|p| p.to_string_lossy().to_string()),
+            Some("foo.bin".to_string())
+        );
+        assert_eq!(analysis.find_include("test.asm", Position::new(0, 1)), None);
+        Ok(())
+    }
+
     fn analysis(src: &str) -> Analysis {
         let (tree, error) = parse("test.asm".as_ref(), src);
-        Analysis::new(tree.clone(), error)
+        Analysis::new(tree, error, None)
     }
 }
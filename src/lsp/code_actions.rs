@@ -0,0 +1,384 @@
+use crate::core::codegen::{CodegenContext, CodegenError, DetailedCodegenError, Symbol};
+use crate::core::parser::{Block, IdentifierPath, Instruction, Mnemonic, Token};
+use crate::errors::MosResult;
+use crate::impl_request_handler;
+use crate::lsp::analysis::Analysis;
+use crate::lsp::{LspContext, RequestHandler};
+use codemap::Span;
+use itertools::Itertools;
+use lsp_types::request::CodeActionRequest;
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams, CodeActionResponse,
+    Position, Range, TextEdit, Url, WorkspaceEdit,
+};
+use std::collections::HashMap;
+
+pub struct CodeActionHandler {}
+
+impl_request_handler!(CodeActionHandler);
+
+impl RequestHandler<CodeActionRequest> for CodeActionHandler {
+    fn handle(
+        &self,
+        ctx: &mut LspContext,
+        params: CodeActionParams,
+    ) -> MosResult<Option<CodeActionResponse>> {
+        let analysis = match &ctx.analysis {
+            Some(analysis) => analysis,
+            None => return Ok(None),
+        };
+        let codegen = match &analysis.codegen {
+            Some(codegen) => codegen,
+            None => return Ok(None),
+        };
+
+        let actions = codegen
+            .errors()
+            .iter()
+            .chain(codegen.warnings())
+            .filter_map(|error| match error {
+                CodegenError::Detailed(span, detail) => Some((*span, detail)),
+                _ => None,
+            })
+            .filter(|(span, _)| {
+                let loc = analysis.look_up_span(*span);
+                loc.uri() == params.text_document.uri && overlaps(&loc.into(), &params.range)
+            })
+            .flat_map(|(span, detail)| code_actions_for_error(analysis, codegen, span, detail))
+            .map(CodeActionOrCommand::CodeAction)
+            .collect_vec();
+
+        Ok(Some(actions))
+    }
+}
+
+fn overlaps(lhs: &Range, rhs: &Range) -> bool {
+    lhs.start <= rhs.end && rhs.start <= lhs.end
+}
+
+fn code_actions_for_error(
+    analysis: &Analysis,
+    codegen: &CodegenContext,
+    span: Span,
+    error: &DetailedCodegenError,
+) -> Vec<CodeAction> {
+    match error {
+        DetailedCodegenError::UnknownIdentifier(path) => {
+            let mut actions = vec![];
+            if let Some(action) = define_missing_constant(analysis, span, path) {
+                actions.push(action);
+            }
+            actions.extend(did_you_mean(analysis, codegen, span, path));
+            actions
+        }
+        DetailedCodegenError::BranchTooFar() => {
+            branch_trampoline(analysis, span).into_iter().collect()
+        }
+        DetailedCodegenError::ImplicitDefaultSegment() => {
+            wrap_in_segment(analysis, codegen).into_iter().collect()
+        }
+        _ => vec![],
+    }
+}
+
+/// Inserts a `.const` definition on the line before the unknown identifier is used
+fn define_missing_constant(
+    analysis: &Analysis,
+    span: Span,
+    path: &IdentifierPath,
+) -> Option<CodeAction> {
+    if path.len() != 1 {
+        return None;
+    }
+    let name = path.single().value();
+    if name.chars().all(|c| c == '-') || name.chars().all(|c| c == '+') {
+        return None;
+    }
+
+    let loc = analysis.tree.code_map().look_up_span(span);
+    let line = loc.file.source_line(loc.begin.line);
+    let indent = &line[0..line.len() - line.trim_start().len()];
+    let position = Position::new(loc.begin.line as u32, 0);
+    let edit = TextEdit {
+        range: Range::new(position, position),
+        new_text: format!("{}.const {} = 0\n", indent, name),
+    };
+
+    Some(quick_fix(
+        format!("Define missing constant `{}`", name),
+        analysis.look_up_span(span).uri(),
+        vec![edit],
+    ))
+}
+
+/// Suggests symbols that have a similar name to the unknown identifier
+fn did_you_mean(
+    analysis: &Analysis,
+    codegen: &CodegenContext,
+    span: Span,
+    path: &IdentifierPath,
+) -> Vec<CodeAction> {
+    let unknown = path.to_string();
+    let max_distance = std::cmp::max(1, unknown.len() / 3);
+
+    let loc = analysis.look_up_span(span);
+    codegen
+        .symbol_table()
+        .symbols()
+        .iter()
+        .filter(|(_, symbol)| !matches!(symbol, Symbol::System(_)))
+        .map(|(candidate, _)| candidate.to_string())
+        .filter(|candidate| !candidate.contains("$$"))
+        .filter_map(|candidate| {
+            let last = candidate.rsplit('.').next().unwrap_or(&candidate);
+            let distance = std::cmp::min(
+                edit_distance(&unknown, &candidate),
+                edit_distance(&unknown, last),
+            );
+            if distance > 0 && distance <= max_distance {
+                Some((distance, candidate))
+            } else {
+                None
+            }
+        })
+        .sorted()
+        .take(3)
+        .map(|(_, candidate)| {
+            let edit = TextEdit {
+                range: loc.clone().into(),
+                new_text: candidate.clone(),
+            };
+            quick_fix(
+                format!("Did you mean `{}`?", candidate),
+                loc.uri(),
+                vec![edit],
+            )
+        })
+        .collect()
+}
+
+/// Replaces a branch that is out of range with an inverted branch that skips over a `jmp` to the original target
+fn branch_trampoline(analysis: &Analysis, span: Span) -> Option<CodeAction> {
+    let instruction = find_instruction(analysis.tree.tokens(), span)?;
+    let inverse = match instruction.mnemonic.data {
+        Mnemonic::Bcc => Mnemonic::Bcs,
+        Mnemonic::Bcs => Mnemonic::Bcc,
+        Mnemonic::Beq => Mnemonic::Bne,
+        Mnemonic::Bne => Mnemonic::Beq,
+        Mnemonic::Bmi => Mnemonic::Bpl,
+        Mnemonic::Bpl => Mnemonic::Bmi,
+        Mnemonic::Bvc => Mnemonic::Bvs,
+        Mnemonic::Bvs => Mnemonic::Bvc,
+        _ => return None,
+    };
+    let operand = instruction.operand.as_ref()?;
+
+    let loc = analysis.tree.code_map().look_up_span(span);
+    let original_mnemonic = loc.file.source_slice(span);
+    let (inverse, jmp) = if original_mnemonic.chars().any(|c| c.is_lowercase()) {
+        (inverse.to_string().to_lowercase(), "jmp")
+    } else {
+        (inverse.to_string(), "JMP")
+    };
+    let target = loc.file.source_slice(operand.expr.span).trim();
+    let line = loc.file.source_line(loc.begin.line);
+    let indent = &line[0..line.len() - line.trim_start().len()];
+
+    // The inverted branch (2 bytes) skips the `jmp` (3 bytes)
+    let edit = TextEdit {
+        range: analysis.look_up_span(span.merge(operand.expr.span)).into(),
+        new_text: format!("{} * + 5\n{}{} {}", inverse, indent, jmp, target),
+    };
+
+    Some(quick_fix(
+        "Convert branch to `jmp` trampoline",
+        analysis.look_up_span(span).uri(),
+        vec![edit],
+    ))
+}
+
+/// Wraps the entire source file in an explicitly defined segment
+fn wrap_in_segment(analysis: &Analysis, codegen: &CodegenContext) -> Option<CodeAction> {
+    let file = analysis.tree.files().first()?;
+    let start = codegen.segments().try_get("default")?.options().initial_pc;
+    let end = Range::from(analysis.look_up_span(file.span)).end;
+
+    let begin = Position::new(0, 0);
+    let edits = vec![
+        TextEdit {
+            range: Range::new(begin, begin),
+            new_text: format!(
+                ".define segment {{\n    name = default\n    start = ${:04x}\n}}\n\n.segment default {{\n",
+                start.as_i64()
+            ),
+        },
+        TextEdit {
+            range: Range::new(end, end),
+            new_text: "\n}\n".to_string(),
+        },
+    ];
+
+    Some(quick_fix(
+        "Wrap in segment",
+        analysis.look_up_span(file.span).uri(),
+        edits,
+    ))
+}
+
+fn quick_fix<S: Into<String>>(title: S, uri: Url, edits: Vec<TextEdit>) -> CodeAction {
+    let mut changes = HashMap::new();
+    changes.insert(uri, edits);
+    CodeAction {
+        title: title.into(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit: Some(WorkspaceEdit {
+            changes: Some(changes),
+            document_changes: None,
+            change_annotations: None,
+        }),
+        ..Default::default()
+    }
+}
+
+/// Finds the instruction whose mnemonic is located at the provided span
+fn find_instruction(tokens: &[Token], span: Span) -> Option<&Instruction> {
+    tokens.iter().find_map(|token| match token {
+        Token::Instruction(i) if i.mnemonic.span == span => Some(i),
+        Token::Braces { block, .. } => find_instruction(&block.inner, span),
+        Token::Label { block, .. } | Token::Segment { block, .. } => {
+            find_instruction_in_block(block, span)
+        }
+        Token::If { if_, else_, .. } => {
+            find_instruction(&if_.inner, span).or_else(|| find_instruction_in_block(else_, span))
+        }
+        _ => None,
+    })
+}
+
+fn find_instruction_in_block(block: &Option<Block>, span: Span) -> Option<&Instruction> {
+    block
+        .as_ref()
+        .and_then(|b| find_instruction(&b.inner, span))
+}
+
+/// Calculates the Levenshtein distance between two strings
+fn edit_distance(lhs: &str, rhs: &str) -> usize {
+    let rhs = rhs.chars().collect_vec();
+    let mut row = (0..=rhs.len()).collect_vec();
+    for (i, lc) in lhs.chars().enumerate() {
+        let mut prev_diagonal = row[0];
+        row[0] = i + 1;
+        for (j, rc) in rhs.iter().enumerate() {
+            let substitution = prev_diagonal + if lc == *rc { 0 } else { 1 };
+            prev_diagonal = row[j + 1];
+            row[j + 1] = std::cmp::min(substitution, std::cmp::min(row[j], row[j + 1]) + 1);
+        }
+    }
+    row[rhs.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::edit_distance;
+    use crate::errors::MosResult;
+    use crate::lsp::analysis::to_file_uri;
+    use crate::lsp::LspServer;
+    use lsp_types::{CodeActionOrCommand, Position, Range, TextEdit};
+
+    #[test]
+    fn calculate_edit_distance() {
+        assert_eq!(edit_distance("label", "label"), 0);
+        assert_eq!(edit_distance("lable", "label"), 2);
+        assert_eq!(edit_distance("labl", "label"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn define_missing_constant() -> MosResult<()> {
+        let actions = code_actions("  lda #foo", Position::new(0, 7))?;
+        assert_eq!(
+            edits(&actions, "Define missing constant `foo`"),
+            vec![TextEdit {
+                range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+                new_text: "  .const foo = 0\n".to_string()
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn did_you_mean() -> MosResult<()> {
+        let actions = code_actions("my_label: nop\njmp my_lbel", Position::new(1, 5))?;
+        assert_eq!(
+            edits(&actions, "Did you mean `my_label`?"),
+            vec![TextEdit {
+                range: Range::new(Position::new(1, 4), Position::new(1, 11)),
+                new_text: "my_label".to_string()
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn branch_trampoline() -> MosResult<()> {
+        let many_nops = "nop\n".repeat(140);
+        let src = format!("foo: {}  bne foo", many_nops);
+        let actions = code_actions(&src, Position::new(140, 3))?;
+        assert_eq!(
+            edits(&actions, "Convert branch to `jmp` trampoline"),
+            vec![TextEdit {
+                range: Range::new(Position::new(140, 2), Position::new(140, 9)),
+                new_text: "beq * + 5\n  jmp foo".to_string()
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn wrap_in_segment() -> MosResult<()> {
+        let actions = code_actions("nop", Position::new(0, 0))?;
+        assert_eq!(
+            edits(&actions, "Wrap in segment"),
+            vec![
+                TextEdit {
+                    range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+                    new_text: ".define segment {\n    name = default\n    start = $2000\n}\n\n.segment default {\n".to_string()
+                },
+                TextEdit {
+                    range: Range::new(Position::new(0, 3), Position::new(0, 3)),
+                    new_text: "\n}\n".to_string()
+                }
+            ]
+        );
+        Ok(())
+    }
+
+    fn code_actions(src: &str, position: Position) -> MosResult<Vec<CodeActionOrCommand>> {
+        let mut server = LspServer::new();
+        server.did_open_text_document("test.asm", src)?;
+        server.code_action("test.asm", Range::new(position, position))?;
+        let response = server.context.responses().pop().unwrap();
+        Ok(serde_json::from_value(response.result.unwrap()).unwrap())
+    }
+
+    fn edits(actions: &[CodeActionOrCommand], title: &str) -> Vec<TextEdit> {
+        actions
+            .iter()
+            .find_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) if action.title == title => Some(
+                    action
+                        .edit
+                        .as_ref()
+                        .unwrap()
+                        .changes
+                        .as_ref()
+                        .unwrap()
+                        .get(&to_file_uri("test.asm"))
+                        .unwrap()
+                        .clone(),
+                ),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("No code action found: {}", title))
+    }
+}
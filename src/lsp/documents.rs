@@ -1,15 +1,27 @@
-use crate::core::codegen::{codegen, CodegenOptions};
-use crate::core::parser::parse;
+use crate::config::Config;
+use crate::core::codegen::{codegen_partial, CodegenError, CodegenOptions, DetailedCodegenError};
+use crate::core::parser::{parse, ParseTree};
 use crate::errors::{MosError, MosResult};
 use crate::impl_notification_handler;
 use crate::lsp::analysis::{from_file_uri, Analysis};
 use crate::lsp::{LspContext, NotificationHandler};
+use crate::mos_toml_path;
+use fs_err as fs;
 use lsp_types::notification::{DidChangeTextDocument, DidOpenTextDocument, PublishDiagnostics};
 use lsp_types::{
-    Diagnostic, DidChangeTextDocumentParams, DidOpenTextDocumentParams, Position,
-    PublishDiagnosticsParams, Range, Url,
+    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidOpenTextDocumentParams,
+    Position, PublishDiagnosticsParams, Range, Url,
 };
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// A loaded `mos.toml`, along with the time it was modified so that it is only read again when it changes
+pub struct LoadedConfig {
+    path: PathBuf,
+    modified: SystemTime,
+    config: Config,
+}
 
 pub struct DidOpenTextDocumentHandler {}
 pub struct DidChangeTextDocumentHandler {}
@@ -39,33 +51,104 @@ fn perform_analysis(ctx: &mut LspContext, uri: &Url, source: &str) {
     log::trace!("Performing analysis, caused by: {}", path.to_str().unwrap());
     let source = source.to_string();
 
+    let options = codegen_options(ctx, uri).unwrap_or_else(|e| {
+        log::error!("Could not load configuration: {}", e);
+        CodegenOptions::default()
+    });
+
     let (tree, error) = parse(path, &source);
-    let error = match error {
-        Some(e) => Some(e),
-        None => match codegen(tree.clone(), CodegenOptions::default()) {
-            Ok(_) => None,
-            Err(e) => Some(e),
-        },
+    let codegen = match error {
+        Some(_) => None,
+        None => Some(codegen_partial(tree.clone(), options)),
     };
 
-    ctx.analysis = Some(Analysis::new(tree, error));
+    ctx.analysis = Some(Analysis::new(tree, error, codegen));
+}
+
+/// Determines the options from the `mos.toml` that applies to a document, so that it is analysed with the same options as `mos build` uses
+fn codegen_options(ctx: &mut LspContext, uri: &Url) -> MosResult<CodegenOptions> {
+    let dir = match uri.to_file_path().ok().as_deref().and_then(Path::parent) {
+        Some(dir) if dir.exists() => dir.to_path_buf(),
+        _ => return Config::default().build.codegen_options(),
+    };
+    let path = match mos_toml_path(None, dir)? {
+        Some(path) => path,
+        None => return Config::default().build.codegen_options(),
+    };
+
+    let modified = fs::metadata(&path)?.modified()?;
+    let is_loaded =
+        matches!(&ctx.config, Some(loaded) if loaded.path == path && loaded.modified == modified);
+    if !is_loaded {
+        log::trace!("Loading configuration: {}", path.to_string_lossy());
+        let config = Config::from_toml(&fs::read_to_string(&path)?)?;
+        ctx.config = Some(LoadedConfig {
+            path,
+            modified,
+            config,
+        });
+    }
+
+    ctx.config.as_ref().unwrap().config.build.codegen_options()
 }
 
 fn publish_diagnostics(ctx: &LspContext, uri: &Url) -> MosResult<()> {
     let analysis = ctx.analysis.as_ref().unwrap();
+    let mut diagnostics = analysis
+        .error
+        .as_ref()
+        .map(|e| to_diagnostics(e))
+        .unwrap_or_default();
+    if let Some(codegen) = &analysis.codegen {
+        for error in codegen.errors() {
+            diagnostics.extend(codegen_error_to_diagnostics(&analysis.tree, error));
+        }
+        for warning in codegen.warnings() {
+            diagnostics.extend(
+                codegen_error_to_diagnostics(&analysis.tree, warning)
+                    .into_iter()
+                    .map(|mut d| {
+                        d.severity = Some(match warning {
+                            CodegenError::Detailed(
+                                _,
+                                DetailedCodegenError::ImplicitDefaultSegment(),
+                            ) => DiagnosticSeverity::Hint,
+                            _ => DiagnosticSeverity::Warning,
+                        });
+                        d
+                    }),
+            );
+        }
+    }
+
     let params = PublishDiagnosticsParams::new(
         uri.clone(),
-        analysis
-            .error
-            .as_ref()
-            .map(|e| to_diagnostics(e))
-            .unwrap_or_default(),
+        diagnostics,
         None, // todo: handle document version
     );
     ctx.publish_notification::<PublishDiagnostics>(params)?;
     Ok(())
 }
 
+fn codegen_error_to_diagnostics(tree: &Arc<ParseTree>, error: &CodegenError) -> Vec<Diagnostic> {
+    match error {
+        CodegenError::Detailed(span, detail) => to_diagnostics(&MosError::Codegen {
+            tree: tree.clone(),
+            span: *span,
+            message: detail.to_string(),
+        }),
+        CodegenError::Mos(e) => to_diagnostics(e),
+        CodegenError::Multiple(errors) => errors
+            .iter()
+            .flat_map(|e| codegen_error_to_diagnostics(tree, e))
+            .collect(),
+        CodegenError::Io(e) => {
+            log::error!("IO error during code generation: {:?}", e);
+            vec![]
+        }
+    }
+}
+
 fn to_diagnostics(error: &MosError) -> Vec<Diagnostic> {
     match &error {
         MosError::Parser {
@@ -93,3 +176,67 @@ fn to_diagnostics(error: &MosError) -> Vec<Diagnostic> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::MosResult;
+    use crate::lsp::LspServer;
+    use fs_err as fs;
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
+    use tempfile::tempdir;
+
+    #[test]
+    fn analysis_uses_build_options_from_mos_toml() -> MosResult<()> {
+        let root = tempdir()?;
+        fs::write(root.path().join("mos.toml"), "[build]\ntarget = \"c64\"")?;
+        let file = root.path().join("main.asm");
+
+        let mut server = LspServer::new();
+        server.did_open_text_document(
+            &file.to_string_lossy(),
+            ".define segment { name = code start = $0801 region = ram }\nsta VIC.border",
+        )?;
+        let codegen = server.context.analysis.unwrap().codegen.unwrap();
+        assert!(codegen.errors().is_empty());
+
+        root.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn mos_toml_is_only_reloaded_when_it_changes() -> MosResult<()> {
+        let root = tempdir()?;
+        let toml = root.path().join("mos.toml");
+        fs::write(&toml, "[build]\ntarget = \"c64\"")?;
+        let file = root.path().join("main.asm");
+        let source = "sta VIC.border";
+
+        let mut server = LspServer::new();
+        server.did_open_text_document(&file.to_string_lossy(), source)?;
+        let modified = fs::metadata(&toml)?.modified()?;
+
+        // The contents changed, but the modification time did not, so the cached configuration is used
+        fs::write(&toml, "[build]")?;
+        set_modified(&toml, modified)?;
+        server.did_open_text_document(&file.to_string_lossy(), source)?;
+        let codegen = server.context.analysis.take().unwrap().codegen.unwrap();
+        assert!(codegen.errors().is_empty());
+
+        set_modified(&toml, modified + Duration::from_secs(1))?;
+        server.did_open_text_document(&file.to_string_lossy(), source)?;
+        let codegen = server.context.analysis.take().unwrap().codegen.unwrap();
+        assert_eq!(codegen.errors().len(), 1);
+
+        root.close()?;
+        Ok(())
+    }
+
+    fn set_modified(path: &Path, modified: SystemTime) -> MosResult<()> {
+        std::fs::File::options()
+            .write(true)
+            .open(path)?
+            .set_modified(modified)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use crate::core::parser::{Block, Located, Token, Trivia};
+use crate::errors::MosResult;
+use crate::impl_request_handler;
+use crate::lsp::analysis::Analysis;
+use crate::lsp::{LspContext, RequestHandler};
+use codemap::Span;
+use lsp_types::request::FoldingRangeRequest;
+use lsp_types::{FoldingRange, FoldingRangeKind, FoldingRangeParams, Range, Url};
+
+pub struct FoldingRangeHandler {}
+
+impl_request_handler!(FoldingRangeHandler);
+
+impl RequestHandler<FoldingRangeRequest> for FoldingRangeHandler {
+    fn handle(
+        &self,
+        ctx: &mut LspContext,
+        params: FoldingRangeParams,
+    ) -> MosResult<Option<Vec<FoldingRange>>> {
+        let analysis = match &ctx.analysis {
+            Some(analysis) => analysis,
+            None => return Ok(None),
+        };
+
+        let mut folder = Folder {
+            analysis,
+            uri: &params.text_document.uri,
+            ranges: vec![],
+        };
+        folder.fold_tokens(analysis.tree.tokens());
+        Ok(Some(folder.ranges))
+    }
+}
+
+struct Folder<'a> {
+    analysis: &'a Analysis,
+    uri: &'a Url,
+    ranges: Vec<FoldingRange>,
+}
+
+impl<'a> Folder<'a> {
+    fn fold_tokens(&mut self, tokens: &[Token]) {
+        for token in tokens {
+            self.fold_token(token);
+        }
+    }
+
+    fn fold_token(&mut self, token: &Token) {
+        if let Some(trivia) = token.located_trivia() {
+            self.fold_comments(trivia);
+        }
+
+        match token {
+            Token::Braces { block, .. } | Token::Config(block) => self.fold_block(block),
+            Token::Definition {
+                value: Some(value), ..
+            } => self.fold_token(value),
+            Token::If { if_, else_, .. } => {
+                self.fold_block(if_);
+                if let Some(else_) = else_ {
+                    self.fold_block(else_);
+                }
+            }
+            Token::Label { block, .. } | Token::Segment { block, .. } => {
+                if let Some(block) = block {
+                    self.fold_block(block);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn fold_block(&mut self, block: &Block) {
+        if let Some(range) = self.range(block.span()) {
+            // Keep the line containing the closing brace visible
+            if range.end.line > range.start.line + 1 {
+                self.ranges.push(FoldingRange {
+                    start_line: range.start.line,
+                    start_character: None,
+                    end_line: range.end.line - 1,
+                    end_character: None,
+                    kind: Some(FoldingRangeKind::Region),
+                });
+            }
+        }
+
+        self.fold_tokens(&block.inner);
+        if let Some(trivia) = &block.rparen.trivia {
+            self.fold_comments(trivia);
+        }
+    }
+
+    /// Folds the multi-line C-style comments that are contained in the trivia
+    fn fold_comments(&mut self, trivia: &Located<Vec<Trivia>>) {
+        let source = self
+            .analysis
+            .tree
+            .code_map()
+            .look_up_span(trivia.span)
+            .file
+            .source_slice(trivia.span)
+            .to_string();
+
+        let mut offset = 0;
+        for item in &trivia.data {
+            let len = match item {
+                Trivia::Whitespace(s) | Trivia::CppStyle(s) => s.len(),
+                Trivia::NewLine if source[offset..].starts_with('\r') => 2,
+                Trivia::NewLine => 1,
+                Trivia::CStyle(s) => {
+                    let span = trivia
+                        .span
+                        .subspan(offset as u64, (offset + s.len()) as u64);
+                    if let Some(range) = self.range(span) {
+                        if range.end.line > range.start.line {
+                            self.ranges.push(FoldingRange {
+                                start_line: range.start.line,
+                                start_character: None,
+                                end_line: range.end.line,
+                                end_character: None,
+                                kind: Some(FoldingRangeKind::Comment),
+                            });
+                        }
+                    }
+                    s.len()
+                }
+            };
+            offset += len;
+        }
+    }
+
+    /// Determines the range of a span, but only if it is in the file we're folding
+    fn range(&self, span: Span) -> Option<Range> {
+        let loc = self.analysis.look_up_span(span);
+        if &loc.uri() == self.uri {
+            Some(loc.into())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::MosResult;
+    use crate::lsp::testing::response;
+    use crate::lsp::LspServer;
+    use lsp_types::request::FoldingRangeRequest;
+    use lsp_types::{FoldingRange, FoldingRangeKind};
+
+    #[test]
+    fn folding_ranges() -> MosResult<()> {
+        let mut server = LspServer::new();
+        server.did_open_text_document(
+            "test.asm",
+            "/*\n * comment\n */\nfoo: {\n  nop\n  .if 1 {\n    nop\n  } else {\n    /* a\n    b */\n  }\n}\nbar: { nop }",
+        )?;
+        server.folding_ranges("test.asm")?;
+
+        let range = |start_line, end_line, kind| FoldingRange {
+            start_line,
+            start_character: None,
+            end_line,
+            end_character: None,
+            kind: Some(kind),
+        };
+        assert_eq!(
+            server.context.responses().pop().unwrap().result,
+            response::<FoldingRangeRequest>(Some(vec![
+                range(0, 2, FoldingRangeKind::Comment),
+                range(3, 10, FoldingRangeKind::Region),
+                range(5, 6, FoldingRangeKind::Region),
+                range(7, 9, FoldingRangeKind::Region),
+                range(8, 9, FoldingRangeKind::Comment),
+            ]))
+            .result
+        );
+
+        Ok(())
+    }
+}
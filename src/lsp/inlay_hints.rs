@@ -0,0 +1,182 @@
+use crate::core::codegen::{CodegenContext, GeneratedValue, Value};
+use crate::core::parser::{Block, Token};
+use crate::errors::MosResult;
+use crate::impl_request_handler;
+use crate::lsp::{LspContext, RequestHandler};
+use codemap::Span;
+use itertools::Itertools;
+use lsp_types::request::Request;
+use lsp_types::{Position, Range, TextDocumentIdentifier, WorkDoneProgressParams};
+use serde::{Deserialize, Serialize};
+
+/// The `textDocument/inlayHint` request, which is not yet available in the version of `lsp-types` that we use
+pub enum InlayHintRequest {}
+
+impl Request for InlayHintRequest {
+    type Params = InlayHintParams;
+    type Result = Option<Vec<InlayHint>>;
+    const METHOD: &'static str = "textDocument/inlayHint";
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHintParams {
+    #[serde(flatten)]
+    pub work_done_progress_params: WorkDoneProgressParams,
+    pub text_document: TextDocumentIdentifier,
+    pub range: Range,
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHint {
+    pub position: Position,
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub padding_left: Option<bool>,
+}
+
+pub struct InlayHintHandler {}
+
+impl_request_handler!(InlayHintHandler);
+
+impl RequestHandler<InlayHintRequest> for InlayHintHandler {
+    fn handle(
+        &self,
+        ctx: &mut LspContext,
+        params: InlayHintParams,
+    ) -> MosResult<Option<Vec<InlayHint>>> {
+        let analysis = match &ctx.analysis {
+            Some(analysis) => analysis,
+            None => return Ok(None),
+        };
+        let codegen = match &analysis.codegen {
+            Some(codegen) => codegen,
+            None => return Ok(None),
+        };
+
+        let mut hints = vec![];
+        collect_hints(codegen, analysis.tree.tokens(), &mut hints);
+
+        let hints = hints
+            .into_iter()
+            .filter_map(|(span, label)| {
+                let loc = analysis.look_up_span(span);
+                let position = Range::from(loc.clone()).end;
+                let in_range = position >= params.range.start && position <= params.range.end;
+                if loc.uri() == params.text_document.uri && in_range {
+                    Some(InlayHint {
+                        position,
+                        label,
+                        padding_left: Some(true),
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        Ok(Some(hints))
+    }
+}
+
+/// Collects the hints for all tokens, as a tuple of the span the hint should follow and the hint's label
+fn collect_hints(codegen: &CodegenContext, tokens: &[Token], hints: &mut Vec<(Span, String)>) {
+    for token in tokens {
+        match token {
+            Token::Label { id, colon, block } => {
+                if let Some(GeneratedValue::Address(pc)) = codegen.generated_value(&id.span) {
+                    hints.push((colon.span, format!("${:04X}", pc)));
+                }
+                collect_hints_in_block(codegen, block, hints);
+            }
+            Token::VariableDefinition { id, value, .. } => {
+                if let Some(GeneratedValue::Value(val)) = codegen.generated_value(&id.span) {
+                    let label = match val {
+                        Value::Integer(v) if *v >= 0 => format!("= {} (${:X})", v, v),
+                        _ => format!("= {}", val),
+                    };
+                    hints.push((value.span, label));
+                }
+            }
+            Token::Instruction(i) => {
+                if let Some(GeneratedValue::Bytes(_, bytes)) =
+                    codegen.generated_value(&i.mnemonic.span)
+                {
+                    let label = bytes.iter().map(|b| format!("{:02X}", b)).join(" ");
+                    hints.push((i.span(), label));
+                }
+            }
+            Token::Braces { block, .. } => {
+                collect_hints(codegen, &block.inner, hints);
+            }
+            Token::Segment { block, .. } => {
+                collect_hints_in_block(codegen, block, hints);
+            }
+            Token::If { if_, else_, .. } => {
+                collect_hints(codegen, &if_.inner, hints);
+                collect_hints_in_block(codegen, else_, hints);
+            }
+            _ => (),
+        }
+    }
+}
+
+fn collect_hints_in_block(
+    codegen: &CodegenContext,
+    block: &Option<Block>,
+    hints: &mut Vec<(Span, String)>,
+) {
+    if let Some(block) = block {
+        collect_hints(codegen, &block.inner, hints);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InlayHint, InlayHintRequest};
+    use crate::errors::MosResult;
+    use crate::lsp::testing::response;
+    use crate::lsp::LspServer;
+    use lsp_types::Position;
+
+    #[test]
+    fn inlay_hints() -> MosResult<()> {
+        let mut server = LspServer::new();
+        server.did_open_text_document(
+            "test.asm",
+            ".const foo = 10\nmy_label: lda #foo\n{\n  sta ($fb), y\n}",
+        )?;
+        server.inlay_hints("test.asm")?;
+
+        let hint = |line, character, label: &str| InlayHint {
+            position: Position::new(line, character),
+            label: label.to_string(),
+            padding_left: Some(true),
+        };
+        assert_eq!(
+            server.context.responses().pop().unwrap().result,
+            response::<InlayHintRequest>(Some(vec![
+                hint(0, 15, "= 10 ($A)"),
+                hint(1, 9, "$2000"),
+                hint(1, 18, "A9 0A"),
+                hint(3, 14, "91 FB"),
+            ]))
+            .result
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn no_inlay_hints_for_unparseable_source() -> MosResult<()> {
+        let mut server = LspServer::new();
+        server.did_open_text_document("test.asm", "lda #")?;
+        server.inlay_hints("test.asm")?;
+        assert_eq!(
+            server.context.responses().pop().unwrap().result,
+            response::<InlayHintRequest>(None).result
+        );
+        Ok(())
+    }
+}
@@ -1,16 +1,24 @@
 use crate::errors::MosResult;
 use crate::lsp::analysis::{from_file_uri, Analysis, Definition};
-use crate::lsp::documents::{DidChangeTextDocumentHandler, DidOpenTextDocumentHandler};
+use crate::lsp::code_actions::CodeActionHandler;
+use crate::lsp::documents::{
+    DidChangeTextDocumentHandler, DidOpenTextDocumentHandler, LoadedConfig,
+};
+use crate::lsp::folding_ranges::FoldingRangeHandler;
 use crate::lsp::formatting::{FormattingRequestHandler, OnTypeFormattingRequestHandler};
+use crate::lsp::inlay_hints::InlayHintHandler;
 use crate::lsp::references::{
     DocumentHighlightRequestHandler, FindReferencesHandler, GoToDefinitionHandler,
 };
-use crate::lsp::rename::RenameHandler;
+use crate::lsp::rename::{PrepareRenameHandler, RenameHandler};
+use crate::lsp::selection_ranges::SelectionRangeHandler;
 use crate::lsp::semantic_highlighting::SemanticTokensFullRequestHandler;
+use crate::lsp::signature_help::SignatureHelpHandler;
 use lsp_server::{Connection, IoThreads, Message, RequestId};
 use lsp_types::notification::Notification;
 use lsp_types::{
-    DocumentOnTypeFormattingOptions, InitializeParams, OneOf, ServerCapabilities,
+    CodeActionProviderCapability, DocumentOnTypeFormattingOptions, InitializeParams, OneOf,
+    RenameOptions, SelectionRangeProviderCapability, ServerCapabilities, SignatureHelpOptions,
     TextDocumentPositionParams, TextDocumentSyncKind,
 };
 use serde::de::DeserializeOwned;
@@ -21,11 +29,16 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 mod analysis;
+mod code_actions;
 mod documents;
+mod folding_ranges;
 mod formatting;
+mod inlay_hints;
 mod references;
 mod rename;
+mod selection_ranges;
 mod semantic_highlighting;
+mod signature_help;
 #[cfg(test)]
 mod testing;
 mod traits;
@@ -34,7 +47,10 @@ pub use traits::*;
 
 pub struct LspContext {
     connection: Option<(Arc<Connection>, IoThreads)>,
+    /// The analysis of the most recently opened or changed document. Symbols defined in other documents are not known.
     analysis: Option<Analysis>,
+    /// The `mos.toml` that was used for the most recent analysis
+    config: Option<LoadedConfig>,
     #[cfg(test)]
     responses: Arc<RefCell<Vec<lsp_server::Response>>>,
 }
@@ -44,6 +60,7 @@ impl LspContext {
         Self {
             connection: None,
             analysis: None,
+            config: None,
             #[cfg(test)]
             responses: Arc::new(RefCell::new(vec![])),
         }
@@ -97,6 +114,23 @@ impl LspContext {
         Ok(())
     }
 
+    fn send_error(&self, id: RequestId, message: String) -> MosResult<()> {
+        let response = lsp_server::Response::new_err(
+            id,
+            lsp_server::ErrorCode::InvalidRequest as i32,
+            message,
+        );
+
+        #[cfg(test)]
+        {
+            self.responses.borrow_mut().push(response.clone());
+        }
+        if let Some(conn) = self.connection() {
+            conn.sender.send(Message::Response(response))?;
+        }
+        Ok(())
+    }
+
     fn join(self) -> MosResult<()> {
         self.connection.unwrap().1.join()?;
         Ok(())
@@ -139,7 +173,13 @@ impl LspServer {
         ctx.register_request_handler(GoToDefinitionHandler {});
         ctx.register_request_handler(FindReferencesHandler {});
         ctx.register_request_handler(DocumentHighlightRequestHandler {});
+        ctx.register_request_handler(PrepareRenameHandler {});
         ctx.register_request_handler(RenameHandler {});
+        ctx.register_request_handler(CodeActionHandler {});
+        ctx.register_request_handler(InlayHintHandler {});
+        ctx.register_request_handler(SignatureHelpHandler {});
+        ctx.register_request_handler(FoldingRangeHandler {});
+        ctx.register_request_handler(SelectionRangeHandler {});
         ctx.register_notification_handler(DidOpenTextDocumentHandler {});
         ctx.register_notification_handler(DidChangeTextDocumentHandler {});
 
@@ -161,11 +201,24 @@ impl LspServer {
                 more_trigger_character: None,
             }),
             document_highlight_provider: Some(OneOf::Left(true)),
-            rename_provider: Some(OneOf::Left(true)),
+            rename_provider: Some(OneOf::Right(RenameOptions {
+                prepare_provider: Some(true),
+                work_done_progress_options: Default::default(),
+            })),
             definition_provider: Some(OneOf::Left(true)),
+            code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+            signature_help_provider: Some(SignatureHelpOptions {
+                trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
+                retrigger_characters: None,
+                work_done_progress_options: Default::default(),
+            }),
+            folding_range_provider: Some(true.into()),
+            selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
             ..Default::default()
         };
-        let server_capabilities = serde_json::to_value(&caps).unwrap();
+        let mut server_capabilities = serde_json::to_value(&caps).unwrap();
+        // Inlay hints are not yet part of the ServerCapabilities that lsp-types provides
+        server_capabilities["inlayHintProvider"] = true.into();
         let initialization_params = self
             .context
             .connection()
@@ -1,10 +1,11 @@
 use crate::errors::MosResult;
 use crate::impl_request_handler;
+use crate::lsp::analysis::{from_file_uri, to_file_uri};
 use crate::lsp::{LspContext, RequestHandler};
 use lsp_types::request::{DocumentHighlightRequest, GotoDefinition, References};
 use lsp_types::{
     DocumentHighlight, DocumentHighlightParams, GotoDefinitionParams, GotoDefinitionResponse,
-    Location, ReferenceParams,
+    Location, Range, ReferenceParams,
 };
 
 pub struct GoToDefinitionHandler {}
@@ -21,6 +22,19 @@ impl RequestHandler<GotoDefinition> for GoToDefinitionHandler {
         ctx: &mut LspContext,
         params: GotoDefinitionParams,
     ) -> MosResult<Option<GotoDefinitionResponse>> {
+        let pos = &params.text_document_position_params;
+        if let Some(analysis) = &ctx.analysis {
+            if let Some(path) =
+                analysis.find_include(from_file_uri(&pos.text_document.uri), pos.position)
+            {
+                let location = Location {
+                    uri: to_file_uri(&path.to_string_lossy()),
+                    range: Range::default(),
+                };
+                return Ok(Some(location.into()));
+            }
+        }
+
         Ok(ctx
             .find_definition(&params.text_document_position_params)
             .map(|def| {
@@ -72,3 +86,54 @@ impl RequestHandler<DocumentHighlightRequest> for DocumentHighlightRequestHandle
             .flatten())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::MosResult;
+    use crate::lsp::analysis::to_file_uri;
+    use crate::lsp::testing::response;
+    use crate::lsp::LspServer;
+    use lsp_types::request::GotoDefinition;
+    use lsp_types::{Location, Position, Range};
+
+    #[test]
+    fn go_to_segment_definition() -> MosResult<()> {
+        let mut server = LspServer::new();
+        server.did_open_text_document(
+            "test.asm",
+            ".define segment {\n  name = foo\n  start = $1000\n}\n.segment foo { nop }",
+        )?;
+        server.go_to_definition("test.asm", Position::new(4, 10))?;
+        assert_eq!(
+            server.context.responses().pop().unwrap().result,
+            response::<GotoDefinition>(Some(
+                Location {
+                    uri: to_file_uri("test.asm"),
+                    range: Range::new(Position::new(1, 9), Position::new(1, 12))
+                }
+                .into()
+            ))
+            .result
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn go_to_included_file() -> MosResult<()> {
+        let mut server = LspServer::new();
+        server.did_open_text_document("src/test.asm", ".include \"data/foo.bin\"")?;
+        server.go_to_definition("src/test.asm", Position::new(0, 12))?;
+        assert_eq!(
+            server.context.responses().pop().unwrap().result,
+            response::<GotoDefinition>(Some(
+                Location {
+                    uri: to_file_uri("src/data/foo.bin"),
+                    range: Range::default()
+                }
+                .into()
+            ))
+            .result
+        );
+        Ok(())
+    }
+}
@@ -1,9 +1,34 @@
-use crate::errors::MosResult;
+use crate::core::parser::{is_valid_identifier, IdentifierPath};
+use crate::errors::{MosError, MosResult};
 use crate::impl_request_handler;
+use crate::lsp::analysis::{from_file_uri, Analysis};
 use crate::lsp::{LspContext, RequestHandler};
+use codemap::Span;
 use itertools::Itertools;
-use lsp_types::request::Rename;
-use lsp_types::{RenameParams, TextEdit, WorkspaceEdit};
+use lsp_types::request::{PrepareRenameRequest, Rename};
+use lsp_types::{
+    PrepareRenameResponse, Range, RenameParams, TextDocumentPositionParams, TextEdit, WorkspaceEdit,
+};
+
+pub struct PrepareRenameHandler {}
+
+impl_request_handler!(PrepareRenameHandler);
+
+impl RequestHandler<PrepareRenameRequest> for PrepareRenameHandler {
+    fn handle(
+        &self,
+        ctx: &mut LspContext,
+        params: TextDocumentPositionParams,
+    ) -> MosResult<Option<PrepareRenameResponse>> {
+        let analysis = match &ctx.analysis {
+            Some(analysis) => analysis,
+            None => return Ok(None),
+        };
+
+        Ok(find_target(analysis, &params)
+            .map(|(_, span)| PrepareRenameResponse::Range(analysis.look_up_span(span).into())))
+    }
+}
 
 pub struct RenameHandler {}
 
@@ -15,41 +40,133 @@ impl RequestHandler<Rename> for RenameHandler {
         ctx: &mut LspContext,
         params: RenameParams,
     ) -> MosResult<Option<WorkspaceEdit>> {
-        Ok(ctx
-            .find_definition(&params.text_document_position)
-            .map(|def| {
-                ctx.analysis.as_ref().map(|a| {
-                    let changes = def
-                        .definition_and_usages()
-                        .into_iter()
-                        .map(|span| {
-                            let span = a.look_up_span(span);
-                            let edit = TextEdit {
-                                range: span.clone().into(),
-                                new_text: params.new_name.clone(),
-                            };
-                            (span.uri(), edit)
-                        })
-                        .into_group_map();
-                    WorkspaceEdit {
-                        changes: Some(changes),
-                        document_changes: None,
-                        change_annotations: None,
-                    }
-                })
+        let analysis = match &ctx.analysis {
+            Some(analysis) => analysis,
+            None => return Ok(None),
+        };
+        let path = match find_target(analysis, &params.text_document_position) {
+            Some((path, _)) => path,
+            None => return Ok(None),
+        };
+
+        let new_name = &params.new_name;
+        if !is_valid_identifier(new_name) {
+            return Err(MosError::InvalidRequest(format!(
+                "'{}' is not a valid identifier",
+                new_name
+            )));
+        }
+
+        let old_name = path.parts().last().unwrap().clone();
+        let mut new_path = path.clone();
+        new_path.pop();
+        let new_path = new_path.join(new_name.as_str());
+        if new_path != path && analysis.definitions.get(&new_path).is_some() {
+            return Err(MosError::InvalidRequest(format!(
+                "a symbol named '{}' already exists in this scope",
+                new_name
+            )));
+        }
+
+        // Rename the symbol itself, but also every reference to a nested symbol that goes through its name.
+        // Since only the current document is analysed, the edits never touch other files.
+        let changes = analysis
+            .definitions
+            .iter()
+            .filter(|(p, _)| p.parts().starts_with(path.parts()))
+            .flat_map(|(p, def)| {
+                let depth = p.len() - path.len();
+                def.definition_and_usages()
+                    .into_iter()
+                    .filter_map(move |span| segments(analysis, span).into_iter().rev().nth(depth))
+            })
+            .filter(|span| source(analysis, *span) == old_name.value())
+            .unique()
+            .map(|span| {
+                let span = analysis.look_up_span(span);
+                let edit = TextEdit {
+                    range: span.clone().into(),
+                    new_text: new_name.clone(),
+                };
+                (span.uri(), edit)
             })
-            .flatten())
+            .into_group_map()
+            .into_iter()
+            .map(|(uri, mut edits)| {
+                edits.sort_by_key(|e| (e.range.start.line, e.range.start.character));
+                (uri, edits)
+            })
+            .collect();
+
+        Ok(Some(WorkspaceEdit {
+            changes: Some(changes),
+            document_changes: None,
+            change_annotations: None,
+        }))
     }
 }
 
+/// Determines which symbol is being renamed at a position, returning its full path and the span of the name under the cursor.
+///
+/// In a path such as `foo.bar`, placing the cursor on `foo` will rename `foo` instead of `bar`.
+fn find_target(
+    analysis: &Analysis,
+    pos: &TextDocumentPositionParams,
+) -> Option<(IdentifierPath, Span)> {
+    let (path, def) =
+        analysis.find_with_path(from_file_uri(&pos.text_document.uri), pos.position)?;
+
+    def.definition_and_usages().into_iter().find_map(|span| {
+        segments(analysis, span)
+            .into_iter()
+            .filter(|segment| !source(analysis, *segment).eq_ignore_ascii_case("super"))
+            .rev()
+            .enumerate()
+            .find_map(|(depth, segment)| {
+                let loc = analysis.look_up_span(segment);
+                let range = Range::from(loc.clone());
+                let contains = loc.uri() == pos.text_document.uri
+                    && range.start <= pos.position
+                    && pos.position <= range.end;
+                if !contains || depth >= path.len() {
+                    return None;
+                }
+
+                let target = IdentifierPath::new(&path.parts()[..path.len() - depth]);
+                analysis.definitions.get(&target).map(|_| (target, segment))
+            })
+    })
+}
+
+/// Splits the span of an identifier path into the spans of its individual identifiers
+fn segments(analysis: &Analysis, span: Span) -> Vec<Span> {
+    let mut offset = 0;
+    source(analysis, span)
+        .split('.')
+        .map(|part| {
+            let segment = span.subspan(offset as u64, (offset + part.len()) as u64);
+            offset += part.len() + 1;
+            segment
+        })
+        .collect()
+}
+
+fn source(analysis: &Analysis, span: Span) -> String {
+    analysis
+        .look_up_span(span)
+        .file()
+        .source_slice(span)
+        .to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use crate::errors::MosResult;
     use crate::lsp::analysis::to_file_uri;
     use crate::lsp::testing::response;
     use crate::lsp::LspServer;
-    use lsp_types::request::Rename;
-    use lsp_types::{Position, Range, TextEdit, Url, WorkspaceEdit};
+    use lsp_types::request::{PrepareRenameRequest, Rename};
+    use lsp_types::{Position, PrepareRenameResponse, Range, TextEdit, Url, WorkspaceEdit};
     use std::collections::HashMap;
 
     #[test]
@@ -58,30 +175,130 @@ mod tests {
         server.did_open_text_document("test.asm", "foo: nop\nlda foo")?;
         server.rename("test.asm", Position::new(1, 4), "bar")?;
 
-        let mut expected_changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
-        expected_changes.insert(
-            to_file_uri("test.asm"),
-            vec![
-                TextEdit {
-                    range: Range::new(Position::new(0, 0), Position::new(0, 3)),
-                    new_text: "bar".to_string(),
-                },
-                TextEdit {
-                    range: Range::new(Position::new(1, 4), Position::new(1, 7)),
-                    new_text: "bar".to_string(),
-                },
-            ],
+        assert_eq!(
+            server.context.responses().pop().unwrap().result,
+            response::<Rename>(Some(edits(vec![
+                edit(0, 0, 0, 3, "bar"),
+                edit(1, 4, 1, 7, "bar")
+            ])))
+            .result
         );
+
+        Ok(())
+    }
+
+    #[test]
+    fn rename_scoped_symbols() -> MosResult<()> {
+        let mut server = LspServer::new();
+        server
+            .did_open_text_document("test.asm", "foo: {\n  bar: nop\n  lda bar\n}\nlda foo.bar")?;
+        server.rename("test.asm", Position::new(4, 9), "baz")?;
+
         assert_eq!(
             server.context.responses().pop().unwrap().result,
-            response::<Rename>(Some(WorkspaceEdit {
-                changes: Some(expected_changes),
-                document_changes: None,
-                change_annotations: None
-            }))
+            response::<Rename>(Some(edits(vec![
+                edit(1, 2, 1, 5, "baz"),
+                edit(2, 6, 2, 9, "baz"),
+                edit(4, 8, 4, 11, "baz")
+            ])))
             .result
         );
 
         Ok(())
     }
+
+    #[test]
+    fn rename_scope_used_in_paths() -> MosResult<()> {
+        let mut server = LspServer::new();
+        server.did_open_text_document("test.asm", "foo: {\n  bar: nop\n}\nlda foo.bar\nlda foo")?;
+        server.rename("test.asm", Position::new(3, 5), "baz")?;
+
+        assert_eq!(
+            server.context.responses().pop().unwrap().result,
+            response::<Rename>(Some(edits(vec![
+                edit(0, 0, 0, 3, "baz"),
+                edit(3, 4, 3, 7, "baz"),
+                edit(4, 4, 4, 7, "baz")
+            ])))
+            .result
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn cannot_rename_to_invalid_identifier() -> MosResult<()> {
+        let mut server = LspServer::new();
+        server.did_open_text_document("test.asm", "foo: nop\nlda foo")?;
+        server.rename("test.asm", Position::new(1, 4), "1abc")?;
+
+        let error = server.context.responses().pop().unwrap().error.unwrap();
+        assert_eq!(error.message, "'1abc' is not a valid identifier");
+
+        Ok(())
+    }
+
+    #[test]
+    fn cannot_rename_to_existing_symbol() -> MosResult<()> {
+        let mut server = LspServer::new();
+        server.did_open_text_document("test.asm", "foo: nop\nbar: nop\nlda foo")?;
+        server.rename("test.asm", Position::new(2, 4), "bar")?;
+
+        let error = server.context.responses().pop().unwrap().error.unwrap();
+        assert_eq!(
+            error.message,
+            "a symbol named 'bar' already exists in this scope"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn prepare_rename() -> MosResult<()> {
+        let mut server = LspServer::new();
+        server.did_open_text_document("test.asm", "foo: {\n  bar: nop\n}\nlda foo.bar")?;
+        server.prepare_rename("test.asm", Position::new(3, 9))?;
+        server.prepare_rename("test.asm", Position::new(3, 5))?;
+        server.prepare_rename("test.asm", Position::new(1, 7))?;
+
+        let range = |sl, sc, el, ec| {
+            Some(PrepareRenameResponse::Range(Range::new(
+                Position::new(sl, sc),
+                Position::new(el, ec),
+            )))
+        };
+        let responses = server
+            .context
+            .responses()
+            .into_iter()
+            .map(|r| r.result)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            responses,
+            vec![
+                response::<PrepareRenameRequest>(range(3, 8, 3, 11)).result,
+                response::<PrepareRenameRequest>(range(3, 4, 3, 7)).result,
+                response::<PrepareRenameRequest>(None).result,
+            ]
+        );
+
+        Ok(())
+    }
+
+    fn edit(sl: u32, sc: u32, el: u32, ec: u32, new_text: &str) -> TextEdit {
+        TextEdit {
+            range: Range::new(Position::new(sl, sc), Position::new(el, ec)),
+            new_text: new_text.to_string(),
+        }
+    }
+
+    fn edits(edits: Vec<TextEdit>) -> WorkspaceEdit {
+        let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+        changes.insert(to_file_uri("test.asm"), edits);
+        WorkspaceEdit {
+            changes: Some(changes),
+            document_changes: None,
+            change_annotations: None,
+        }
+    }
 }
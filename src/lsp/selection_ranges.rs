@@ -0,0 +1,240 @@
+use crate::core::parser::{Block, Expression, ExpressionFactor, Located, Token};
+use crate::errors::MosResult;
+use crate::impl_request_handler;
+use crate::lsp::analysis::Analysis;
+use crate::lsp::{LspContext, RequestHandler};
+use codemap::Span;
+use lsp_types::request::SelectionRangeRequest;
+use lsp_types::{Position, Range, SelectionRange, SelectionRangeParams, Url};
+
+pub struct SelectionRangeHandler {}
+
+impl_request_handler!(SelectionRangeHandler);
+
+impl RequestHandler<SelectionRangeRequest> for SelectionRangeHandler {
+    fn handle(
+        &self,
+        ctx: &mut LspContext,
+        params: SelectionRangeParams,
+    ) -> MosResult<Option<Vec<SelectionRange>>> {
+        let analysis = match &ctx.analysis {
+            Some(analysis) => analysis,
+            None => return Ok(None),
+        };
+
+        let uri = &params.text_document.uri;
+        let ranges = params
+            .positions
+            .iter()
+            .map(|position| {
+                let mut selector = Selector {
+                    analysis,
+                    uri,
+                    position: *position,
+                    ranges: vec![],
+                };
+                selector.select_tokens(analysis.tree.tokens());
+
+                // Build the hierarchy from the outermost range inwards
+                let mut ranges = selector.ranges;
+                ranges.dedup();
+                let innermost = ranges
+                    .pop()
+                    .unwrap_or_else(|| Range::new(*position, *position));
+                let parent = ranges.into_iter().fold(None, |parent, range| {
+                    Some(Box::new(SelectionRange { range, parent }))
+                });
+                SelectionRange {
+                    range: innermost,
+                    parent,
+                }
+            })
+            .collect();
+
+        Ok(Some(ranges))
+    }
+}
+
+/// Collects the ranges that contain a position, from the outermost range inwards
+struct Selector<'a> {
+    analysis: &'a Analysis,
+    uri: &'a Url,
+    position: Position,
+    ranges: Vec<Range>,
+}
+
+impl<'a> Selector<'a> {
+    fn select_tokens(&mut self, tokens: &[Token]) {
+        if let Some(token) = tokens.iter().find(|t| self.contains(t.span())) {
+            self.select_token(token);
+        }
+    }
+
+    fn select_token(&mut self, token: &Token) {
+        self.select(token.span());
+
+        match token {
+            Token::Align { value, .. }
+            | Token::ProgramCounterDefinition { value, .. }
+            | Token::Reserve { count: value, .. } => self.select_expression(value),
+            Token::Fill { count, value, .. } => {
+                self.select_expression(count);
+                self.select_expression(value);
+            }
+            Token::Braces { block, .. } | Token::Config(block) => self.select_block(block),
+            Token::ConfigPair { key, value, .. } => {
+                self.select(key.span);
+                self.select_token(&value.data);
+            }
+            Token::Charmap { id, value, .. } => {
+                self.select(id.span);
+                self.select_expression(value);
+            }
+            Token::Data { values, .. } | Token::Text { values, .. } => {
+                for (value, _) in values {
+                    self.select_expression(value);
+                }
+            }
+            Token::Definition { id, value, .. } => {
+                self.select(id.span);
+                if let Some(value) = value {
+                    self.select_token(value);
+                }
+            }
+            Token::Expression(expr) => self.select_expression_data(expr),
+            Token::If {
+                value, if_, else_, ..
+            } => {
+                self.select_expression(value);
+                self.select_block(if_);
+                if let Some(else_) = else_ {
+                    self.select_block(else_);
+                }
+            }
+            Token::Include { filename, .. } => {
+                self.select(filename.span);
+            }
+            Token::Instruction(i) => {
+                self.select(i.mnemonic.span);
+                if let Some(operand) = &i.operand {
+                    self.select_expression(&operand.expr);
+                }
+            }
+            Token::Label { id, block, .. } | Token::Segment { id, block, .. } => {
+                self.select(id.span);
+                if let Some(block) = block {
+                    self.select_block(block);
+                }
+            }
+            Token::VariableDefinition { id, value, .. } => {
+                self.select(id.span);
+                self.select_expression(value);
+            }
+            _ => (),
+        }
+    }
+
+    fn select_block(&mut self, block: &Block) {
+        if self.select(block.span()) {
+            self.select_tokens(&block.inner);
+        }
+    }
+
+    fn select_expression(&mut self, expr: &Located<Expression>) {
+        if self.select(expr.span) {
+            self.select_expression_data(&expr.data);
+        }
+    }
+
+    fn select_expression_data(&mut self, expr: &Expression) {
+        match expr {
+            Expression::BinaryExpression(expr) => {
+                self.select_expression(&expr.lhs);
+                self.select_expression(&expr.rhs);
+            }
+            Expression::Factor { factor, .. } => self.select_factor(factor),
+        }
+    }
+
+    fn select_factor(&mut self, factor: &Located<ExpressionFactor>) {
+        if !self.select(factor.span) {
+            return;
+        }
+
+        match &factor.data {
+            ExpressionFactor::ExprParens { inner, .. } => self.select_expression(inner),
+            ExpressionFactor::FunctionCall { name, args, .. } => {
+                self.select(name.span);
+                for (arg, _) in args {
+                    self.select_expression(arg);
+                }
+            }
+            ExpressionFactor::IdentifierValue { path, .. } => {
+                self.select(path.span);
+            }
+            _ => (),
+        }
+    }
+
+    /// Adds the span to the selection if it contains the position we're looking for
+    fn select(&mut self, span: Span) -> bool {
+        if self.contains(span) {
+            let span = self.trim_start(span);
+            self.ranges.push(self.analysis.look_up_span(span).into());
+            true
+        } else {
+            false
+        }
+    }
+
+    fn contains(&self, span: Span) -> bool {
+        let loc = self.analysis.look_up_span(self.trim_start(span));
+        let range = Range::from(loc.clone());
+        &loc.uri() == self.uri && range.start <= self.position && self.position <= range.end
+    }
+
+    /// Some spans include leading whitespace, which we don't want to select
+    fn trim_start(&self, span: Span) -> Span {
+        let file = self.analysis.look_up_span(span).file();
+        let source = file.source_slice(span);
+        let whitespace = source.len() - source.trim_start().len();
+        span.subspan(whitespace as u64, span.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::MosResult;
+    use crate::lsp::LspServer;
+    use lsp_types::{Position, Range, SelectionRange};
+
+    #[test]
+    fn selection_ranges() -> MosResult<()> {
+        let mut server = LspServer::new();
+        server.did_open_text_document("test.asm", "foo: {\n  lda #1 + bar\n}\nbar: nop")?;
+        server.selection_range("test.asm", Position::new(1, 12))?;
+
+        let response = server.context.responses().pop().unwrap().result.unwrap();
+        let selections: Vec<SelectionRange> = serde_json::from_value(response).unwrap();
+        let mut ranges = vec![];
+        let mut selection = selections.first().cloned().map(Box::new);
+        while let Some(s) = selection {
+            ranges.push(s.range);
+            selection = s.parent;
+        }
+
+        let range = |sl, sc, el, ec| Range::new(Position::new(sl, sc), Position::new(el, ec));
+        assert_eq!(
+            ranges,
+            vec![
+                range(1, 11, 1, 14),
+                range(1, 7, 1, 14),
+                range(1, 2, 1, 14),
+                range(0, 5, 2, 1),
+                range(0, 0, 2, 1),
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -19,6 +19,7 @@ enum TokenType {
     Identifier,
     Mnemonic,
     Number,
+    String,
     Variable,
     Constant,
     Keyword,
@@ -33,6 +34,7 @@ impl TokenType {
             TokenType::Identifier => SemanticTokenType::METHOD,
             TokenType::Mnemonic => SemanticTokenType::PROPERTY,
             TokenType::Number => SemanticTokenType::NUMBER,
+            TokenType::String => SemanticTokenType::STRING,
             TokenType::Variable => SemanticTokenType::VARIABLE,
             TokenType::Constant => SemanticTokenType::VARIABLE,
             TokenType::Keyword => SemanticTokenType::KEYWORD,
@@ -235,7 +237,19 @@ fn emit_semantic(token: &Token) -> SemTokBuilder {
 
     match &token {
         Token::Align { tag, value } => b.keyword(tag).expression(&value.data),
+        Token::AnonymousLabel(marker) => b.identifier(marker),
         Token::Braces { block, .. } | Token::Config(block) => b.block(block),
+        Token::Charmap {
+            tag,
+            id,
+            chars,
+            value,
+            ..
+        } => b
+            .keyword(tag)
+            .identifier(id)
+            .push(chars, TokenType::String)
+            .expression(&value.data),
         Token::ConfigPair { key, value, .. } => b.push(key, TokenType::Keyword).token(&value.data),
         Token::Data { values, size } => b.push(size, TokenType::Keyword).args(values),
         Token::Definition { tag, id, value } => {
@@ -245,6 +259,14 @@ fn emit_semantic(token: &Token) -> SemTokBuilder {
                 None => b,
             }
         }
+        Token::Encoding { tag, encoding } => b.keyword(tag).identifier(encoding),
+        Token::Fill {
+            tag, count, value, ..
+        } => b
+            .keyword(tag)
+            .expression(&count.data)
+            .expression(&value.data),
+        Token::Reserve { tag, count } => b.keyword(tag).expression(&count.data),
         Token::Eof(_) => b,
         Token::Error(_) => b,
         Token::Expression(expr) => b.expression(&expr),
@@ -279,6 +301,18 @@ fn emit_semantic(token: &Token) -> SemTokBuilder {
                 None => b,
             }
         }
+        Token::Text {
+            tag,
+            encoding,
+            values,
+        } => {
+            let b = b.keyword(tag);
+            match encoding {
+                Some(encoding) => b.identifier(encoding),
+                None => b,
+            }
+            .args(values)
+        }
         Token::VariableDefinition { ty, id, value, .. } => {
             let token_type = match &ty.data {
                 VariableType::Constant => TokenType::Constant,
@@ -314,6 +348,8 @@ fn emit_expression_semantic(expression: &Expression) -> SemTokBuilder {
             ExpressionFactor::FunctionCall { name, args, .. } => {
                 SemTokBuilder::new().identifier(name).args(args)
             }
+            ExpressionFactor::StringLiteral(s) => SemTokBuilder::new().push(s, TokenType::String),
+            ExpressionFactor::CharLiteral(c) => SemTokBuilder::new().push(c, TokenType::String),
         },
     }
 }
@@ -0,0 +1,223 @@
+use crate::core::codegen::builtin_functions;
+use crate::core::parser::{
+    ArgItem, Block, Expression, ExpressionFactor, Identifier, Located, Token,
+};
+use crate::errors::MosResult;
+use crate::impl_request_handler;
+use crate::lsp::analysis::Analysis;
+use crate::lsp::{LspContext, RequestHandler};
+use lsp_types::request::SignatureHelpRequest;
+use lsp_types::{
+    Documentation, ParameterInformation, ParameterLabel, Position, Range, SignatureHelp,
+    SignatureHelpParams, SignatureInformation, Url,
+};
+
+pub struct SignatureHelpHandler {}
+
+impl_request_handler!(SignatureHelpHandler);
+
+impl RequestHandler<SignatureHelpRequest> for SignatureHelpHandler {
+    fn handle(
+        &self,
+        ctx: &mut LspContext,
+        params: SignatureHelpParams,
+    ) -> MosResult<Option<SignatureHelp>> {
+        let analysis = match &ctx.analysis {
+            Some(analysis) => analysis,
+            None => return Ok(None),
+        };
+        let pos = &params.text_document_position_params;
+        let finder = CallFinder {
+            analysis,
+            uri: &pos.text_document.uri,
+            position: pos.position,
+        };
+        let (name, active_parameter) = match finder.find_in_tokens(analysis.tree.tokens()) {
+            Some(call) => call,
+            None => return Ok(None),
+        };
+
+        let function = match builtin_functions()
+            .into_iter()
+            .find(|f| f.name == name.value())
+        {
+            Some(function) => function,
+            None => return Ok(None),
+        };
+
+        let parameters = function
+            .parameters
+            .iter()
+            .map(|(name, _)| ParameterInformation {
+                label: ParameterLabel::Simple(name.to_string()),
+                documentation: None,
+            })
+            .collect();
+        let signature = SignatureInformation {
+            label: function.signature(),
+            documentation: Some(Documentation::String(function.documentation.to_string())),
+            parameters: Some(parameters),
+            active_parameter: Some(active_parameter),
+        };
+
+        Ok(Some(SignatureHelp {
+            signatures: vec![signature],
+            active_signature: Some(0),
+            active_parameter: Some(active_parameter),
+        }))
+    }
+}
+
+/// Finds the innermost function call whose argument list contains a position
+struct CallFinder<'a> {
+    analysis: &'a Analysis,
+    uri: &'a Url,
+    position: Position,
+}
+
+impl<'a> CallFinder<'a> {
+    fn find_in_tokens<'t>(&self, tokens: &'t [Token]) -> Option<(&'t Identifier, u32)> {
+        tokens.iter().find_map(|token| self.find_in_token(token))
+    }
+
+    fn find_in_block<'t>(&self, block: &'t Option<Block>) -> Option<(&'t Identifier, u32)> {
+        block.as_ref().and_then(|b| self.find_in_tokens(&b.inner))
+    }
+
+    fn find_in_token<'t>(&self, token: &'t Token) -> Option<(&'t Identifier, u32)> {
+        match token {
+            Token::Align { value, .. }
+            | Token::Charmap { value, .. }
+            | Token::ProgramCounterDefinition { value, .. }
+            | Token::Reserve { count: value, .. }
+            | Token::VariableDefinition { value, .. } => self.find_in_expression(value),
+            Token::Fill { count, value, .. } => self
+                .find_in_expression(count)
+                .or_else(|| self.find_in_expression(value)),
+            Token::Braces { block, .. } => self.find_in_tokens(&block.inner),
+            Token::Data { values, .. } | Token::Text { values, .. } => self.find_in_args(values),
+            Token::If {
+                value, if_, else_, ..
+            } => self
+                .find_in_expression(value)
+                .or_else(|| self.find_in_tokens(&if_.inner))
+                .or_else(|| self.find_in_block(else_)),
+            Token::Instruction(i) => i
+                .operand
+                .as_ref()
+                .and_then(|o| self.find_in_expression(&o.expr)),
+            Token::Label { block, .. } | Token::Segment { block, .. } => self.find_in_block(block),
+            _ => None,
+        }
+    }
+
+    fn find_in_args<'t>(&self, args: &'t [ArgItem]) -> Option<(&'t Identifier, u32)> {
+        args.iter()
+            .find_map(|(expr, _comma)| self.find_in_expression(expr))
+    }
+
+    fn find_in_expression<'t>(
+        &self,
+        expr: &'t Located<Expression>,
+    ) -> Option<(&'t Identifier, u32)> {
+        match &expr.data {
+            Expression::Factor { factor, .. } => self.find_in_factor(factor),
+            Expression::BinaryExpression(expr) => self
+                .find_in_expression(&expr.lhs)
+                .or_else(|| self.find_in_expression(&expr.rhs)),
+        }
+    }
+
+    fn find_in_factor<'t>(
+        &self,
+        factor: &'t Located<ExpressionFactor>,
+    ) -> Option<(&'t Identifier, u32)> {
+        match &factor.data {
+            ExpressionFactor::ExprParens { inner, .. } => self.find_in_expression(inner),
+            ExpressionFactor::FunctionCall {
+                name,
+                lparen,
+                args,
+                rparen,
+            } => self.find_in_args(args).or_else(|| {
+                let lparen = self.range(lparen)?;
+                let rparen = self.range(rparen)?;
+                if self.position < lparen.end || self.position > rparen.start {
+                    return None;
+                }
+
+                let active_parameter = args
+                    .iter()
+                    .filter_map(|(_, comma)| comma.as_ref().and_then(|c| self.range(c)))
+                    .filter(|comma| comma.end <= self.position)
+                    .count();
+                Some((&name.data, active_parameter as u32))
+            }),
+            _ => None,
+        }
+    }
+
+    /// Determines the range of a located item, but only if it is in the file we're looking at
+    fn range<T>(&self, located: &Located<T>) -> Option<Range> {
+        let loc = self.analysis.look_up_span(located.span);
+        if &loc.uri() == self.uri {
+            Some(loc.into())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::MosResult;
+    use crate::lsp::testing::response;
+    use crate::lsp::LspServer;
+    use lsp_types::request::SignatureHelpRequest;
+    use lsp_types::{
+        Documentation, ParameterInformation, ParameterLabel, Position, SignatureHelp,
+        SignatureInformation,
+    };
+
+    #[test]
+    fn signature_help() -> MosResult<()> {
+        let mut server = LspServer::new();
+        server.did_open_text_document("test.asm", ".if defined(foo) { nop }")?;
+        server.signature_help("test.asm", Position::new(0, 13))?;
+
+        assert_eq!(
+            server.context.responses().pop().unwrap().result,
+            response::<SignatureHelpRequest>(Some(SignatureHelp {
+                signatures: vec![SignatureInformation {
+                    label: "defined(symbol)".to_string(),
+                    documentation: Some(Documentation::String(
+                        "Evaluates to `1` if the symbol is defined and to `0` otherwise."
+                            .to_string()
+                    )),
+                    parameters: Some(vec![ParameterInformation {
+                        label: ParameterLabel::Simple("symbol".to_string()),
+                        documentation: None
+                    }]),
+                    active_parameter: Some(0)
+                }],
+                active_signature: Some(0),
+                active_parameter: Some(0)
+            }))
+            .result
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn no_signature_help_outside_of_arguments() -> MosResult<()> {
+        let mut server = LspServer::new();
+        server.did_open_text_document("test.asm", ".if defined(foo) { nop }")?;
+        server.signature_help("test.asm", Position::new(0, 20))?;
+        assert_eq!(
+            server.context.responses().pop().unwrap().result,
+            response::<SignatureHelpRequest>(None).result
+        );
+        Ok(())
+    }
+}
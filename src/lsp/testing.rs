@@ -1,11 +1,16 @@
 use crate::errors::MosResult;
 use crate::lsp::analysis::to_file_uri;
+use crate::lsp::inlay_hints::{InlayHintParams, InlayHintRequest};
 use crate::lsp::LspServer;
 use lsp_types::notification::{DidOpenTextDocument, Notification};
-use lsp_types::request::{Rename, Request};
+use lsp_types::request::{
+    CodeActionRequest, FoldingRangeRequest, GotoDefinition, PrepareRenameRequest, Rename, Request,
+    SelectionRangeRequest, SignatureHelpRequest,
+};
 use lsp_types::{
-    DidOpenTextDocumentParams, Position, RenameParams, TextDocumentIdentifier, TextDocumentItem,
-    TextDocumentPositionParams,
+    CodeActionContext, CodeActionParams, DidOpenTextDocumentParams, FoldingRangeParams,
+    GotoDefinitionParams, Position, Range, RenameParams, SelectionRangeParams, SignatureHelpParams,
+    TextDocumentIdentifier, TextDocumentItem, TextDocumentPositionParams,
 };
 
 impl LspServer {
@@ -34,6 +39,89 @@ impl LspServer {
             work_done_progress_params: Default::default(),
         }))
     }
+
+    pub fn prepare_rename(&mut self, file: &str, position: Position) -> MosResult<()> {
+        self.handle_message(request::<PrepareRenameRequest>(
+            TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: to_file_uri(file),
+                },
+                position,
+            },
+        ))
+    }
+
+    pub fn inlay_hints(&mut self, file: &str) -> MosResult<()> {
+        self.handle_message(request::<InlayHintRequest>(InlayHintParams {
+            work_done_progress_params: Default::default(),
+            text_document: TextDocumentIdentifier {
+                uri: to_file_uri(file),
+            },
+            range: Range::new(Position::new(0, 0), Position::new(u32::MAX, 0)),
+        }))
+    }
+
+    pub fn signature_help(&mut self, file: &str, position: Position) -> MosResult<()> {
+        self.handle_message(request::<SignatureHelpRequest>(SignatureHelpParams {
+            context: None,
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: to_file_uri(file),
+                },
+                position,
+            },
+            work_done_progress_params: Default::default(),
+        }))
+    }
+
+    pub fn folding_ranges(&mut self, file: &str) -> MosResult<()> {
+        self.handle_message(request::<FoldingRangeRequest>(FoldingRangeParams {
+            text_document: TextDocumentIdentifier {
+                uri: to_file_uri(file),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        }))
+    }
+
+    pub fn selection_range(&mut self, file: &str, position: Position) -> MosResult<()> {
+        self.handle_message(request::<SelectionRangeRequest>(SelectionRangeParams {
+            text_document: TextDocumentIdentifier {
+                uri: to_file_uri(file),
+            },
+            positions: vec![position],
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        }))
+    }
+
+    pub fn go_to_definition(&mut self, file: &str, position: Position) -> MosResult<()> {
+        self.handle_message(request::<GotoDefinition>(GotoDefinitionParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: to_file_uri(file),
+                },
+                position,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        }))
+    }
+
+    pub fn code_action(&mut self, file: &str, range: Range) -> MosResult<()> {
+        self.handle_message(request::<CodeActionRequest>(CodeActionParams {
+            text_document: TextDocumentIdentifier {
+                uri: to_file_uri(file),
+            },
+            range,
+            context: CodeActionContext {
+                diagnostics: vec![],
+                only: None,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        }))
+    }
 }
 
 fn request<T: Request>(params: T::Params) -> lsp_server::Message {
@@ -38,9 +38,13 @@ macro_rules! impl_request_handler {
             fn handle(&self, ctx: &mut LspContext, req: lsp_server::Request) -> MosResult<()> {
                 let method = RequestHandler::method(self);
                 let (id, params) = req.extract(method).unwrap();
-                let result = RequestHandler::handle(self, ctx, params)?;
-                ctx.send_response(id, result)?;
-                Ok(())
+                match RequestHandler::handle(self, ctx, params) {
+                    Ok(result) => ctx.send_response(id, result),
+                    Err(crate::errors::MosError::InvalidRequest(message)) => {
+                        ctx.send_error(id, message)
+                    }
+                    Err(e) => Err(e),
+                }
             }
         }
     };
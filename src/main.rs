@@ -88,7 +88,7 @@ fn mos_toml_path<P: Into<PathBuf>>(
 
 fn run(args: ArgMatches) -> MosResult<()> {
     let mos_toml = mos_toml_path(None, &Path::new("."))?;
-    let (root, cfg) = match mos_toml {
+    let (root, mut cfg) = match mos_toml {
         Some(path) => {
             log::trace!("Using configuration from: {}", &path.to_str().unwrap());
             let toml = fs::read_to_string(&path)?;
@@ -104,7 +104,12 @@ fn run(args: ArgMatches) -> MosResult<()> {
     };
 
     match args.subcommand() {
-        Some(("build", _)) => build_command(&root, &cfg),
+        Some(("build", args)) => {
+            if args.is_present("memory-map") {
+                cfg.build.memory_map = true;
+            }
+            build_command(&root, &cfg)
+        }
         Some(("format", _)) => format_command(&cfg),
         Some(("init", _)) => init_command(&root, &cfg),
         Some(("lsp", args)) => lsp_command(args),
@@ -154,6 +159,13 @@ mod tests {
         assert_eq!(args.subcommand_name(), Some("build"));
     }
 
+    #[test]
+    fn can_invoke_build_with_memory_map() {
+        let args = get_app().get_matches_from(vec!["mos", "build", "--memory-map"]);
+        let (_, args) = args.subcommand().unwrap();
+        assert!(args.is_present("memory-map"));
+    }
+
     #[test]
     fn can_invoke_format() {
         let args = get_app().get_matches_from(vec!["mos", "format"]);
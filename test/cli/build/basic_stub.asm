@@ -0,0 +1,2 @@
+inc $d020
+rts
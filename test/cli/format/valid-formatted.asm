@@ -42,7 +42,15 @@ data: {
     .byte 1 // hello
     .word 4
 
+    .fill 2, $ff
+
+    .res 2
+
+    .text petscii_upper "hi\""
+
     nop
 }
 
+- dex
+bne -
 nop
\ No newline at end of file
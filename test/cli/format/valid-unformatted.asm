@@ -23,6 +23,11 @@ start=$2000 + 4 -%00100}
             data: {          /* here it is */
  .byte          1// hello
  .word  4
+  .fill   2  ,   $ff
+   .res  2
+  .text   petscii_upper    "hi\""
  nop}
 
+  -    dex
+   bne    -
  nop
\ No newline at end of file